@@ -0,0 +1,105 @@
+use std::io;
+use strip_shared::audio::{Analyzer, Sound};
+
+/// Sample rate files are decimated to, so analyzer bands cover the musical range.
+const ANALYZER_RATE: u32 = 11025;
+
+/// Mono samples of a PCM WAV file, channels are mixed down.
+pub struct Wav {
+  pub rate: u32,
+  pub samples: Vec<i16>,
+}
+
+impl Wav {
+  pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+      return Err(invalid("not a WAV file"));
+    }
+    let mut format = None;
+    let mut chunks = &bytes[12..];
+    while chunks.len() >= 8 {
+      let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+      let body = chunks
+        .get(8..(8 + len))
+        .ok_or_else(|| invalid("truncated WAV chunk"))?;
+      match &chunks[..4] {
+        b"fmt " if len >= 16 => {
+          let half = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+          let rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+          // Extensible format keeps the PCM tag in its subformat.
+          if !matches!(half(0), 1 | 0xfffe) || half(2) == 0 || rate == 0 {
+            return Err(invalid("WAV file is not PCM"));
+          }
+          format = Some((half(2) as usize, rate, half(14)));
+        }
+        b"data" => {
+          let (channels, rate, bits) = format.ok_or_else(|| invalid("WAV data before format"))?;
+          let samples: Vec<i16> = match bits {
+            8 => body.iter().map(|val| (*val as i16 - 128) << 8).collect(),
+            16 => body
+              .chunks_exact(2)
+              .map(|val| i16::from_le_bytes([val[0], val[1]]))
+              .collect(),
+            _ => return Err(invalid("WAV file is not 8 or 16-bit")),
+          };
+          let samples = samples
+            .chunks_exact(channels)
+            .map(|frame| {
+              (frame.iter().map(|val| *val as i32).sum::<i32>() / channels as i32) as i16
+            })
+            .collect();
+          return Ok(Wav { rate, samples });
+        }
+        _ => {}
+      }
+      // Chunks are padded to an even length.
+      chunks = chunks.get((8 + len + len % 2)..).unwrap_or(&[]);
+    }
+    Err(invalid("WAV file without data"))
+  }
+
+  /// Averages every `factor` samples into one.
+  fn decimate(self, factor: usize) -> Self {
+    let samples = self
+      .samples
+      .chunks(factor)
+      .map(|chunk| (chunk.iter().map(|val| *val as i32).sum::<i32>() / chunk.len() as i32) as i16)
+      .collect();
+    Wav {
+      rate: self.rate / factor as u32,
+      samples,
+    }
+  }
+}
+
+fn invalid(msg: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Sound of a WAV file played from program start, analyzed in step with frame time.
+pub struct AudioFile {
+  wav: Wav,
+  analyzer: Analyzer,
+  pos: usize,
+}
+
+impl AudioFile {
+  pub fn open(path: &str) -> io::Result<Self> {
+    let wav = Wav::parse(&std::fs::read(path)?)?;
+    let factor = (wav.rate / ANALYZER_RATE).max(1) as usize;
+    Ok(AudioFile {
+      wav: wav.decimate(factor),
+      analyzer: Analyzer::new(),
+      pos: 0,
+    })
+  }
+
+  /// Feeds samples up to `elapsed_ms` into the analyzer, silence follows the end of the file.
+  pub fn sound(&mut self, elapsed_ms: u32) -> Sound {
+    let end = (elapsed_ms as u64 * self.wav.rate as u64 / 1000) as usize;
+    let end = end.min(self.wav.samples.len()).max(self.pos);
+    self.analyzer.feed(&self.wav.samples[self.pos..end]);
+    self.pos = end;
+    self.analyzer.analyze()
+  }
+}
@@ -1,3 +1,5 @@
+use strip_shared::ecall::{lookup, EcallDef, Param};
+use strip_shared::led::*;
 use strip_shared::vm::*;
 
 pub struct Trace<'input> {
@@ -11,17 +13,12 @@ impl<'input> Trace<'input> {
   pub fn new(
     spins: u16,
     max_ops: Option<u32>,
-    ram_size: u16,
-    trace_memory: bool,
-    trace_ecalls: bool,
+    env: Environment,
     bytecode: &'input [u8],
   ) -> Result<Self, VMError> {
-    let mut vm = VM::new(Environment {
-      trace_memory,
-      trace_ecalls,
-      ram: vec![0; ram_size as usize],
-    });
+    let mut vm = VM::new(env);
     vm.load(bytecode)?;
+    vm.get_env().next_frame();
     Ok(Trace {
       vm,
       spins,
@@ -38,6 +35,7 @@ impl<'input> Trace<'input> {
           println!("{:=<80}", "VM HALTED   ");
           self.spins -= 1;
           self.vm.rewind();
+          self.vm.get_env().next_frame();
         }
         Ok(_) => {
           self.ops += 1;
@@ -57,58 +55,77 @@ impl<'input> Trace<'input> {
 pub struct Environment {
   trace_ecalls: bool,
   trace_memory: bool,
-  ram: Vec<u8>,
+  strip: LedEnv<Vec<u8>>,
+}
+
+impl Environment {
+  pub fn new(strip: LedEnv<Vec<u8>>, trace_memory: bool, trace_ecalls: bool) -> Self {
+    Environment {
+      trace_ecalls,
+      trace_memory,
+      strip,
+    }
+  }
+
+  /// Advances the timer until the next frame is due, as between spins on the device.
+  fn next_frame(&mut self) {
+    while !self.strip.tick() {}
+  }
 }
 
 impl Env for Environment {
-  type Error = ();
+  type Error = LedError;
 
   fn reset(&mut self) {
-    self.ram = vec![0; self.ram.len()];
+    self.strip.reset();
   }
 
   fn mem_fetch(&self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
-    let offset = addr as usize;
     if self.trace_memory {
-      println!("            MEM FETCH          0x{:x}", offset);
+      println!("            MEM FETCH          0x{:x}", addr);
     }
-    if offset >= 0x1000 {
-      return Ok(());
-    }
-    let end = offset + buf.len();
-    if end > self.ram.len() {
-      return Err(());
-    }
-    buf.copy_from_slice(&self.ram[offset..end]);
-    Ok(())
+    self.strip.mem_fetch(addr, buf)
   }
 
   fn mem_set(&mut self, addr: u16, val: &[u8]) -> Result<(), Self::Error> {
-    let offset = addr as usize;
     if self.trace_memory {
-      println!("            MEM SET            0x{:x} {:?}", offset, val);
-    }
-    if offset >= 0x1000 {
-      return Ok(());
-    }
-    let end = offset + val.len();
-    if end > self.ram.len() {
-      return Err(());
+      println!("            MEM SET            0x{:x} {:?}", addr, val);
     }
-    self.ram[offset..end].copy_from_slice(val);
-    Ok(())
+    self.strip.mem_set(addr, val)
   }
 
   fn ecall(&mut self, ecall: u8, param: i32) -> Result<i32, Self::Error> {
+    self.ecall_args(ecall, param, &mut [0; 8])
+  }
+
+  fn ecall_args(&mut self, ecall: u8, param: i32, args: &mut Args) -> Result<i32, Self::Error> {
     if self.trace_ecalls {
-      println!("            ECALL              0x{:x}(0x{:x?})", ecall, param);
+      match lookup(ECALLS, ecall) {
+        Some(EcallDef {
+          name,
+          param: Param::Args(n),
+          ..
+        }) => println!(
+          "            ECALL              {}({:x?})",
+          name,
+          &args[..*n as usize]
+        ),
+        Some(def) => println!(
+          "            ECALL              {}(0x{:x?})",
+          def.name, param
+        ),
+        None => println!(
+          "            ECALL              0x{:x}(0x{:x?})",
+          ecall, param
+        ),
+      }
     }
-    Ok(0)
+    self.strip.ecall_args(ecall, param, args)
   }
 }
 
 impl core::fmt::Debug for Environment {
   fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-    write!(f, "{:?}", self.ram)
+    write!(f, "{:?}", self.strip.ram())
   }
 }
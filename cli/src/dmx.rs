@@ -0,0 +1,194 @@
+use crate::run::Output;
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+use strip_shared::led::LedEnv;
+
+const E131_PORT: u16 = 5568;
+const ARTNET_PORT: u16 = 6454;
+const SOURCE_NAME: &[u8] = b"StripVM";
+const CID: [u8; 16] = [
+  0x5f, 0x1e, 0x8a, 0x3c, 0x42, 0x7d, 0x4b, 0x09, 0x9e, 0x61, 0x2a, 0xd4, 0x70, 0x13, 0xc5, 0xb8,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+  E131,
+  ArtNet,
+}
+
+impl Protocol {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "e131" | "sacn" => Some(Protocol::E131),
+      "artnet" => Some(Protocol::ArtNet),
+      _ => None,
+    }
+  }
+
+  pub fn port(self) -> u16 {
+    match self {
+      Protocol::E131 => E131_PORT,
+      Protocol::ArtNet => ARTNET_PORT,
+    }
+  }
+
+  pub fn first_universe(self) -> u16 {
+    *self.universes().start()
+  }
+
+  /// Universes the protocol can address, Art-Net port addresses are 15 bits.
+  pub fn universes(self) -> RangeInclusive<u16> {
+    match self {
+      Protocol::E131 => 1..=63999,
+      Protocol::ArtNet => 0..=0x7fff,
+    }
+  }
+
+  fn universe(self, universe: u32) -> io::Result<u16> {
+    let range = self.universes();
+    u16::try_from(universe)
+      .ok()
+      .filter(|universe| range.contains(universe))
+      .ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!(
+            "DMX universe {} is out of range {}..={}",
+            universe,
+            range.start(),
+            range.end()
+          ),
+        )
+      })
+  }
+}
+
+pub struct Dmx {
+  socket: UdpSocket,
+  dest: Option<SocketAddr>,
+  protocol: Protocol,
+  universe: u16,
+  channels: usize,
+  sequence: u8,
+}
+
+impl Dmx {
+  /// Without destination E1.31 frames go to universe multicast groups and Art-Net frames are broadcasted.
+  pub fn bind(
+    protocol: Protocol,
+    dest: Option<&str>,
+    universe: u32,
+    channels: usize,
+  ) -> io::Result<Self> {
+    let universe = protocol.universe(universe)?;
+    let dest = match dest {
+      None => None,
+      Some(addr) => Some(match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => {
+          let ip = addr
+            .parse::<IpAddr>()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+          SocketAddr::new(ip, protocol.port())
+        }
+      }),
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    // Universes hold whole pixels only, 512 channels fit 170 RGB pixels.
+    let channels = (channels.clamp(3, 512) / 3) * 3;
+    Ok(Dmx {
+      socket,
+      dest,
+      protocol,
+      universe,
+      channels,
+      sequence: 0,
+    })
+  }
+
+  fn destination(&self, universe: u16) -> SocketAddr {
+    if let Some(dest) = self.dest {
+      return dest;
+    }
+    let ip = match self.protocol {
+      Protocol::E131 => Ipv4Addr::new(239, 255, (universe >> 8) as u8, universe as u8),
+      Protocol::ArtNet => Ipv4Addr::BROADCAST,
+    };
+    SocketAddr::new(IpAddr::V4(ip), self.protocol.port())
+  }
+}
+
+impl Output for Dmx {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, _ticks: u32) -> io::Result<()> {
+    self.sequence = match self.protocol {
+      // Art-Net reserves zero sequence for "sequencing disabled".
+      Protocol::ArtNet if self.sequence == 255 => 1,
+      _ => self.sequence.wrapping_add(1),
+    };
+    // Every strip channel starts on a new universe.
+    let mut next = self.universe as u32;
+    for ch in 0..strip.channels() {
+      let data: Vec<u8> = strip
+        .channel_output_pixels(ch)
+        .flat_map(|(r, g, b)| [r, g, b])
+        .collect();
+      for chunk in data.chunks(self.channels) {
+        let universe = self.protocol.universe(next)?;
+        next += 1;
+        let packet = match self.protocol {
+          Protocol::E131 => e131_packet(universe, self.sequence, chunk),
+          Protocol::ArtNet => artnet_packet(universe, self.sequence, chunk),
+        };
+        self.socket.send_to(&packet, self.destination(universe))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+fn e131_packet(universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+  let len = 126 + data.len();
+  let mut packet = Vec::with_capacity(len);
+  // Root layer
+  packet.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+  packet.extend_from_slice(b"ASC-E1.17\0\0\0");
+  packet.extend_from_slice(&(0x7000 | (len - 16) as u16).to_be_bytes());
+  packet.extend_from_slice(&4u32.to_be_bytes());
+  packet.extend_from_slice(&CID);
+  // Framing layer
+  packet.extend_from_slice(&(0x7000 | (len - 38) as u16).to_be_bytes());
+  packet.extend_from_slice(&2u32.to_be_bytes());
+  let mut name = [0; 64];
+  name[..SOURCE_NAME.len()].copy_from_slice(SOURCE_NAME);
+  packet.extend_from_slice(&name);
+  packet.push(100);
+  packet.extend_from_slice(&[0, 0]);
+  packet.push(sequence);
+  packet.push(0);
+  packet.extend_from_slice(&universe.to_be_bytes());
+  // DMP layer
+  packet.extend_from_slice(&(0x7000 | (len - 115) as u16).to_be_bytes());
+  packet.extend_from_slice(&[0x02, 0xa1, 0x00, 0x00, 0x00, 0x01]);
+  packet.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+  packet.push(0);
+  packet.extend_from_slice(data);
+  packet
+}
+
+fn artnet_packet(universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+  let len = data.len() + data.len() % 2;
+  let mut packet = Vec::with_capacity(18 + len);
+  packet.extend_from_slice(b"Art-Net\0");
+  packet.extend_from_slice(&0x5000u16.to_le_bytes());
+  packet.extend_from_slice(&14u16.to_be_bytes());
+  packet.push(sequence);
+  packet.push(0);
+  packet.extend_from_slice(&(universe & 0x7fff).to_le_bytes());
+  packet.extend_from_slice(&(len as u16).to_be_bytes());
+  packet.extend_from_slice(data);
+  packet.resize(18 + len, 0);
+  packet
+}
@@ -0,0 +1,279 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+#[cfg(unix)]
+use std::sync::OnceLock;
+use std::thread;
+use strip_shared::input::*;
+
+pub const KEYS_HELP: &str = "Reads controls from keyboard: 1-9 tap buttons, q/a w/s e/d r/f move \
+                             analog inputs up/down, z/x c/v turn encoders";
+/// Buttons, analog inputs down and up, encoders back and forth.
+const BUTTON_KEYS: &[u8] = b"123456789";
+const ANALOG_KEYS: &[(u8, u8)] = &[(b'a', b'q'), (b's', b'w'), (b'd', b'e'), (b'f', b'r')];
+const ENCODER_KEYS: &[(u8, u8)] = &[(b'z', b'x'), (b'c', b'v')];
+const ANALOG_STEP: i32 = 16;
+
+/// Controls driven by events of a script, one `frame control index [value]` per line.
+///
+/// Controls are `press`, `release`, `analog` with a level and `encoder` with steps, frames count
+/// from zero and `#` starts a comment.
+pub struct Script {
+  events: Vec<(u64, Event)>,
+  frame: u64,
+  state: State,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+  Press(usize),
+  Release(usize),
+  Analog(usize, u8),
+  Encoder(usize, i32),
+}
+
+#[derive(Debug, Default)]
+struct State {
+  buttons: u16,
+  analog: [u8; MAX_ANALOG],
+  encoders: [i32; MAX_ENCODERS],
+}
+
+impl State {
+  fn apply(&mut self, event: Event) {
+    match event {
+      Event::Press(idx) => self.buttons |= 1 << idx,
+      Event::Release(idx) => self.buttons &= !(1 << idx),
+      Event::Analog(idx, level) => self.analog[idx] = level,
+      Event::Encoder(idx, steps) => self.encoders[idx] += steps,
+    }
+  }
+
+  fn take_encoder(&mut self, idx: usize) -> i32 {
+    std::mem::take(&mut self.encoders[idx])
+  }
+}
+
+impl Script {
+  pub fn parse(script: &str) -> io::Result<Self> {
+    let mut events = vec![];
+    for (line_idx, line) in script.lines().enumerate() {
+      let line = line.split('#').next().unwrap().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let event = parse_event(line).ok_or_else(|| {
+        let msg = format!("invalid input event on line {}: {}", line_idx + 1, line);
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+      })?;
+      events.push(event);
+    }
+    events.sort_by_key(|(frame, _)| *frame);
+    Ok(Script {
+      events,
+      frame: 0,
+      state: State::default(),
+    })
+  }
+}
+
+fn parse_event(line: &str) -> Option<(u64, Event)> {
+  let parts: Vec<&str> = line.split_whitespace().collect();
+  let frame = parts.first()?.parse::<u64>().ok()?;
+  let idx = parts.get(2)?.parse::<usize>().ok()?;
+  let value = parts.get(3);
+  let event = match (*parts.get(1)?, value) {
+    ("press", None) if idx < MAX_BUTTONS => Event::Press(idx),
+    ("release", None) if idx < MAX_BUTTONS => Event::Release(idx),
+    ("analog", Some(level)) if idx < MAX_ANALOG => Event::Analog(idx, level.parse().ok()?),
+    ("encoder", Some(steps)) if idx < MAX_ENCODERS => {
+      Event::Encoder(idx, steps.trim_start_matches('+').parse().ok()?)
+    }
+    _ => return None,
+  };
+  if parts.len() > 4 {
+    return None;
+  }
+  Some((frame, event))
+}
+
+impl Input for Script {
+  fn poll(&mut self) {
+    let frame = self.frame;
+    let due = self
+      .events
+      .iter()
+      .take_while(|(at, _)| *at <= frame)
+      .count();
+    for (_, event) in self.events.drain(..due) {
+      self.state.apply(event);
+    }
+    self.frame += 1;
+  }
+
+  fn buttons(&mut self) -> u16 {
+    self.state.buttons
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    self.state.analog[idx]
+  }
+
+  fn encoder(&mut self, idx: usize) -> i32 {
+    self.state.take_encoder(idx)
+  }
+}
+
+/// Controls from keys typed in the terminal, button keys hold the button for one frame.
+pub struct Keyboard {
+  keys: Receiver<u8>,
+  state: State,
+  _raw: RawMode,
+}
+
+impl Keyboard {
+  pub fn open() -> io::Result<Self> {
+    let raw = RawMode::enable()?;
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+      let mut buf = [0; 1];
+      while let Ok(1) = io::Read::read(&mut io::stdin(), &mut buf) {
+        if sender.send(buf[0]).is_err() {
+          break;
+        }
+      }
+    });
+    Ok(Keyboard {
+      keys,
+      state: State::default(),
+      _raw: raw,
+    })
+  }
+}
+
+impl Input for Keyboard {
+  fn poll(&mut self) {
+    self.state.buttons = 0;
+    for key in self.keys.try_iter() {
+      if let Some(idx) = BUTTON_KEYS.iter().position(|k| *k == key) {
+        self.state.apply(Event::Press(idx));
+      }
+      for (idx, (down, up)) in ANALOG_KEYS.iter().enumerate() {
+        let step = if key == *down {
+          -ANALOG_STEP
+        } else if key == *up {
+          ANALOG_STEP
+        } else {
+          continue;
+        };
+        let level = (self.state.analog[idx] as i32 + step).clamp(0, 255) as u8;
+        self.state.apply(Event::Analog(idx, level));
+      }
+      for (idx, (back, forth)) in ENCODER_KEYS.iter().enumerate() {
+        if key == *back {
+          self.state.apply(Event::Encoder(idx, -1));
+        } else if key == *forth {
+          self.state.apply(Event::Encoder(idx, 1));
+        }
+      }
+    }
+  }
+
+  fn buttons(&mut self) -> u16 {
+    self.state.buttons
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    self.state.analog[idx]
+  }
+
+  fn encoder(&mut self, idx: usize) -> i32 {
+    self.state.take_encoder(idx)
+  }
+}
+
+/// Several inputs at once: buttons are combined, the highest analog level wins and encoder steps
+/// add up.
+pub struct Inputs(pub Vec<Box<dyn Input>>);
+
+impl Input for Inputs {
+  fn poll(&mut self) {
+    for input in self.0.iter_mut() {
+      input.poll();
+    }
+  }
+
+  fn buttons(&mut self) -> u16 {
+    self
+      .0
+      .iter_mut()
+      .fold(0, |acc, input| acc | input.buttons())
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    self
+      .0
+      .iter_mut()
+      .map(|input| input.analog(idx))
+      .max()
+      .unwrap_or(0)
+  }
+
+  fn encoder(&mut self, idx: usize) -> i32 {
+    self.0.iter_mut().map(|input| input.encoder(idx)).sum()
+  }
+}
+
+/// Terminal without line buffering and echo, restored on drop and on Ctrl-C.
+struct RawMode;
+
+#[cfg(unix)]
+static SAVED: OnceLock<libc::termios> = OnceLock::new();
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+  if let Some(saved) = SAVED.get() {
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+  }
+  unsafe { libc::_exit(130) };
+}
+
+#[cfg(unix)]
+impl RawMode {
+  fn enable() -> io::Result<Self> {
+    unsafe {
+      let mut saved = std::mem::zeroed();
+      if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+        return Err(io::Error::last_os_error());
+      }
+      let saved = *SAVED.get_or_init(|| saved);
+      let mut raw = saved;
+      raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+      raw.c_cc[libc::VMIN] = 1;
+      raw.c_cc[libc::VTIME] = 0;
+      if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+        return Err(io::Error::last_os_error());
+      }
+      libc::signal(
+        libc::SIGINT,
+        on_interrupt as *const () as libc::sighandler_t,
+      );
+      Ok(RawMode)
+    }
+  }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+  fn drop(&mut self) {
+    if let Some(saved) = SAVED.get() {
+      unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+    }
+  }
+}
+
+#[cfg(not(unix))]
+impl RawMode {
+  fn enable() -> io::Result<Self> {
+    Ok(RawMode)
+  }
+}
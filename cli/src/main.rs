@@ -1,12 +1,45 @@
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use strip_shared::compiler::compile;
+use std::path::Path;
+use strip_shared::compiler::compile_with;
+use strip_shared::compositor::{Layer, MAX_LAYERS};
+use strip_shared::input::Input;
+use strip_shared::led::{
+  Channel, ColorOrder, LedEnv, Matrix, PixelFormat, Wiring, ECALLS, MAX_PIXEL_SIZE,
+};
+use strip_shared::link::Packet;
+use strip_shared::matrix::NO_LED;
+use strip_shared::output::PowerBudget;
 use strip_shared::parser::parse;
+use strip_shared::storage::NAME_LEN;
+use strip_shared::transition::Transition;
+use strip_shared::vm::Header;
 
+mod audio;
 mod debug;
-use debug::Trace;
+mod dmx;
+mod input;
+mod monitor;
+mod opc;
+mod playlist;
+mod power;
+mod render;
+mod run;
+mod term;
+mod upload;
+
+use audio::AudioFile;
+use debug::{Environment, Trace};
+use dmx::{Dmx, Protocol};
+use input::{Inputs, Keyboard, Script, KEYS_HELP};
+use opc::Opc;
+use playlist::{Entry, PLAYLIST_HELP, TRANSITION_HELP};
+use power::PowerMeter;
+use render::{Animation, Filmstrip, Preview};
+use run::{LayerSpec, Runner, Show, LAYER_HELP};
+use term::Terminal;
 
 fn main() -> io::Result<()> {
   let mut app = App::new("strip")
@@ -57,6 +90,21 @@ fn main() -> io::Result<()> {
             .default_value("8")
             .help("Sets RAM size"),
         )
+        .arg(
+          Arg::with_name("LEDS")
+            .short("leds")
+            .long("leds")
+            .default_value("300")
+            .help("Sets LED strip size, comma separated for several channels"),
+        )
+        .arg(
+          Arg::with_name("ORDER")
+            .long("order")
+            .default_value("rgb")
+            .help(
+              "Sets default pixel format, like grb, grbw or rgb16, comma separated per channel",
+            ),
+        )
         .arg(
           Arg::with_name("SPINS")
             .short("spins")
@@ -69,6 +117,421 @@ fn main() -> io::Result<()> {
             .takes_value(true)
             .help("Sets VM ops quota"),
         ),
+    )
+    .subcommand(
+      App::new("run")
+        .about("Runs program on simulated LED strip")
+        .arg(
+          Arg::with_name("INPUT")
+            .help("Sets the input file")
+            .value_name("INPUT")
+            .required_unless("PLAYLIST")
+            .conflicts_with("PLAYLIST")
+            .index(1),
+        )
+        .arg(
+          Arg::with_name("RAM")
+            .short("ram")
+            .default_value("1024")
+            .help("Sets RAM size"),
+        )
+        .arg(
+          Arg::with_name("LEDS")
+            .short("leds")
+            .long("leds")
+            .default_value("300")
+            .help("Sets LED strip size, comma separated for several channels"),
+        )
+        .arg(
+          Arg::with_name("ORDER")
+            .long("order")
+            .default_value("rgb")
+            .help(
+              "Sets default pixel format, like grb, grbw or rgb16, comma separated per channel",
+            ),
+        )
+        .arg(
+          Arg::with_name("FRAMES")
+            .short("frames")
+            .long("frames")
+            .takes_value(true)
+            .help("Stops after number of frames"),
+        )
+        .arg(
+          Arg::with_name("SEED")
+            .long("seed")
+            .takes_value(true)
+            .help("Sets random number generator seed"),
+        )
+        .arg(
+          Arg::with_name("LIVE")
+            .long("live")
+            .help("Renders LED strip in terminal"),
+        )
+        .arg(
+          Arg::with_name("WIDTH")
+            .short("width")
+            .long("width")
+            .default_value("60")
+            .help("Sets LEDs per terminal row"),
+        )
+        .arg(
+          Arg::with_name("MATRIX")
+            .long("matrix")
+            .help("Maps LED strip as row-major matrix of given width"),
+        )
+        .arg(
+          Arg::with_name("XY")
+            .long("xy")
+            .takes_value(true)
+            .help("Maps LEDs as 2D panel, like 16x16,serpentine,rot90,flipx"),
+        )
+        .arg(
+          Arg::with_name("XY_LUT")
+            .long("xy-lut")
+            .takes_value(true)
+            .help("Sets LED indices of lut wired panel positions from file"),
+        )
+        .arg(
+          Arg::with_name("INPUT_SCRIPT")
+            .long("input")
+            .takes_value(true)
+            .help("Reads controls from script of frame, control, index and value lines"),
+        )
+        .arg(
+          Arg::with_name("AUDIO")
+            .long("audio")
+            .takes_value(true)
+            .help("Analyzes sound of a PCM WAV file in step with frames"),
+        )
+        .arg(
+          Arg::with_name("LAYER")
+            .long("layer")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help(LAYER_HELP),
+        )
+        .arg(
+          Arg::with_name("PLAYLIST")
+            .long("playlist")
+            .takes_value(true)
+            .help(PLAYLIST_HELP),
+        )
+        .arg(
+          Arg::with_name("TRANSITION")
+            .long("transition")
+            .default_value("cut")
+            .help(TRANSITION_HELP),
+        )
+        .arg(Arg::with_name("KEYS").long("keys").help(KEYS_HELP))
+        .arg(
+          Arg::with_name("POWER")
+            .long("power")
+            .help("Reports estimated current draw"),
+        )
+        .arg(
+          Arg::with_name("POWER_LIMIT")
+            .long("power-limit")
+            .takes_value(true)
+            .help("Scales frames down to supply limit in mA"),
+        )
+        .arg(
+          Arg::with_name("CHANNEL_MA")
+            .long("channel-ma")
+            .default_value("20")
+            .help("Sets current draw of a fully lit channel in mA"),
+        )
+        .arg(
+          Arg::with_name("OPC")
+            .long("opc")
+            .takes_value(true)
+            .help("Streams frames to Open Pixel Control server"),
+        )
+        .arg(
+          Arg::with_name("OPC_CHANNEL")
+            .long("opc-channel")
+            .default_value("0")
+            .help("Sets OPC channel of the first strip channel"),
+        )
+        .arg(
+          Arg::with_name("OPC_ORDER")
+            .long("opc-order")
+            .default_value("rgb")
+            .help("Sets OPC color order"),
+        )
+        .arg(
+          Arg::with_name("OPC_FPS")
+            .long("opc-fps")
+            .takes_value(true)
+            .help("Limits OPC frame rate"),
+        )
+        .arg(
+          Arg::with_name("DMX")
+            .long("dmx")
+            .takes_value(true)
+            .possible_values(&["e131", "sacn", "artnet"])
+            .help("Sends frames as DMX universes over UDP"),
+        )
+        .arg(
+          Arg::with_name("DMX_DEST")
+            .long("dmx-dest")
+            .takes_value(true)
+            .help("Sets DMX unicast or broadcast destination"),
+        )
+        .arg(
+          Arg::with_name("DMX_UNIVERSE")
+            .long("dmx-universe")
+            .takes_value(true)
+            .help("Sets DMX start universe"),
+        )
+        .arg(
+          Arg::with_name("DMX_CHANNELS")
+            .long("dmx-channels")
+            .default_value("510")
+            .help("Sets DMX channels per universe"),
+        ),
+    )
+    .subcommand(
+      App::new("render")
+        .about("Renders program animation to GIF or PNG filmstrip")
+        .arg(
+          Arg::with_name("INPUT")
+            .help("Sets the input file")
+            .value_name("INPUT")
+            .required_unless("PLAYLIST")
+            .conflicts_with("PLAYLIST")
+            .index(1),
+        )
+        .arg(
+          Arg::with_name("OUTPUT")
+            .short("out")
+            .long("out")
+            .required(true)
+            .takes_value(true)
+            .help("Sets the output file, .gif or .png"),
+        )
+        .arg(
+          Arg::with_name("RAM")
+            .short("ram")
+            .default_value("1024")
+            .help("Sets RAM size"),
+        )
+        .arg(
+          Arg::with_name("LEDS")
+            .short("leds")
+            .long("leds")
+            .default_value("300")
+            .help("Sets LED strip size, comma separated for several channels"),
+        )
+        .arg(
+          Arg::with_name("ORDER")
+            .long("order")
+            .default_value("rgb")
+            .help(
+              "Sets default pixel format, like grb, grbw or rgb16, comma separated per channel",
+            ),
+        )
+        .arg(
+          Arg::with_name("FRAMES")
+            .short("frames")
+            .long("frames")
+            .default_value("300")
+            .help("Sets number of frames"),
+        )
+        .arg(
+          Arg::with_name("SEED")
+            .long("seed")
+            .takes_value(true)
+            .help("Sets random number generator seed"),
+        )
+        .arg(
+          Arg::with_name("WIDTH")
+            .short("width")
+            .long("width")
+            .takes_value(true)
+            .help("Sets LEDs per image row"),
+        )
+        .arg(
+          Arg::with_name("SCALE")
+            .short("scale")
+            .long("scale")
+            .default_value("4")
+            .help("Sets LED size in pixels"),
+        )
+        .arg(
+          Arg::with_name("XY")
+            .long("xy")
+            .takes_value(true)
+            .help("Maps LEDs as 2D panel, like 16x16,serpentine,rot90,flipx"),
+        )
+        .arg(
+          Arg::with_name("XY_LUT")
+            .long("xy-lut")
+            .takes_value(true)
+            .help("Sets LED indices of lut wired panel positions from file"),
+        )
+        .arg(
+          Arg::with_name("INPUT_SCRIPT")
+            .long("input")
+            .takes_value(true)
+            .help("Reads controls from script of frame, control, index and value lines"),
+        )
+        .arg(
+          Arg::with_name("AUDIO")
+            .long("audio")
+            .takes_value(true)
+            .help("Analyzes sound of a PCM WAV file in step with frames"),
+        )
+        .arg(
+          Arg::with_name("LAYER")
+            .long("layer")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help(LAYER_HELP),
+        )
+        .arg(
+          Arg::with_name("PLAYLIST")
+            .long("playlist")
+            .takes_value(true)
+            .help(PLAYLIST_HELP),
+        )
+        .arg(
+          Arg::with_name("TRANSITION")
+            .long("transition")
+            .default_value("cut")
+            .help(TRANSITION_HELP),
+        )
+        .arg(
+          Arg::with_name("GAMMA")
+            .long("gamma")
+            .default_value("1.0")
+            .help("Sets preview gamma"),
+        )
+        .arg(
+          Arg::with_name("BRIGHTNESS")
+            .long("brightness")
+            .default_value("1.0")
+            .help("Sets preview brightness"),
+        ),
+    )
+    .subcommand(
+      App::new("upload")
+        .about("Uploads program to the device")
+        .arg(
+          Arg::with_name("INPUT")
+            .help("Sets the input file")
+            .value_name("INPUT")
+            .required(true)
+            .index(1),
+        )
+        .arg(
+          Arg::with_name("PORT")
+            .short("port")
+            .long("port")
+            .required(true)
+            .takes_value(true)
+            .help("Sets serial port"),
+        )
+        .arg(
+          Arg::with_name("BAUD_RATE")
+            .short("baud")
+            .long("baud")
+            .default_value("115200")
+            .help("Sets serial port baud rate"),
+        )
+        .arg(
+          Arg::with_name("SLOT")
+            .long("slot")
+            .takes_value(true)
+            .help("Stores program in the slot"),
+        )
+        .arg(
+          Arg::with_name("NAME")
+            .long("name")
+            .takes_value(true)
+            .requires("SLOT")
+            .help("Sets slot name, defaults to file name"),
+        )
+        .arg(
+          Arg::with_name("DEFAULT")
+            .long("default")
+            .requires("SLOT")
+            .help("Runs the slot at boot"),
+        ),
+    )
+    .subcommand(
+      App::new("slot")
+        .about("Switches the program slot on the device")
+        .arg(
+          Arg::with_name("SLOT")
+            .help("Sets slot number, next or prev")
+            .value_name("SLOT")
+            .required(true)
+            .index(1),
+        )
+        .arg(
+          Arg::with_name("PORT")
+            .short("port")
+            .long("port")
+            .required(true)
+            .takes_value(true)
+            .help("Sets serial port"),
+        )
+        .arg(
+          Arg::with_name("BAUD_RATE")
+            .short("baud")
+            .long("baud")
+            .default_value("115200")
+            .help("Sets serial port baud rate"),
+        )
+        .arg(
+          Arg::with_name("DEFAULT")
+            .long("default")
+            .help("Runs the slot at boot"),
+        ),
+    )
+    .subcommand(
+      App::new("monitor")
+        .about("Queries and controls the program running on the device")
+        .arg(
+          Arg::with_name("ACTION")
+            .help("Sets action")
+            .value_name("ACTION")
+            .possible_values(&["status", "pause", "resume", "step", "read", "write"])
+            .required(true)
+            .index(1),
+        )
+        .arg(
+          Arg::with_name("ADDR")
+            .help("Sets VM memory address to read or write, like 0x1000")
+            .value_name("ADDR")
+            .required_ifs(&[("ACTION", "read"), ("ACTION", "write")])
+            .index(2),
+        )
+        .arg(
+          Arg::with_name("VALUE")
+            .help("Sets byte count to read or hex bytes to write")
+            .value_name("VALUE")
+            .required_ifs(&[("ACTION", "read"), ("ACTION", "write")])
+            .index(3),
+        )
+        .arg(
+          Arg::with_name("PORT")
+            .short("port")
+            .long("port")
+            .required(true)
+            .takes_value(true)
+            .help("Sets serial port"),
+        )
+        .arg(
+          Arg::with_name("BAUD_RATE")
+            .short("baud")
+            .long("baud")
+            .default_value("115200")
+            .help("Sets serial port baud rate"),
+        ),
     );
 
   match app.clone().get_matches().subcommand() {
@@ -78,38 +541,211 @@ fn main() -> io::Result<()> {
       let mut code = String::new();
       file.read_to_string(&mut code)?;
 
-      let exprs = parse(&code).unwrap();
-      let bytecode = compile(&exprs).unwrap();
+      let bytecode = compile(&code);
 
       let out_path = args.value_of("OUTPUT").unwrap();
       let mut file = File::create(out_path).unwrap();
       file.write_all(&bytecode).unwrap();
     }
     ("trace", Some(args)) => {
-      let input = args.value_of("INPUT").unwrap();
-      let mut file = File::open(input).unwrap();
-      let mut content = vec![];
-      file.read_to_end(&mut content)?;
+      let bytecode = load_bytecode(args.value_of("INPUT").unwrap())?;
 
-      let bytecode = if content.len() >= 4 && content[0] == 0xaf && content[1] == 0xaf {
-        content
-      } else {
-        let code = String::from_utf8_lossy(&content);
-        let exprs = parse(&code).unwrap();
-        compile(&exprs).unwrap()
-      };
-
-      let spins = u16::from_str_radix(args.value_of("SPINS").unwrap(), 10).unwrap();
-      let ram = u16::from_str_radix(args.value_of("RAM").unwrap(), 10).unwrap();
+      let spins = args.value_of("SPINS").unwrap().parse::<u16>().unwrap();
+      let ram = args.value_of("RAM").unwrap().parse::<u16>().unwrap();
+      let leds = args.value_of("LEDS").unwrap();
+      let format = args.value_of("ORDER").unwrap();
       let trace_mem = args.is_present("MEMORY");
       let trace_ecalls = args.is_present("ECALLS");
-      let max_ops = args
-        .value_of("MAX_OPS")
-        .map(|s| u32::from_str_radix(s, 10).unwrap());
+      let max_ops = args.value_of("MAX_OPS").map(|s| s.parse::<u32>().unwrap());
 
-      let mut trace = Trace::new(spins, max_ops, ram, trace_mem, trace_ecalls, &bytecode).unwrap();
+      let strip = strip_env(ram, leds, format);
+      let env = Environment::new(strip, trace_mem, trace_ecalls);
+      let mut trace = Trace::new(spins, max_ops, env, &bytecode).unwrap();
       trace.start().unwrap();
     }
+    ("run", Some(args)) => {
+      let playlist = load_playlist(args)?;
+      let bytecode = match args.value_of("INPUT") {
+        Some(input) => load_bytecode(input)?,
+        None => playlist[0].1.clone(),
+      };
+
+      let ram = args.value_of("RAM").unwrap().parse::<u16>().unwrap();
+      let leds = args.value_of("LEDS").unwrap();
+      let format = args.value_of("ORDER").unwrap();
+      let width = args.value_of("WIDTH").unwrap().parse::<usize>().unwrap();
+      let max_frames = args.value_of("FRAMES").map(|s| s.parse::<u64>().unwrap());
+
+      let new_strip = || {
+        let mut strip = strip_env(ram, leds, format);
+        if let Some(seed) = args.value_of("SEED") {
+          strip.set_seed(seed.parse::<u32>().unwrap());
+        }
+        strip.output_mut().set_power_budget(PowerBudget {
+          channel_ma: args.value_of("CHANNEL_MA").unwrap().parse::<u16>().unwrap(),
+          limit_ma: args
+            .value_of("POWER_LIMIT")
+            .map_or(0, |s| s.parse::<u32>().unwrap()),
+          ..PowerBudget::default()
+        });
+        if args.is_present("MATRIX") {
+          let height = strip.leds().div_ceil(width.max(1));
+          strip.set_matrix(Some(Matrix::new(width as u16, height as u16, Wiring::Rows)));
+        }
+        set_matrix(&mut strip, args).map(|_| strip)
+      };
+      let power = args.is_present("POWER");
+      let layers = load_layers(args)?;
+      let mut runner = Runner::new(new_strip()?, new_strip()?, max_frames, &bytecode).unwrap();
+      add_layers(&mut runner, &layers, new_strip)?;
+      set_playlist(&mut runner, &playlist, args, new_strip()?);
+      let mut inputs: Vec<Box<dyn Input>> = vec![];
+      if let Some(path) = args.value_of("INPUT_SCRIPT") {
+        inputs.push(Box::new(Script::parse(&std::fs::read_to_string(path)?)?));
+      }
+      if args.is_present("KEYS") {
+        inputs.push(Box::new(Keyboard::open()?));
+      }
+      runner.set_input(Box::new(Inputs(inputs)));
+      if let Some(path) = args.value_of("AUDIO") {
+        runner.set_audio(AudioFile::open(path)?);
+      }
+      if args.is_present("LIVE") {
+        runner.add_output(Box::new(Terminal::new(width, power)));
+      }
+      if power {
+        runner.add_output(Box::new(PowerMeter::new()));
+      }
+      if let Some(addr) = args.value_of("OPC") {
+        let channel = args.value_of("OPC_CHANNEL").unwrap().parse::<u8>().unwrap();
+        let order = ColorOrder::parse(args.value_of("OPC_ORDER").unwrap()).unwrap();
+        let fps = args.value_of("OPC_FPS").map(|s| s.parse::<u32>().unwrap());
+        runner.add_output(Box::new(Opc::connect(addr, channel, order, fps)?));
+      }
+      if let Some(protocol) = args.value_of("DMX").and_then(Protocol::parse) {
+        let dest = args.value_of("DMX_DEST");
+        let universe = args
+          .value_of("DMX_UNIVERSE")
+          .map_or(protocol.first_universe() as u32, |s| s.parse::<u32>().unwrap());
+        let channels = args
+          .value_of("DMX_CHANNELS")
+          .unwrap()
+          .parse::<usize>()
+          .unwrap();
+        runner.add_output(Box::new(Dmx::bind(protocol, dest, universe, channels)?));
+      }
+      runner.start()?;
+    }
+    ("render", Some(args)) => {
+      let playlist = load_playlist(args)?;
+      let bytecode = match args.value_of("INPUT") {
+        Some(input) => load_bytecode(input)?,
+        None => playlist[0].1.clone(),
+      };
+
+      let out_path = args.value_of("OUTPUT").unwrap();
+      let ram = args.value_of("RAM").unwrap().parse::<u16>().unwrap();
+      let leds = args.value_of("LEDS").unwrap();
+      let format = args.value_of("ORDER").unwrap();
+      let frames = args.value_of("FRAMES").unwrap().parse::<u64>().unwrap();
+      let scale = args.value_of("SCALE").unwrap().parse::<usize>().unwrap();
+      let gamma = args.value_of("GAMMA").unwrap().parse::<f32>().unwrap();
+      let brightness = args.value_of("BRIGHTNESS").unwrap().parse::<f32>().unwrap();
+
+      let new_strip = || {
+        let mut strip = strip_env(ram, leds, format);
+        if let Some(seed) = args.value_of("SEED") {
+          strip.set_seed(seed.parse::<u32>().unwrap());
+        }
+        set_matrix(&mut strip, args).map(|_| strip)
+      };
+      let strip = new_strip()?;
+      let leds = strip.leds();
+      let width = args
+        .value_of("WIDTH")
+        .map_or(leds, |s| s.parse::<usize>().unwrap());
+      let layers = load_layers(args)?;
+      let mut runner = Runner::new(strip, new_strip()?, Some(frames), &bytecode).unwrap();
+      add_layers(&mut runner, &layers, new_strip)?;
+      set_playlist(&mut runner, &playlist, args, new_strip()?);
+      if let Some(path) = args.value_of("INPUT_SCRIPT") {
+        runner.set_input(Box::new(Script::parse(&std::fs::read_to_string(path)?)?));
+      }
+      if let Some(path) = args.value_of("AUDIO") {
+        runner.set_audio(AudioFile::open(path)?);
+      }
+      let preview = Preview::new(gamma, brightness);
+      if out_path.ends_with(".png") {
+        runner.add_output(Box::new(Filmstrip::new(out_path, leds, scale, preview)));
+      } else {
+        let animation = Animation::create(out_path, width, scale, preview)?;
+        runner.add_output(Box::new(animation));
+      }
+      runner.render()?;
+    }
+    ("upload", Some(args)) => {
+      let input = args.value_of("INPUT").unwrap();
+      let bytecode = load_bytecode(input)?;
+      let baud_rate = args.value_of("BAUD_RATE").unwrap().parse::<u32>().unwrap();
+      let mut port = upload::open(args.value_of("PORT").unwrap(), baud_rate)?;
+      upload::upload(&mut port, &bytecode)?;
+      println!("Uploaded {} bytes", bytecode.len());
+
+      if let Some(slot) = args.value_of("SLOT").map(|s| s.parse::<u8>().unwrap()) {
+        let stem = Path::new(input).file_stem().unwrap().to_string_lossy();
+        let name = args.value_of("NAME").unwrap_or(&stem);
+        let name = &name.as_bytes()[..name.len().min(NAME_LEN)];
+        upload::send(&mut port, Packet::Save { slot, name })?;
+        if args.is_present("DEFAULT") {
+          upload::send(&mut port, Packet::SetDefault(slot))?;
+        }
+        println!("Stored in slot {}", slot);
+      }
+    }
+    ("slot", Some(args)) => {
+      let baud_rate = args.value_of("BAUD_RATE").unwrap().parse::<u32>().unwrap();
+      let mut port = upload::open(args.value_of("PORT").unwrap(), baud_rate)?;
+      let packet = match args.value_of("SLOT").unwrap() {
+        "next" => Packet::Next,
+        "prev" => Packet::Prev,
+        slot => Packet::Select(slot.parse::<u8>().unwrap()),
+      };
+      upload::send(&mut port, packet)?;
+      if let (true, Packet::Select(slot)) = (args.is_present("DEFAULT"), packet) {
+        upload::send(&mut port, Packet::SetDefault(slot))?;
+      }
+    }
+    ("monitor", Some(args)) => {
+      let baud_rate = args.value_of("BAUD_RATE").unwrap().parse::<u32>().unwrap();
+      let mut port = upload::open(args.value_of("PORT").unwrap(), baud_rate)?;
+      let addr = args.value_of("ADDR").map(|addr| {
+        let addr = match addr.strip_prefix("0x") {
+          Some(hex) => u16::from_str_radix(hex, 16),
+          None => addr.parse::<u16>(),
+        };
+        addr.expect("invalid address")
+      });
+      match args.value_of("ACTION").unwrap() {
+        "status" => print!("{}", monitor::format_status(&monitor::status(&mut port)?)),
+        "pause" => upload::send(&mut port, Packet::Pause)?,
+        "resume" => upload::send(&mut port, Packet::Resume)?,
+        "step" => upload::send(&mut port, Packet::Step)?,
+        "read" => {
+          let len = args.value_of("VALUE").unwrap().parse::<usize>().unwrap();
+          let mem = monitor::read(&mut port, addr.unwrap(), len)?;
+          print!("{}", monitor::format_memory(addr.unwrap(), &mem));
+        }
+        _ => {
+          let hex = args.value_of("VALUE").unwrap();
+          let data: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..(idx + 2).min(hex.len())], 16))
+            .collect::<Result<_, _>>()
+            .expect("invalid hex bytes");
+          monitor::write(&mut port, addr.unwrap(), &data)?;
+        }
+      }
+    }
     _ => {
       app.print_long_help().unwrap();
     }
@@ -117,3 +753,140 @@ fn main() -> io::Result<()> {
 
   Ok(())
 }
+
+/// Channels take comma separated lengths and pixel formats, the last format repeats.
+/// LED RAM and the front buffer fit the strip in any pixel format a program may declare.
+fn strip_env(ram: u16, leds: &str, formats: &str) -> LedEnv<Vec<u8>> {
+  let formats: Vec<PixelFormat> = formats
+    .split(',')
+    .map(|format| PixelFormat::parse(format.trim()).expect("unknown pixel format"))
+    .collect();
+  let channels: Vec<Channel> = leds
+    .split(',')
+    .enumerate()
+    .map(|(idx, leds)| Channel {
+      leds: leds.trim().parse::<u16>().expect("invalid LED count") as usize,
+      format: formats[idx.min(formats.len() - 1)],
+    })
+    .collect();
+  let total: usize = channels.iter().map(|ch| ch.leds).sum();
+  let mut strip = LedEnv::new(
+    vec![0; ram as usize],
+    vec![0; total * MAX_PIXEL_SIZE],
+    formats[0],
+  );
+  strip
+    .set_channels(&channels)
+    .expect("invalid channel configuration");
+  strip.set_front(vec![0; total * MAX_PIXEL_SIZE]).unwrap();
+  strip
+}
+
+/// Applies `--xy` and `--xy-lut`, LUT files list LED indices of panel positions row by row,
+/// `-` marks positions without LED.
+fn set_matrix(strip: &mut LedEnv<Vec<u8>>, args: &ArgMatches) -> io::Result<()> {
+  if let Some(spec) = args.value_of("XY") {
+    strip.set_matrix(Some(Matrix::parse(spec).expect("invalid matrix")));
+  }
+  if let Some(path) = args.value_of("XY_LUT") {
+    let mut lut = vec![];
+    for entry in std::fs::read_to_string(path)?.split(|c: char| c == ',' || c.is_whitespace()) {
+      let idx = match entry {
+        "" => continue,
+        "-" => NO_LED,
+        idx => idx.parse::<u16>().expect("invalid LUT entry"),
+      };
+      lut.extend_from_slice(&idx.to_be_bytes());
+    }
+    strip.set_lut(lut);
+  }
+  Ok(())
+}
+
+/// Parses `--layer` options and loads their programs.
+fn load_layers<'a>(args: &'a ArgMatches) -> io::Result<Vec<(LayerSpec<'a>, Vec<u8>)>> {
+  let specs = args.values_of("LAYER").into_iter().flatten();
+  specs
+    .map(|spec| {
+      let spec = LayerSpec::parse(spec).expect("invalid layer");
+      Ok((spec, load_bytecode(spec.path)?))
+    })
+    .collect()
+}
+
+fn add_layers<'a>(
+  runner: &mut Runner<'a>,
+  layers: &'a [(LayerSpec, Vec<u8>)],
+  new_strip: impl Fn() -> io::Result<LedEnv<Vec<u8>>>,
+) -> io::Result<()> {
+  for (spec, bytecode) in layers {
+    let mut layer = Layer::new(new_strip()?, bytecode).expect("invalid layer program");
+    spec.apply(&mut layer);
+    if !runner.add_layer(layer) {
+      panic!("at most {} programs run at once", MAX_LAYERS);
+    }
+  }
+  Ok(())
+}
+
+/// Parses `--playlist` and loads its programs.
+fn load_playlist(args: &ArgMatches) -> io::Result<Vec<(Entry, Vec<u8>)>> {
+  let path = match args.value_of("PLAYLIST") {
+    Some(path) => Path::new(path),
+    None => return Ok(vec![]),
+  };
+  let dir = path.parent().unwrap_or_else(|| Path::new(""));
+  let entries = playlist::parse(&std::fs::read_to_string(path)?, dir)?;
+  entries
+    .into_iter()
+    .map(|entry| {
+      let bytecode = load_bytecode(&entry.path.to_string_lossy())?;
+      Ok((entry, bytecode))
+    })
+    .collect()
+}
+
+fn set_playlist<'a>(
+  runner: &mut Runner<'a>,
+  playlist: &'a [(Entry, Vec<u8>)],
+  args: &ArgMatches,
+  env: LedEnv<Vec<u8>>,
+) {
+  if playlist.is_empty() {
+    return;
+  }
+  let transition = args.value_of("TRANSITION").and_then(Transition::parse);
+  let transition = transition.expect("invalid transition");
+  let shows = playlist
+    .iter()
+    .map(|(entry, bytecode)| Show {
+      bytecode,
+      frames: entry.frames,
+      transition: entry.transition.unwrap_or(transition),
+    })
+    .collect();
+  runner
+    .set_playlist(shows, env)
+    .expect("invalid playlist program");
+}
+
+fn load_bytecode(input: &str) -> io::Result<Vec<u8>> {
+  let mut file = File::open(input)?;
+  let mut content = vec![];
+  file.read_to_end(&mut content)?;
+
+  if Header::parse(&content).is_some() {
+    return Ok(content);
+  }
+  Ok(compile(&String::from_utf8_lossy(&content)))
+}
+
+/// Compiles assembly against the strip ecalls, printing compiler warnings to stderr.
+fn compile(code: &str) -> Vec<u8> {
+  let exprs = parse(code).unwrap();
+  let (bytecode, warnings) = compile_with(&exprs, ECALLS).unwrap();
+  for warning in warnings {
+    eprintln!("{}", warning);
+  }
+  bytecode
+}
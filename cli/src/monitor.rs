@@ -0,0 +1,89 @@
+use crate::upload::{query, send};
+use std::io;
+use std::io::prelude::*;
+use strip_shared::link::{Packet, CHUNK_SIZE};
+use strip_shared::telemetry::{Status, MAX_READ};
+
+pub fn status<P: Read + Write>(port: &mut P) -> io::Result<Status> {
+  query(port, Packet::Status, |reply| match reply {
+    Packet::Report(status) => Some(status),
+    _ => None,
+  })
+}
+
+/// Reads VM memory in chunks the device can reply with.
+pub fn read<P: Read + Write>(port: &mut P, addr: u16, len: usize) -> io::Result<Vec<u8>> {
+  let mut mem = Vec::with_capacity(len);
+  while mem.len() < len {
+    let at = addr.wrapping_add(mem.len() as u16);
+    let chunk = (len - mem.len()).min(MAX_READ) as u8;
+    let data = query(
+      port,
+      Packet::Read {
+        addr: at,
+        len: chunk,
+      },
+      |reply| match reply {
+        Packet::Memory { addr, data } if addr == at && data.len() == chunk as usize => {
+          Some(data.to_vec())
+        }
+        _ => None,
+      },
+    )?;
+    mem.extend_from_slice(&data);
+  }
+  Ok(mem)
+}
+
+pub fn write<P: Read + Write>(port: &mut P, addr: u16, data: &[u8]) -> io::Result<()> {
+  for (idx, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+    let addr = addr.wrapping_add((idx * CHUNK_SIZE) as u16);
+    send(port, Packet::Write { addr, data: chunk })?;
+  }
+  Ok(())
+}
+
+pub fn format_status(status: &Status) -> String {
+  let program = match status.slot {
+    Some(slot) => format!(
+      "slot {} \"{}\"",
+      slot,
+      String::from_utf8_lossy(status.name())
+    ),
+    None => "uploaded or built-in".to_string(),
+  };
+  let state = match (status.paused, status.fallback) {
+    (_, true) => "safe program after faults",
+    (true, false) => "paused",
+    (false, false) => "running",
+  };
+  let last_error = status
+    .last_error
+    .map_or("none".to_string(), |err| format!("{:?}", err));
+  format!(
+    "Program: {}\nState: {}\nFrames: {}\nInstructions: {} average, {} max per frame\n\
+     Spin: {} us\nFaults: {}, last error: {}\nBrightness: {}, power: {} mA\n",
+    program,
+    state,
+    status.frames,
+    status.avg_ops,
+    status.max_ops,
+    status.spin_us,
+    status.faults,
+    last_error,
+    status.brightness,
+    status.power_ma,
+  )
+}
+
+/// Formats memory as hex dump lines of 16 bytes.
+pub fn format_memory(addr: u16, mem: &[u8]) -> String {
+  mem
+    .chunks(16)
+    .enumerate()
+    .map(|(idx, row)| {
+      let bytes: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+      format!("{:04x}: {}\n", addr as usize + idx * 16, bytes.join(" "))
+    })
+    .collect()
+}
@@ -0,0 +1,61 @@
+use crate::run::Output;
+use std::io;
+use std::io::prelude::*;
+use std::net::TcpStream;
+use strip_shared::led::{ColorOrder, LedEnv, TIMER_HZ};
+
+const SET_PIXEL_COLORS: u8 = 0;
+
+pub struct Opc {
+  stream: TcpStream,
+  channel: u8,
+  order: ColorOrder,
+  interval: u64,
+  elapsed: u64,
+  next_frame: u64,
+}
+
+impl Opc {
+  pub fn connect(addr: &str, channel: u8, order: ColorOrder, fps: Option<u32>) -> io::Result<Self> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    let interval = fps.map_or(0, |fps| TIMER_HZ as u64 / fps.max(1) as u64);
+    Ok(Opc {
+      stream,
+      channel,
+      order,
+      interval,
+      elapsed: 0,
+      next_frame: 0,
+    })
+  }
+}
+
+impl Output for Opc {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, ticks: u32) -> io::Result<()> {
+    let due = self.elapsed >= self.next_frame;
+    self.elapsed += ticks as u64;
+    if !due {
+      return Ok(());
+    }
+    self.next_frame += self.interval.max(1);
+    if self.next_frame < self.elapsed {
+      self.next_frame = self.elapsed;
+    }
+
+    // Strip channels go to consecutive OPC channels.
+    let mut msg = vec![];
+    let mut px = [0; 3];
+    for ch in 0..strip.channels() {
+      let len = strip.channel(ch).leds * 3;
+      msg.push(self.channel.wrapping_add(ch as u8));
+      msg.push(SET_PIXEL_COLORS);
+      msg.extend_from_slice(&(len as u16).to_be_bytes());
+      for rgb in strip.channel_output_pixels(ch) {
+        self.order.encode(rgb, &mut px);
+        msg.extend_from_slice(&px);
+      }
+    }
+    self.stream.write_all(&msg)
+  }
+}
@@ -0,0 +1,57 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use strip_shared::transition::Transition;
+
+pub const PLAYLIST_HELP: &str = "Loops over programs listed as FRAMES PATH [TRANSITION] lines, \
+  paths are relative to the playlist";
+pub const TRANSITION_HELP: &str = "Switches playlist programs with crossfade, wipe or dissolve \
+  as EFFECT:FRAMES, or cut";
+
+/// Program of a playlist, shown for `frames` frames after switching to it with `transition`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+  pub frames: u64,
+  pub path: PathBuf,
+  pub transition: Option<Transition>,
+}
+
+pub fn parse(playlist: &str, dir: &Path) -> io::Result<Vec<Entry>> {
+  let mut entries = vec![];
+  for (line_idx, line) in playlist.lines().enumerate() {
+    let line = line.split('#').next().unwrap().trim();
+    if line.is_empty() {
+      continue;
+    }
+    let entry = parse_entry(line, dir).ok_or_else(|| {
+      let msg = format!("invalid playlist entry on line {}: {}", line_idx + 1, line);
+      io::Error::new(io::ErrorKind::InvalidData, msg)
+    })?;
+    entries.push(entry);
+  }
+  if entries.is_empty() {
+    let msg = "playlist has no programs";
+    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+  }
+  Ok(entries)
+}
+
+fn parse_entry(line: &str, dir: &Path) -> Option<Entry> {
+  let parts: Vec<&str> = line.split_whitespace().collect();
+  let frames = parts
+    .first()?
+    .parse::<u64>()
+    .ok()
+    .filter(|&frames| frames > 0)?;
+  let transition = match parts.get(2) {
+    Some(spec) => Some(Transition::parse(spec)?),
+    None => None,
+  };
+  if parts.len() > 3 {
+    return None;
+  }
+  Some(Entry {
+    frames,
+    path: dir.join(parts.get(1)?),
+    transition,
+  })
+}
@@ -0,0 +1,40 @@
+use crate::run::Output;
+use std::io;
+use strip_shared::led::LedEnv;
+
+/// Collects estimated current draw of rendered frames and prints a summary at the end.
+#[derive(Default)]
+pub struct PowerMeter {
+  frames: u64,
+  limited: u64,
+  peak_ma: u32,
+  total_ma: u64,
+}
+
+impl PowerMeter {
+  pub fn new() -> Self {
+    PowerMeter::default()
+  }
+}
+
+impl Output for PowerMeter {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, _ticks: u32) -> io::Result<()> {
+    let power = strip.power();
+    self.frames += 1;
+    self.peak_ma = self.peak_ma.max(power.ma);
+    self.total_ma += power.ma as u64;
+    if power.scale < 255 {
+      self.limited += 1;
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> io::Result<()> {
+    let average = self.total_ma / self.frames.max(1);
+    println!(
+      "Power: peak {} mA, average {} mA, {} of {} frames limited",
+      self.peak_ma, average, self.limited, self.frames
+    );
+    Ok(())
+  }
+}
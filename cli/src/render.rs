@@ -0,0 +1,159 @@
+use crate::run::{grid, Output};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use strip_shared::color::Rgb;
+use strip_shared::led::{LedEnv, TIMER_HZ};
+
+pub struct Preview {
+  lut: [u8; 256],
+}
+
+impl Preview {
+  pub fn new(gamma: f32, brightness: f32) -> Self {
+    let mut lut = [0; 256];
+    for (idx, val) in lut.iter_mut().enumerate() {
+      let linear = (idx as f32 / 255.0).powf(gamma) * brightness;
+      *val = (linear * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    Preview { lut }
+  }
+
+  fn pixels(&self, strip: &LedEnv<Vec<u8>>) -> Vec<Rgb> {
+    strip
+      .output_pixels()
+      .map(|(r, g, b)| {
+        (
+          self.lut[r as usize],
+          self.lut[g as usize],
+          self.lut[b as usize],
+        )
+      })
+      .collect()
+  }
+}
+
+fn to_io_error<E: std::fmt::Display>(err: E) -> io::Error {
+  io::Error::other(err.to_string())
+}
+
+pub struct Animation {
+  file: Option<BufWriter<File>>,
+  encoder: Option<gif::Encoder<BufWriter<File>>>,
+  size: (usize, usize),
+  preview: Preview,
+  width: usize,
+  scale: usize,
+  ticks: u64,
+  delay: u64,
+}
+
+impl Animation {
+  /// Strips without matrix wrap every `width` LEDs, image size is set by the first frame.
+  pub fn create(path: &str, width: usize, scale: usize, preview: Preview) -> io::Result<Self> {
+    Ok(Animation {
+      file: Some(BufWriter::new(File::create(path)?)),
+      encoder: None,
+      size: (0, 0),
+      preview,
+      width: width.max(1),
+      scale,
+      ticks: 0,
+      delay: 0,
+    })
+  }
+}
+
+impl Output for Animation {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, ticks: u32) -> io::Result<()> {
+    let pixels = self.preview.pixels(strip);
+    let rows = grid(strip, &pixels, self.width.min(pixels.len().max(1)));
+    if let Some(file) = self.file.take() {
+      let width = rows.iter().map(|row| row.len()).max().unwrap_or(0).max(1);
+      let height = rows.len().max(1);
+      let (img_width, img_height) = (width * self.scale, height * self.scale);
+      let mut encoder =
+        gif::Encoder::new(file, img_width as u16, img_height as u16, &[]).map_err(to_io_error)?;
+      encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(to_io_error)?;
+      self.encoder = Some(encoder);
+      self.size = (img_width, img_height);
+    }
+    let (img_width, img_height) = self.size;
+    let mut buf = vec![0; img_width * img_height * 3];
+    for (y, row) in rows.iter().enumerate() {
+      for (x, (r, g, b)) in row.iter().enumerate() {
+        for line in (y * self.scale)..((y + 1) * self.scale).min(img_height) {
+          for col in (x * self.scale)..((x + 1) * self.scale).min(img_width) {
+            let offset = (line * img_width + col) * 3;
+            buf[offset..(offset + 3)].copy_from_slice(&[*r, *g, *b]);
+          }
+        }
+      }
+    }
+
+    // GIF delays are in centiseconds, carry the rounding error over to the next frame.
+    self.ticks += ticks as u64;
+    let elapsed = self.ticks * 100 / TIMER_HZ as u64;
+    let mut frame = gif::Frame::from_rgb_speed(img_width as u16, img_height as u16, &buf, 10);
+    frame.delay = (elapsed - self.delay) as u16;
+    self.delay = elapsed;
+    let encoder = self.encoder.as_mut().unwrap();
+    encoder.write_frame(&frame).map_err(to_io_error)
+  }
+}
+
+pub struct Filmstrip {
+  path: String,
+  preview: Preview,
+  scale: usize,
+  leds: usize,
+  rows: Vec<Vec<Rgb>>,
+}
+
+impl Filmstrip {
+  pub fn new(path: &str, leds: usize, scale: usize, preview: Preview) -> Self {
+    Filmstrip {
+      path: path.to_string(),
+      preview,
+      scale,
+      leds,
+      rows: vec![],
+    }
+  }
+}
+
+impl Output for Filmstrip {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, _ticks: u32) -> io::Result<()> {
+    self.rows.push(self.preview.pixels(strip));
+    Ok(())
+  }
+
+  fn finish(&mut self) -> io::Result<()> {
+    let img_width = self.leds * self.scale;
+    let mut data = Vec::with_capacity(img_width * self.rows.len() * self.scale * 3);
+    for row in self.rows.iter() {
+      let mut line = Vec::with_capacity(img_width * 3);
+      for (r, g, b) in row.iter() {
+        for _ in 0..self.scale {
+          line.extend_from_slice(&[*r, *g, *b]);
+        }
+      }
+      for _ in 0..self.scale {
+        data.extend_from_slice(&line);
+      }
+    }
+
+    let file = BufWriter::new(File::create(&self.path)?);
+    let mut encoder = png::Encoder::new(
+      file,
+      img_width as u32,
+      (self.rows.len() * self.scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(to_io_error)?;
+    writer.write_image_data(&data).map_err(to_io_error)
+  }
+}
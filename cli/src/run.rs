@@ -0,0 +1,262 @@
+use crate::audio::AudioFile;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use strip_shared::color::Rgb;
+use strip_shared::compositor::{Blend, Compositor, Layer};
+use strip_shared::input::{Input, NoInput};
+use strip_shared::led::*;
+use strip_shared::transition::Transition;
+use strip_shared::vm::*;
+
+pub const LAYER_HELP: &str = "Runs another program on top as PATH[:BLEND[:OPACITY[:START:LEDS]]], \
+  BLEND is one of normal, add, multiply, max or mask";
+
+/// Program run on top of the main one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerSpec<'a> {
+  pub path: &'a str,
+  pub blend: Blend,
+  pub opacity: u8,
+  pub range: Option<(usize, usize)>,
+}
+
+impl<'a> LayerSpec<'a> {
+  pub fn parse(spec: &'a str) -> Option<Self> {
+    let mut parts = spec.split(':');
+    let path = parts.next()?;
+    let blend = parts.next().map_or(Some(Blend::Normal), Blend::parse)?;
+    let opacity = parts.next().map_or(Ok(255), str::parse).ok()?;
+    let range = match (parts.next(), parts.next()) {
+      (Some(start), Some(leds)) => Some((start.parse().ok()?, leds.parse().ok()?)),
+      (None, None) => None,
+      _ => return None,
+    };
+    if parts.next().is_some() {
+      return None;
+    }
+    Some(LayerSpec {
+      path,
+      blend,
+      opacity,
+      range,
+    })
+  }
+
+  pub fn apply(&self, layer: &mut Layer<Vec<u8>>) {
+    layer.set_blend(self.blend);
+    layer.set_opacity(self.opacity);
+    if let Some((start, leds)) = self.range {
+      layer.set_range(start, leds);
+    }
+  }
+}
+
+/// Output pixels in rows as laid out on the panel, strips without matrix wrap every `width` LEDs.
+pub fn grid(strip: &LedEnv<Vec<u8>>, pixels: &[Rgb], width: usize) -> Vec<Vec<Rgb>> {
+  if !strip.is_matrix() {
+    return pixels
+      .chunks(width.max(1))
+      .map(|row| row.to_vec())
+      .collect();
+  }
+  let (width, height) = strip.matrix().size();
+  (0..height as i32)
+    .map(|y| {
+      (0..width as i32)
+        .map(|x| strip.xy(x, y).map_or((0, 0, 0), |idx| pixels[idx]))
+        .collect()
+    })
+    .collect()
+}
+
+pub trait Output {
+  /// Receives a rendered frame that stays on the strip for `ticks` timer ticks.
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, ticks: u32) -> io::Result<()>;
+
+  fn finish(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Program of a playlist, shown for `frames` frames after switching to it with `transition`.
+pub struct Show<'input> {
+  pub bytecode: &'input [u8],
+  pub frames: u64,
+  pub transition: Transition,
+}
+
+/// Runs programs as layers of the strip, the one it starts with at the bottom.
+pub struct Runner<'input> {
+  strip: LedEnv<Vec<u8>>,
+  layers: Compositor<'input, Vec<u8>>,
+  playlist: Vec<Show<'input>>,
+  /// Playlist program at the bottom and frames it was shown.
+  current: usize,
+  shown: u64,
+  /// Layer the next playlist program loads into.
+  spare: Option<Layer<'input, Vec<u8>>>,
+  outputs: Vec<Box<dyn Output>>,
+  input: Box<dyn Input>,
+  audio: Option<AudioFile>,
+  max_frames: Option<u64>,
+  frames: u64,
+}
+
+impl<'input> Runner<'input> {
+  /// Outputs get `strip`, the program renders into `env` laid out the same.
+  pub fn new(
+    strip: LedEnv<Vec<u8>>,
+    env: LedEnv<Vec<u8>>,
+    max_frames: Option<u64>,
+    bytecode: &'input [u8],
+  ) -> Result<Self, VMError> {
+    let mut layers = Compositor::new();
+    layers.set_layer(0, Some(Layer::new(env, bytecode)?));
+    Ok(Runner {
+      strip,
+      layers,
+      playlist: vec![],
+      current: 0,
+      shown: 0,
+      spare: None,
+      max_frames,
+      outputs: vec![],
+      input: Box::new(NoInput),
+      audio: None,
+      frames: 0,
+    })
+  }
+
+  /// Adds a layer on top, returns false when all layers are taken.
+  pub fn add_layer(&mut self, layer: Layer<'input, Vec<u8>>) -> bool {
+    self.layers.push(layer).is_some()
+  }
+
+  /// Loops over `playlist` at the bottom, starting with the program the runner was made with.
+  /// Incoming programs render into `env` and the layer of the outgoing one in turns.
+  pub fn set_playlist(
+    &mut self,
+    playlist: Vec<Show<'input>>,
+    env: LedEnv<Vec<u8>>,
+  ) -> Result<(), VMError> {
+    self.spare = match playlist.first() {
+      Some(show) => Some(Layer::new(env, show.bytecode)?),
+      None => None,
+    };
+    self.playlist = playlist;
+    self.current = 0;
+    self.shown = 0;
+    Ok(())
+  }
+
+  pub fn set_input(&mut self, input: Box<dyn Input>) {
+    self.input = input;
+  }
+
+  pub fn set_audio(&mut self, audio: AudioFile) {
+    self.audio = Some(audio);
+  }
+
+  pub fn add_output(&mut self, output: Box<dyn Output>) {
+    self.outputs.push(output);
+  }
+
+  /// Runs the program in real time, pacing frames with the timer prescaler.
+  pub fn start(&mut self) -> io::Result<()> {
+    let started = Instant::now();
+    let mut ticks: u64 = 0;
+    self.tick();
+    while !self.is_done() {
+      ticks += self.refresh()? as u64;
+      let deadline = started + Duration::from_micros(ticks * 1_000_000 / TIMER_HZ as u64);
+      let now = Instant::now();
+      if deadline > now {
+        thread::sleep(deadline - now);
+      }
+    }
+    self.finish()
+  }
+
+  /// Runs the program as fast as possible, frame timing is only passed to outputs.
+  pub fn render(&mut self) -> io::Result<()> {
+    self.tick();
+    while !self.is_done() {
+      self.refresh()?;
+    }
+    self.finish()
+  }
+
+  fn is_done(&self) -> bool {
+    match self.max_frames {
+      Some(max_frames) => self.frames >= max_frames,
+      None => false,
+    }
+  }
+
+  fn finish(&mut self) -> io::Result<()> {
+    for output in self.outputs.iter_mut() {
+      output.finish()?;
+    }
+    Ok(())
+  }
+
+  /// Advances the strip clock and all layers by one timer tick, returns whether a frame is due.
+  ///
+  /// The first tick starts the first frame, same as on the device.
+  fn tick(&mut self) -> bool {
+    self.strip.tick();
+    self.layers.tick()
+  }
+
+  /// Switches to the next playlist program once the current one was shown long enough, it
+  /// starts with the next timer tick.
+  fn advance_playlist(&mut self) -> io::Result<()> {
+    let frames = match self.playlist.get(self.current) {
+      Some(show) => show.frames,
+      None => return Ok(()),
+    };
+    self.shown += 1;
+    if self.shown < frames {
+      return Ok(());
+    }
+    self.current = (self.current + 1) % self.playlist.len();
+    self.shown = 0;
+    let (bytecode, transition) = {
+      let show = &self.playlist[self.current];
+      (show.bytecode, show.transition)
+    };
+    // Programs shown shorter than the transition into them are cut short.
+    self.layers.finish_transition();
+    let mut layer = match self.layers.take_outgoing().or_else(|| self.spare.take()) {
+      Some(layer) => layer,
+      None => return Ok(()),
+    };
+    layer.load(bytecode).map_err(|err| {
+      let msg = format!("invalid playlist program: {:?}", err);
+      io::Error::new(io::ErrorKind::InvalidData, msg)
+    })?;
+    self.spare = self.layers.start_transition(0, layer, transition);
+    Ok(())
+  }
+
+  fn refresh(&mut self) -> io::Result<u32> {
+    if let Some(audio) = self.audio.as_mut() {
+      self.layers.set_sound(audio.sound(self.strip.elapsed_ms()));
+    }
+    // Same as the firmware: a faulting spin keeps whatever was drawn so far, repeated faults
+    // fall back to the safe program.
+    self.layers.spin(self.input.as_mut());
+    self.frames += 1;
+    self.layers.compose(&mut self.strip);
+    self.advance_playlist()?;
+    let mut ticks = 1;
+    while !self.tick() {
+      ticks += 1;
+    }
+    for output in self.outputs.iter_mut() {
+      output.frame(&self.strip, ticks)?;
+    }
+    Ok(ticks)
+  }
+}
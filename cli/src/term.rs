@@ -0,0 +1,86 @@
+use crate::run::{grid, Output};
+use std::fmt::Write as _;
+use std::io;
+use std::io::prelude::*;
+use strip_shared::color::Rgb;
+use strip_shared::led::LedEnv;
+
+pub struct Terminal {
+  width: usize,
+  power: bool,
+  lines: usize,
+}
+
+impl Terminal {
+  pub fn new(width: usize, power: bool) -> Self {
+    Terminal {
+      width: width.max(1),
+      power,
+      lines: 0,
+    }
+  }
+
+  fn render_strip(&self, pixels: &[Rgb], out: &mut String) -> usize {
+    let mut lines = 0;
+    for row in pixels.chunks(self.width) {
+      for (r, g, b) in row {
+        write!(out, "\x1b[38;2;{};{};{}m\u{25cf} ", r, g, b).unwrap();
+      }
+      out.push_str("\x1b[0m\n");
+      lines += 1;
+    }
+    lines
+  }
+
+  // Two matrix rows per terminal line: upper half block in the foreground, lower one in the background.
+  fn render_matrix(&self, rows: &[Vec<Rgb>], out: &mut String) -> usize {
+    let mut lines = 0;
+    for pair in rows.chunks(2) {
+      for (x, (r, g, b)) in pair[0].iter().enumerate() {
+        let (br, bg, bb) = pair.get(1).and_then(|row| row.get(x)).unwrap_or(&(0, 0, 0));
+        write!(
+          out,
+          "\x1b[38;2;{};{};{};48;2;{};{};{}m\u{2580}",
+          r, g, b, br, bg, bb
+        )
+        .unwrap();
+      }
+      out.push_str("\x1b[0m\n");
+      lines += 1;
+    }
+    lines
+  }
+}
+
+impl Output for Terminal {
+  fn frame(&mut self, strip: &LedEnv<Vec<u8>>, _ticks: u32) -> io::Result<()> {
+    let mut out = String::new();
+    if self.lines > 0 {
+      write!(out, "\x1b[{}A", self.lines).unwrap();
+    }
+    self.lines = 0;
+    if strip.is_matrix() {
+      let pixels: Vec<Rgb> = strip.output_pixels().collect();
+      self.lines += self.render_matrix(&grid(strip, &pixels, self.width), &mut out);
+    } else {
+      // Every channel starts on a new row.
+      for ch in 0..strip.channels() {
+        let pixels: Vec<Rgb> = strip.channel_output_pixels(ch).collect();
+        self.lines += self.render_strip(&pixels, &mut out);
+      }
+    }
+    if self.power {
+      let power = strip.power();
+      write!(out, "\x1b[2K{} mA", power.ma).unwrap();
+      if power.scale < 255 {
+        write!(out, ", limited to {} mA", power.limited_ma).unwrap();
+      }
+      out.push('\n');
+      self.lines += 1;
+    }
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    handle.write_all(out.as_bytes())?;
+    handle.flush()
+  }
+}
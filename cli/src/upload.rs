@@ -0,0 +1,106 @@
+use serialport::SerialPort;
+use std::io;
+use std::io::prelude::*;
+use std::time::Duration;
+use strip_shared::link::*;
+use strip_shared::upload::{Sender, UploadError, MAX_RETRIES};
+
+fn to_io_error(err: UploadError) -> io::Error {
+  io::Error::other(format!("Upload failed: {:?}", err))
+}
+
+pub fn open(port: &str, baud_rate: u32) -> io::Result<Box<dyn SerialPort>> {
+  let port = serialport::new(port, baud_rate)
+    .timeout(Duration::from_millis(250))
+    .open()?;
+  Ok(port)
+}
+
+pub fn upload<P: Read + Write>(port: &mut P, prog: &[u8]) -> io::Result<()> {
+  let mut sender = Sender::new(prog).map_err(to_io_error)?;
+  let mut decoder = Decoder::new();
+  while let Some(packet) = sender.packet() {
+    let reply = request(port, &mut decoder, &packet, |reply| match reply {
+      Packet::Ack => Some(Packet::Ack),
+      Packet::Nack(reason) => Some(Packet::Nack(reason)),
+      _ => None,
+    })?;
+    sender.reply(reply).map_err(to_io_error)?;
+  }
+  Ok(())
+}
+
+/// Sends a slot or monitor command, retrying until the device acknowledges it.
+pub fn send<P: Read + Write>(port: &mut P, packet: Packet) -> io::Result<()> {
+  query(port, packet, |reply| match reply {
+    Packet::Ack => Some(()),
+    _ => None,
+  })
+}
+
+/// Sends a command, retrying until `accept` takes the reply. Rejected commands fail right away.
+pub fn query<P, T>(
+  port: &mut P,
+  packet: Packet,
+  accept: impl Fn(Packet) -> Option<T>,
+) -> io::Result<T>
+where
+  P: Read + Write,
+{
+  let mut decoder = Decoder::new();
+  for _ in 0..=MAX_RETRIES {
+    let reply = request(port, &mut decoder, &packet, |reply| match reply {
+      Packet::Nack(reason) => Some(Err(reason)),
+      reply => accept(reply).map(Ok),
+    })?;
+    match reply {
+      Some(Ok(val)) => return Ok(val),
+      Some(Err(Nack::BadFrame)) | None => continue,
+      Some(Err(reason)) => {
+        return Err(io::Error::other(format!(
+          "Command {:?} failed: {:?}",
+          packet, reason
+        )))
+      }
+    }
+  }
+  Err(io::Error::other(format!(
+    "Command {:?} failed: {:?}",
+    packet,
+    UploadError::RetriesExceeded
+  )))
+}
+
+/// Writes a packet and waits for the reply `accept` takes, `None` when it timed out, arrived
+/// corrupted or was not taken.
+fn request<P, T>(
+  port: &mut P,
+  decoder: &mut Decoder,
+  packet: &Packet,
+  mut accept: impl FnMut(Packet) -> Option<T>,
+) -> io::Result<Option<T>>
+where
+  P: Read + Write,
+{
+  let mut buf = [0; MAX_FRAME];
+  let len = packet
+    .encode(&mut buf)
+    .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+  port.write_all(&buf[..len])?;
+  port.flush()?;
+
+  decoder.reset();
+  let mut byte = [0];
+  loop {
+    match port.read(&mut byte) {
+      Ok(0) => return Ok(None),
+      Ok(_) => match decoder.feed(byte[0]) {
+        Some(Ok(reply)) => return Ok(accept(reply)),
+        Some(Err(_)) => return Ok(None),
+        None => {}
+      },
+      Err(err) if err.kind() == io::ErrorKind::TimedOut => return Ok(None),
+      Err(err) => return Err(err),
+    }
+  }
+}
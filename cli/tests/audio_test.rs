@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::process::Command;
+
+// About 10 frames per second, LED 0 shows level, beat and the lowest of 4 spectrum bands.
+const PROGRAM: &str = "
+  li t0 102
+  ecall zero SET_PSC(t0)
+  li t0 4
+  ecall zero SET_BANDS(t0)
+  li t9 0x1000
+  ecall t1 LEVEL
+  sb t1 0(t9)
+  ecall t1 BEAT
+  sb t1 1(t9)
+  li t0 0
+  ecall t1 BAND(t0)
+  sb t1 2(t9)
+";
+
+/// Stereo 16-bit WAV with 250 ms of silence, then a loud 100 Hz tone.
+fn wav() -> Vec<u8> {
+  let rate: u32 = 44100;
+  let mut data = vec![];
+  for idx in 0..(rate as usize) {
+    let t = idx as f32 / rate as f32;
+    let val = if t < 0.25 {
+      0
+    } else {
+      ((2.0 * std::f32::consts::PI * 100.0 * t).sin() * 20000.0) as i16
+    };
+    for _ in 0..2 {
+      data.extend_from_slice(&val.to_le_bytes());
+    }
+  }
+  let mut wav = b"RIFF".to_vec();
+  wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+  wav.extend_from_slice(b"WAVEfmt ");
+  wav.extend_from_slice(&16u32.to_le_bytes());
+  for half in [1u16, 2].iter() {
+    wav.extend_from_slice(&half.to_le_bytes());
+  }
+  wav.extend_from_slice(&rate.to_le_bytes());
+  wav.extend_from_slice(&(rate * 4).to_le_bytes());
+  for half in [4u16, 16].iter() {
+    wav.extend_from_slice(&half.to_le_bytes());
+  }
+  wav.extend_from_slice(b"data");
+  wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+  wav.extend_from_slice(&data);
+  wav
+}
+
+#[test]
+fn test_audio_file() {
+  let frames = render_frames(&wav()).unwrap();
+  assert_eq!(frames.len(), 6);
+  for frame in frames[..3].iter() {
+    assert_eq!(*frame, [0, 0, 0]);
+  }
+  let [level, beat, band] = frames[3];
+  assert!(level > 200 && band > 200, "{:?}", frames[3]);
+  assert_eq!(beat, 1);
+  assert_eq!(frames[4][1], 0);
+}
+
+#[test]
+fn test_invalid_audio_file() {
+  assert!(render_frames(b"RIFF\0\0\0\0WAVE").is_none());
+}
+
+fn render_frames(wav: &[u8]) -> Option<Vec<[u8; 3]>> {
+  let dir = std::env::temp_dir();
+  let program = dir.join(format!("strip_audio_test_{}.s", wav.len()));
+  let audio = dir.join(format!("strip_audio_test_{}.wav", wav.len()));
+  let out = dir.join(format!("strip_audio_test_{}.png", wav.len()));
+  std::fs::write(&program, PROGRAM).unwrap();
+  std::fs::write(&audio, wav).unwrap();
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .arg("render")
+    .arg(&program)
+    .args(["--leds", "1", "--frames", "6", "--scale", "1", "--out"])
+    .arg(&out)
+    .arg("--audio")
+    .arg(&audio)
+    .status()
+    .unwrap();
+  if !status.success() {
+    for path in [program, audio].iter() {
+      std::fs::remove_file(path).ok();
+    }
+    return None;
+  }
+
+  let decoder = png::Decoder::new(File::open(&out).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf).unwrap();
+  for path in [program, audio, out].iter() {
+    std::fs::remove_file(path).ok();
+  }
+  Some(
+    buf[..info.buffer_size()]
+      .chunks(info.line_size)
+      .map(|row| [row[0], row[1], row[2]])
+      .collect(),
+  )
+}
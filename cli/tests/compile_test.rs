@@ -0,0 +1,21 @@
+use std::process::Command;
+
+#[test]
+fn test_compile_warnings() {
+  let dir = std::env::temp_dir().join("strip_compile_test");
+  std::fs::create_dir_all(&dir).unwrap();
+  let (src, out) = (dir.join("unknown.s"), dir.join("unknown.bin"));
+  std::fs::write(&src, "ecall zero SET_PSC\necall zero 0x3f\n").unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .arg("compile")
+    .args([&src, &out])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  assert!(out.exists());
+  std::fs::remove_dir_all(&dir).ok();
+  assert_eq!(
+    String::from_utf8(output.stderr).unwrap(),
+    "warning: unknown ecall 0x3f at pc 1\n"
+  );
+}
@@ -0,0 +1,118 @@
+use std::net::UdpSocket;
+use std::process::Command;
+use std::time::Duration;
+
+#[test]
+fn test_e131_universes() {
+  let packets = capture_dmx(&["--frames", "2", "--dmx", "e131"]);
+  assert_eq!(packets.len(), 4);
+  for packet in packets.iter() {
+    assert_eq!(&packet[4..16], b"ASC-E1.17\0\0\0");
+    assert_eq!(packet[125], 0);
+  }
+
+  let universes: Vec<u16> = packets.iter().map(|p| read_u16(p, 113)).collect();
+  assert_eq!(universes, [1, 2, 1, 2]);
+  let sequences: Vec<u8> = packets.iter().map(|p| p[111]).collect();
+  assert_eq!(sequences, [1, 1, 2, 2]);
+  let lengths: Vec<usize> = packets.iter().map(|p| p.len() - 126).collect();
+  assert_eq!(lengths, [510, 390, 510, 390]);
+  let slots: Vec<u16> = packets.iter().map(|p| read_u16(p, 123)).collect();
+  assert_eq!(slots, [511, 391, 511, 391]);
+  assert_eq!(read_u16(&packets[0], 16) & 0x0fff, 510 + 126 - 16);
+  assert_eq!(read_u16(&packets[1], 38) & 0x0fff, 390 + 126 - 38);
+  assert_eq!(read_u16(&packets[1], 115) & 0x0fff, 390 + 126 - 115);
+
+  // Second blinky.s frame lights the last byte of the strip.
+  assert_eq!(lit_bytes(&packets[2][126..]), vec![]);
+  assert_eq!(lit_bytes(&packets[3][126..]), vec![389]);
+}
+
+#[test]
+fn test_artnet_universes() {
+  let packets = capture_dmx(&[
+    "--frames",
+    "2",
+    "--dmx",
+    "artnet",
+    "--dmx-universe",
+    "5",
+    "--dmx-channels",
+    "300",
+  ]);
+  assert_eq!(packets.len(), 6);
+  for packet in packets.iter() {
+    assert_eq!(&packet[..8], b"Art-Net\0");
+    assert_eq!(&packet[8..12], &[0x00, 0x50, 0, 14]);
+    assert_eq!(read_u16(packet, 16), 300);
+    assert_eq!(packet.len(), 318);
+  }
+  let universes: Vec<u8> = packets.iter().map(|p| p[14]).collect();
+  assert_eq!(universes, [5, 6, 7, 5, 6, 7]);
+  assert_eq!(lit_bytes(&packets[5][18..]), vec![299]);
+}
+
+#[test]
+fn test_artnet_padding() {
+  let packets = capture_dmx(&["--frames", "1", "--leds", "3", "--dmx", "artnet"]);
+  assert_eq!(packets.len(), 1);
+  assert_eq!(read_u16(&packets[0], 16), 10);
+  assert_eq!(packets[0].len(), 28);
+}
+
+#[test]
+fn test_universe_range() {
+  for (protocol, universe) in [("e131", "64000"), ("e131", "0"), ("artnet", "70000")].iter() {
+    let output = run_dmx(&["--dmx", protocol, "--dmx-universe", universe]);
+    assert!(!output.status.success());
+  }
+  // The strip spans two universes, the second one is past the last.
+  let output = run_dmx(&["--dmx", "e131", "--dmx-universe", "63999"]);
+  assert!(!output.status.success());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("DMX universe 64000 is out of range"));
+  assert!(!stderr.contains("panicked"));
+}
+
+fn run_dmx(args: &[&str]) -> std::process::Output {
+  Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/blinky.s", "--frames", "1"])
+    .args(["--dmx-dest", "127.0.0.1:9"])
+    .args(args)
+    .output()
+    .unwrap()
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+  u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn lit_bytes(data: &[u8]) -> Vec<usize> {
+  data
+    .iter()
+    .enumerate()
+    .filter(|(_, byte)| **byte > 0)
+    .map(|(idx, _)| idx)
+    .collect()
+}
+
+fn capture_dmx(args: &[&str]) -> Vec<Vec<u8>> {
+  let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+  socket
+    .set_read_timeout(Some(Duration::from_millis(500)))
+    .unwrap();
+  let addr = socket.local_addr().unwrap().to_string();
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/blinky.s", "--dmx-dest", &addr])
+    .args(args)
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let mut packets = vec![];
+  let mut buf = [0; 1024];
+  while let Ok(len) = socket.recv(&mut buf) {
+    packets.push(buf[..len].to_vec());
+  }
+  packets
+}
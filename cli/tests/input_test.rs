@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::process::Command;
+
+// LED 0 shows button 0 held, pressed and analog 0 level, LED 1 red shows encoder 0 steps.
+const PROGRAM: &str = "
+  li t0 0
+  li t9 0x1000
+  ecall t1 BUTTON(t0)
+  sb t1 0(t9)
+  ecall t1 PRESSED(t0)
+  sb t1 1(t9)
+  ecall t1 ANALOG(t0)
+  sb t1 2(t9)
+  ecall t1 ENCODER(t0)
+  sb t1 3(t9)
+";
+
+const SCRIPT: &str = "
+  # frame control index value
+  1 press 0
+  2 analog 0 200
+  2 encoder 0 +3
+  3 release 0
+";
+
+#[test]
+fn test_input_script() {
+  let frames = render_frames(SCRIPT);
+  assert_eq!(
+    frames,
+    vec![
+      vec![[0, 0, 0], [0, 0, 0]],
+      vec![[1, 1, 0], [0, 0, 0]],
+      vec![[1, 0, 200], [3, 0, 0]],
+      vec![[0, 0, 200], [0, 0, 0]],
+    ]
+  );
+}
+
+#[test]
+fn test_invalid_script() {
+  let dir = std::env::temp_dir();
+  let script = dir.join("strip_input_test_invalid.txt");
+  std::fs::write(&script, "1 press\n").unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/blinky.s", "--frames", "1", "--input"])
+    .arg(&script)
+    .output()
+    .unwrap();
+  std::fs::remove_file(&script).ok();
+  assert!(!output.status.success());
+}
+
+fn render_frames(script: &str) -> Vec<Vec<[u8; 3]>> {
+  let dir = std::env::temp_dir();
+  let program = dir.join("strip_input_test.s");
+  let input = dir.join("strip_input_test.txt");
+  let out = dir.join("strip_input_test.png");
+  std::fs::write(&program, PROGRAM).unwrap();
+  std::fs::write(&input, script).unwrap();
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .arg("render")
+    .arg(&program)
+    .args(["--leds", "2", "--frames", "4", "--scale", "1", "--out"])
+    .arg(&out)
+    .arg("--input")
+    .arg(&input)
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let decoder = png::Decoder::new(File::open(&out).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf).unwrap();
+  for path in [program, input, out].iter() {
+    std::fs::remove_file(path).ok();
+  }
+  buf[..info.buffer_size()]
+    .chunks(info.line_size)
+    .map(|row| row.chunks(3).map(|px| [px[0], px[1], px[2]]).collect())
+    .collect()
+}
@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::process::Command;
+
+const BACKGROUND: &str = "
+  li t0 100
+  sb t0 0x1000
+  sb t0 0x1003
+  sb t0 0x1006
+  sb t0 0x1009
+";
+
+// Every other tick, its first LED gets greener by 10.
+const OVERLAY: &str = "
+  li t0 1
+  ecall zero SET_PSC(t0)
+  lb t0 0
+  addi t0 t0 10
+  sb t0 0
+  sb t0 0x1001
+";
+
+#[test]
+fn test_layers() {
+  let dir = std::env::temp_dir();
+  let background = dir.join("strip_layer_test_background.s");
+  let overlay = dir.join("strip_layer_test_overlay.s");
+  let out = dir.join("strip_layer_test.png");
+  std::fs::write(&background, BACKGROUND).unwrap();
+  std::fs::write(&overlay, OVERLAY).unwrap();
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .arg("render")
+    .arg(&background)
+    .args(["--leds", "4", "--frames", "4", "--scale", "1", "--out"])
+    .arg(&out)
+    .arg("--layer")
+    .arg(format!("{}:add:255:2:2", overlay.display()))
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let decoder = png::Decoder::new(File::open(&out).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf).unwrap();
+  for path in [background, overlay, out].iter() {
+    std::fs::remove_file(path).ok();
+  }
+  let frames: Vec<Vec<[u8; 3]>> = buf[..info.buffer_size()]
+    .chunks(info.line_size)
+    .map(|row| row.chunks(3).map(|px| [px[0], px[1], px[2]]).collect())
+    .collect();
+  let frame = |green| vec![[100, 0, 0], [100, 0, 0], [100, green, 0], [100, 0, 0]];
+  assert_eq!(frames, vec![frame(10), frame(10), frame(20), frame(20)]);
+}
+
+#[test]
+fn test_invalid_layer() {
+  let output = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/blinky.s", "--frames", "1"])
+    .args(["--layer", "../docs/blinky.s:lighten"])
+    .output()
+    .unwrap();
+  assert!(!output.status.success());
+}
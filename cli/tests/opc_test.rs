@@ -0,0 +1,71 @@
+use std::io::prelude::*;
+use std::net::TcpListener;
+use std::process::{Child, Command};
+
+#[test]
+fn test_opc_frames() {
+  let messages = capture_opc(&["--frames", "3", "--opc-channel", "2", "--opc-order", "grb"]);
+  assert_eq!(messages.len(), 3);
+  for (channel, command, data) in messages.iter() {
+    assert_eq!(*channel, 2);
+    assert_eq!(*command, 0);
+    assert_eq!(data.len(), 900);
+  }
+  // blinky.s walks a single lit byte down from the strip end, GRB swaps the first two bytes of a pixel.
+  assert_eq!(lit_bytes(&messages[0].2), vec![]);
+  assert_eq!(lit_bytes(&messages[1].2), vec![899]);
+  assert_eq!(lit_bytes(&messages[2].2), vec![897]);
+}
+
+#[test]
+fn test_opc_channels() {
+  let messages = capture_opc(&["--frames", "2", "--leds", "300,4", "--opc-channel", "2"]);
+  let channels: Vec<(u8, usize)> = messages
+    .iter()
+    .map(|(channel, _, data)| (*channel, data.len()))
+    .collect();
+  assert_eq!(channels, vec![(2, 900), (3, 12), (2, 900), (3, 12)]);
+  assert_eq!(lit_bytes(&messages[2].2), vec![899]);
+}
+
+#[test]
+fn test_opc_frame_rate() {
+  let messages = capture_opc(&["--frames", "10", "--opc-fps", "10"]);
+  assert_eq!(messages.len(), 3);
+}
+
+fn lit_bytes(data: &[u8]) -> Vec<usize> {
+  data
+    .iter()
+    .enumerate()
+    .filter(|(_, byte)| **byte > 0)
+    .map(|(idx, _)| idx)
+    .collect()
+}
+
+fn capture_opc(args: &[&str]) -> Vec<(u8, u8, Vec<u8>)> {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap().to_string();
+  let mut child = spawn_run(&addr, args);
+  let (mut stream, _) = listener.accept().unwrap();
+  let mut buf = vec![];
+  stream.read_to_end(&mut buf).unwrap();
+  assert!(child.wait().unwrap().success());
+
+  let mut messages = vec![];
+  let mut rest = &buf[..];
+  while !rest.is_empty() {
+    let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+    messages.push((rest[0], rest[1], rest[4..(len + 4)].to_vec()));
+    rest = &rest[(len + 4)..];
+  }
+  messages
+}
+
+fn spawn_run(addr: &str, args: &[&str]) -> Child {
+  Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/blinky.s", "--opc", addr])
+    .args(args)
+    .spawn()
+    .unwrap()
+}
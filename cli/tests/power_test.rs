@@ -0,0 +1,30 @@
+use std::process::Command;
+
+#[test]
+fn test_power_report() {
+  let report = run_power(&[]);
+  assert_eq!(
+    report,
+    "Power: peak 1438 mA, average 1415 mA, 0 of 10 frames limited\n"
+  );
+
+  // Estimate is taken before limiting, every frame is above the supply limit.
+  let report = run_power(&["--power-limit", "400"]);
+  assert_eq!(
+    report,
+    "Power: peak 1438 mA, average 1415 mA, 10 of 10 frames limited\n"
+  );
+
+  let report = run_power(&["--channel-ma", "40"]);
+  assert!(report.starts_with("Power: peak 2576 mA"), "{}", report);
+}
+
+fn run_power(args: &[&str]) -> String {
+  let output = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["run", "../docs/rainbow.s", "--frames", "10", "--power"])
+    .args(args)
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  String::from_utf8(output.stdout).unwrap()
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+use std::process::Command;
+
+#[test]
+fn test_render_size() {
+  assert_eq!(render_size("row.gif", &["--width", "100"]), (400, 12));
+  // Panels are drawn as seen by the program, after rotation.
+  assert_eq!(
+    render_size("panel.gif", &["--xy", "20x15,serpentine,rot90"]),
+    (60, 80)
+  );
+}
+
+#[test]
+fn test_render_frames() {
+  let gif = render("frames.gif", &["--leds", "10", "--scale", "4"]);
+  let mut decoder = gif::DecodeOptions::new()
+    .read_info(File::open(&gif).unwrap())
+    .unwrap();
+  assert_eq!((decoder.width(), decoder.height()), (40, 4));
+  let mut frames = 0;
+  while decoder.read_next_frame().unwrap().is_some() {
+    frames += 1;
+  }
+  assert_eq!(frames, 2);
+  std::fs::remove_file(&gif).ok();
+
+  // Filmstrips stack frames top to bottom.
+  let png = render("frames.png", &["--leds", "10", "--scale", "2"]);
+  let reader = png::Decoder::new(File::open(&png).unwrap())
+    .read_info()
+    .unwrap();
+  assert_eq!((reader.info().width, reader.info().height), (20, 4));
+  std::fs::remove_file(&png).ok();
+}
+
+fn render(name: &str, args: &[&str]) -> std::path::PathBuf {
+  let path = std::env::temp_dir().join(format!("strip_render_test_{}", name));
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["render", "../docs/rainbow.s", "--frames", "2", "--out"])
+    .arg(&path)
+    .args(args)
+    .status()
+    .unwrap();
+  assert!(status.success());
+  path
+}
+
+fn render_size(name: &str, args: &[&str]) -> (u16, u16) {
+  let path = render(name, args);
+  let gif = std::fs::read(&path).unwrap();
+  std::fs::remove_file(&path).ok();
+  (
+    u16::from_le_bytes([gif[6], gif[7]]),
+    u16::from_le_bytes([gif[8], gif[9]]),
+  )
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::process::Command;
+
+const R: [u8; 3] = [200, 0, 0];
+const G: [u8; 3] = [0, 200, 0];
+const B: [u8; 3] = [0, 0, 200];
+
+// Red, then blue faded in over two frames, then green dissolved in over four. Going back to red
+// wipes with the default transition.
+const PLAYLIST: &str = "
+  # frames program transition
+  3 red.s
+  3 blue.s crossfade:2
+  5 green.s dissolve:4
+";
+
+#[test]
+fn test_playlist_transitions() {
+  let frames = render_playlist(&["--transition", "wipe:4", "--frames", "13"]);
+  let blend = [100, 0, 100];
+  assert_eq!(
+    frames,
+    vec![
+      vec![R, R, R, R],
+      vec![R, R, R, R],
+      vec![R, R, R, R],
+      vec![blend, blend, blend, blend],
+      vec![B, B, B, B],
+      vec![B, B, B, B],
+      vec![B, G, B, B],
+      vec![B, G, B, G],
+      vec![G, G, B, G],
+      vec![G, G, G, G],
+      vec![G, G, G, G],
+      vec![R, G, G, G],
+      vec![R, R, G, G],
+    ]
+  );
+
+  // Every render of the playlist is the same.
+  let frames = render_playlist(&["--transition", "wipe:4", "--frames", "13"]);
+  assert_eq!(
+    frames[6..9],
+    [vec![B, G, B, B], vec![B, G, B, G], vec![G, G, B, G]]
+  );
+}
+
+#[test]
+fn test_cut() {
+  let frames = render_playlist(&["--frames", "13"]);
+  assert_eq!(frames[11], vec![R, R, R, R]);
+}
+
+fn render_playlist(args: &[&str]) -> Vec<Vec<[u8; 3]>> {
+  let dir = std::env::temp_dir().join(format!("strip_transition_test_{}", args.len()));
+  std::fs::create_dir_all(&dir).unwrap();
+  for (name, offset) in [("red.s", 0), ("green.s", 1), ("blue.s", 2)].iter() {
+    let fill: String = (0..4)
+      .map(|led| format!("  sb t0 {}\n", 0x1000 + led * 3 + offset))
+      .collect();
+    std::fs::write(dir.join(name), format!("  li t0 200\n{}", fill)).unwrap();
+  }
+  let playlist = dir.join("playlist.txt");
+  let out = dir.join("frames.png");
+  std::fs::write(&playlist, PLAYLIST).unwrap();
+  let status = Command::new(env!("CARGO_BIN_EXE_strip"))
+    .args(["render", "--leds", "4", "--scale", "1", "--out"])
+    .arg(&out)
+    .arg("--playlist")
+    .arg(&playlist)
+    .args(args)
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let decoder = png::Decoder::new(File::open(&out).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf).unwrap();
+  std::fs::remove_dir_all(&dir).ok();
+  buf[..info.buffer_size()]
+    .chunks(info.line_size)
+    .map(|row| row.chunks(3).map(|px| [px[0], px[1], px[2]]).collect())
+    .collect()
+}
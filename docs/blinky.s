@@ -5,7 +5,6 @@
 
 .equ STRIP_SIZE 900 # 300 leds * 3 color components
 .equ STRIP_BASE 0x1000
-.equ SET_PSC 0x0
 .equ PRESCALER 0x18
 .equ LUMA 0x22
 
@@ -2,14 +2,13 @@
 .alias hue s1
 .alias sv s2
 .alias led s3
+.alias end s4
 
 .equ PRESCALER 24
 .equ MAX_HUE 255
 .equ SV 0xff20
 .equ STRIP_BASE 0x1000
 .equ STRIP_SIZE 300
-.equ HSV2RGB 0x1
-.equ SET_PSC 0x0
 
 bgtz sv start
 li sv SV
@@ -20,6 +19,7 @@ start:
   li led STRIP_SIZE
   muli led led 3
   la led STRIP_BASE(led)
+  li end STRIP_BASE
 
 loop:
   dec hue
@@ -31,4 +31,4 @@ loop:
   sh sv 1(led)
   ecall zero HSV2RGB(led)
 
-  bnez led loop
\ No newline at end of file
+  bne led end loop
\ No newline at end of file
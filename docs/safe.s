@@ -0,0 +1,6 @@
+# Fallback the supervisor loads after repeated faults: a dark strip at 32 fps, the supervisor
+# draws the fault on the first LEDs.
+li t0 31
+ecall zero SET_PSC(t0)
+li t0 1
+ecall zero BUFFER(t0)
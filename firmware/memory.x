@@ -1,6 +1,7 @@
 /* STM32G031J6 */
 MEMORY
 {
+  /* Flash pages after the image hold program storage, see src/flash.rs */
   FLASH : ORIGIN = 0x08000000, LENGTH = 32K
   RAM : ORIGIN = 0x20000000, LENGTH = 8K
-}
\ No newline at end of file
+}
@@ -0,0 +1,154 @@
+use core::ops::Range;
+use hal::stm32::FLASH;
+use strip_shared::storage::FlashStorage;
+
+const FLASH_BASE: usize = 0x0800_0000;
+/// Must match the `FLASH` length in `memory.x`.
+const FLASH_SIZE: usize = 32 * 1024;
+const PAGE_SIZE: usize = 2048;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+extern "C" {
+  // Load address and bounds of `.data`, the last section of the image in flash.
+  static __sidata: u32;
+  static __sdata: u32;
+  static __edata: u32;
+}
+
+/// First flash page after the firmware image.
+fn image_end() -> usize {
+  let (load, start, end) = unsafe {
+    (
+      &__sidata as *const u32 as usize,
+      &__sdata as *const u32 as usize,
+      &__edata as *const u32 as usize,
+    )
+  };
+  (load + end - start).div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
+#[derive(Debug)]
+pub enum FlashError {
+  Alignment,
+  Program,
+}
+
+/// STM32G0 flash controller restricted to the program storage region.
+///
+/// Programs are stored in the flash pages left after the firmware image, so the region grows and
+/// shrinks with the image.
+pub struct Flash {
+  regs: FLASH,
+  base: usize,
+}
+
+impl Flash {
+  pub fn new(regs: FLASH) -> Self {
+    Flash {
+      regs,
+      base: image_end(),
+    }
+  }
+
+  /// Storage region bytes as mapped into the address space, valid until the pages are rewritten.
+  pub fn mapped(&self, range: Range<usize>) -> &'static [u8] {
+    let src = (self.base + range.start) as *const u8;
+    unsafe { core::slice::from_raw_parts(src, range.len()) }
+  }
+
+  fn unlock(&mut self) {
+    if self.regs.cr.read().lock().bit_is_set() {
+      self.regs.keyr.write(|w| unsafe { w.keyr().bits(KEY1) });
+      self.regs.keyr.write(|w| unsafe { w.keyr().bits(KEY2) });
+    }
+  }
+
+  fn lock(&mut self) {
+    self.regs.cr.modify(|_, w| w.lock().set_bit());
+  }
+
+  fn wait(&self) -> Result<(), FlashError> {
+    while self.regs.sr.read().bsy().bit_is_set() {}
+    let sr = self.regs.sr.read();
+    if sr.progerr().bit_is_set()
+      || sr.wrperr().bit_is_set()
+      || sr.pgaerr().bit_is_set()
+      || sr.sizerr().bit_is_set()
+      || sr.pgserr().bit_is_set()
+    {
+      // Error flags are cleared by writing them back.
+      self.regs.sr.write(|w| unsafe { w.bits(sr.bits()) });
+      return Err(FlashError::Program);
+    }
+    Ok(())
+  }
+}
+
+impl FlashStorage for Flash {
+  type Error = FlashError;
+
+  fn page_size(&self) -> usize {
+    PAGE_SIZE
+  }
+
+  fn write_size(&self) -> usize {
+    8
+  }
+
+  fn capacity(&self) -> usize {
+    (FLASH_BASE + FLASH_SIZE).saturating_sub(self.base)
+  }
+
+  fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+    let src = (self.base + offset) as *const u8;
+    let mem = unsafe { core::slice::from_raw_parts(src, buf.len()) };
+    buf.copy_from_slice(mem);
+    Ok(())
+  }
+
+  fn erase(&mut self, offset: usize) -> Result<(), Self::Error> {
+    if offset % PAGE_SIZE != 0 {
+      return Err(FlashError::Alignment);
+    }
+    let page = (self.base - FLASH_BASE + offset) / PAGE_SIZE;
+    self.unlock();
+    let res = self.wait().and_then(|_| {
+      self
+        .regs
+        .cr
+        .modify(|_, w| unsafe { w.per().set_bit().pnb().bits(page as u8) });
+      self.regs.cr.modify(|_, w| w.strt().set_bit());
+      self.wait()
+    });
+    self.regs.cr.modify(|_, w| w.per().clear_bit());
+    self.lock();
+    res
+  }
+
+  fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+    if offset % 8 != 0 || data.len() % 8 != 0 {
+      return Err(FlashError::Alignment);
+    }
+    self.unlock();
+    self.regs.cr.modify(|_, w| w.pg().set_bit());
+    let mut res = Ok(());
+    for (idx, chunk) in data.chunks(8).enumerate() {
+      // Double word is programmed by two consecutive word writes.
+      let addr = (self.base + offset + idx * 8) as *mut u32;
+      let lo = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+      let hi = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+      unsafe {
+        core::ptr::write_volatile(addr, lo);
+        core::ptr::write_volatile(addr.add(1), hi);
+      }
+      res = self.wait();
+      if res.is_err() {
+        break;
+      }
+    }
+    self.regs.cr.modify(|_, w| w.pg().clear_bit());
+    self.lock();
+    res
+  }
+}
@@ -0,0 +1,45 @@
+use hal::analog::adc::Adc;
+use hal::gpio::gpioa::PA1;
+use hal::gpio::gpiob::{PB0, PB1};
+use hal::gpio::{Analog, Input as GpioInput, PullUp};
+use hal::hal::adc::OneShot;
+use hal::hal::digital::v2::InputPin;
+use strip_shared::input::Input;
+
+/// Effect board controls: two buttons to ground and a potentiometer.
+pub struct Panel {
+  buttons: (PB0<GpioInput<PullUp>>, PB1<GpioInput<PullUp>>),
+  adc: Adc,
+  pot: PA1<Analog>,
+}
+
+impl Panel {
+  pub fn new(
+    buttons: (PB0<GpioInput<PullUp>>, PB1<GpioInput<PullUp>>),
+    adc: Adc,
+    pot: PA1<Analog>,
+  ) -> Self {
+    Panel { buttons, adc, pot }
+  }
+}
+
+impl Input for Panel {
+  fn buttons(&mut self) -> u16 {
+    let first = self.buttons.0.is_low().unwrap_or(false) as u16;
+    let second = self.buttons.1.is_low().unwrap_or(false) as u16;
+    first | second << 1
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    if idx != 0 {
+      return 0;
+    }
+    // 12-bit conversion scaled down to 8 bits.
+    let raw: u16 = self.adc.read(&mut self.pot).unwrap_or(0);
+    (raw >> 4) as u8
+  }
+
+  fn encoder(&mut self, _: usize) -> i32 {
+    0
+  }
+}
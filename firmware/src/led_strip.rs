@@ -1,136 +1,282 @@
+#[cfg(feature = "storage")]
+use crate::flash::Flash;
 use hal::hal::spi::FullDuplex;
-use rgb::FromSlice;
-use smart_leds::SmartLedsWrite;
-use strip_shared::vm::*;
-use ws2812_spi::Ws2812;
+use nb::block;
+use strip_shared::driver::*;
+use strip_shared::input::Input;
+use strip_shared::led::*;
+use strip_shared::link::*;
+use strip_shared::output::PowerBudget;
+use strip_shared::runtime::{Output, Runtime};
+#[cfg(feature = "storage")]
+use strip_shared::storage::{Storage, StorageError};
+use strip_shared::supervisor::tick_budget;
+#[cfg(feature = "monitor")]
+use strip_shared::telemetry::Program;
+use strip_shared::upload::Receiver;
 
 const LEDS: usize = 300;
+/// LED RAM per LED, room for the RGB the strip is set up with or for any format programs declare.
+#[cfg(not(feature = "wide-pixels"))]
+const PIXEL_SIZE: usize = 3;
+#[cfg(feature = "wide-pixels")]
+const PIXEL_SIZE: usize = MAX_PIXEL_SIZE;
+/// Strip split in two runs, the first one is driven from SPI2 and the second one from SPI1.
+#[cfg(feature = "split")]
+const CHANNELS: [Channel; 2] = [
+  Channel {
+    leds: LEDS / 2,
+    format: PixelFormat::new(ColorOrder::RGB, Channels::Rgb),
+  },
+  Channel {
+    leds: LEDS - LEDS / 2,
+    format: PixelFormat::new(ColorOrder::RGB, Channels::Rgb),
+  },
+];
+const RAM_SIZE: usize = 1024;
+/// Upload limit, uploaded programs run from a RAM bank of this size.
+const PROG_SIZE: usize = 1024;
+/// One flash page, stored programs run in place from their slot.
+#[cfg(feature = "storage")]
+const SLOT_SIZE: usize = 2048;
+/// Core clock, HSI through the PLL as set up in `init`.
+const CPU_HZ: u32 = 48_000_000;
+/// Supply current available to the strip.
+const SUPPLY_MA: u32 = 5000;
 
-pub struct LedStrip<SPI> {
-  link: Ws2812<SPI>,
-  vm: VM<'static, Environment>,
+const BLINKY: &[u8] = include_bytes!("../../docs/blinky.bin");
+
+#[cfg(not(any(feature = "apa102", feature = "sk9822")))]
+const DRIVER: Driver = Driver::Ws2812(Ws2812::new(ColorOrder::GRB));
+#[cfg(feature = "apa102")]
+const DRIVER: Driver = Driver::Apa102(Apa102::new());
+#[cfg(feature = "sk9822")]
+const DRIVER: Driver = Driver::Apa102(Apa102::sk9822());
+
+/// Whole strip on one SPI bus.
+#[cfg(not(feature = "split"))]
+pub struct SpiOutput<SPI>(pub SPI);
+
+#[cfg(not(feature = "split"))]
+impl<SPI: FullDuplex<u8>> Output for SpiOutput<SPI> {
+  type Error = SPI::Error;
+
+  fn write(&mut self, _: usize, byte: u8) -> Result<(), SPI::Error> {
+    send(&mut self.0, byte)
+  }
 }
 
-impl<SPI> LedStrip<SPI>
+/// Split strip outputs, the first channel goes out on SPI2 and the second one on SPI1.
+#[cfg(feature = "split")]
+pub struct SplitOutput<SPI1, SPI2>(pub SPI2, pub SPI1);
+
+#[cfg(feature = "split")]
+impl<SPI1, SPI2, E> Output for SplitOutput<SPI1, SPI2>
 where
-  SPI: FullDuplex<u8>,
+  SPI1: FullDuplex<u8, Error = E>,
+  SPI2: FullDuplex<u8, Error = E>,
 {
-  pub fn new(spi: SPI) -> LedStrip<SPI> {
-    let link = Ws2812::new(spi);
-    let mut vm = VM::new(Environment {
-      ram: [0; 1024],
-      led_ram: [0; LEDS * 3],
-      ops: 0,
-      psc: 0,
-    });
-    vm.load(include_bytes!("../../docs/blinky.bin")).unwrap();
-    LedStrip { vm, link }
-  }
+  type Error = E;
 
-  pub fn refresh(&mut self) {
-    let env = self.vm.get_env();
-    if env.psc > 0 && env.ops < env.psc {
-      env.ops += 1;
-      return;
+  fn write(&mut self, ch: usize, byte: u8) -> Result<(), E> {
+    match ch {
+      0 => send(&mut self.0, byte),
+      _ => send(&mut self.1, byte),
     }
-    env.ops = 0;
-    self.vm.respin().ok();
-    self
-      .link
-      .write(self.vm.get_env().led_ram.as_rgb().iter().cloned())
-      .ok();
   }
 }
 
-pub enum StripError {
-  MemoryOverread,
+fn send<SPI: FullDuplex<u8>>(spi: &mut SPI, byte: u8) -> Result<(), SPI::Error> {
+  block!(spi.send(byte))?;
+  block!(spi.read()).map(|_| ())
 }
 
-pub struct Environment {
-  ops: u32,
-  psc: u32,
-  ram: [u8; 1024],
-  led_ram: [u8; LEDS * 3],
-}
+type StripRuntime<OUT, IN> = Runtime<'static, &'static mut [u8], OUT, IN>;
 
-impl Env for Environment {
-  type Error = StripError;
+pub struct LedStrip<OUT, IN> {
+  runtime: StripRuntime<OUT, IN>,
+  decoder: Decoder,
+  upload: Receiver<&'static mut [u8]>,
+  #[cfg(feature = "storage")]
+  storage: Option<Storage<Flash>>,
+  #[cfg(feature = "storage")]
+  slot: Option<u8>,
+  bank: &'static mut [u8],
+}
 
-  fn reset(&mut self) {
-    for byte in self.ram.iter_mut() {
-      *byte = 0;
-    }
-    for byte in self.led_ram.iter_mut() {
-      *byte = 0;
+impl<OUT, IN> LedStrip<OUT, IN>
+where
+  OUT: Output,
+  IN: Input,
+{
+  pub fn new(
+    output: OUT,
+    input: IN,
+    #[cfg(feature = "storage")] flash: Flash,
+  ) -> LedStrip<OUT, IN> {
+    let ram = cortex_m::singleton!(: [u8; RAM_SIZE] = [0; RAM_SIZE]).unwrap();
+    let led_ram = cortex_m::singleton!(: [u8; LEDS * PIXEL_SIZE] = [0; LEDS * PIXEL_SIZE]).unwrap();
+    let staging = cortex_m::singleton!(: [u8; PROG_SIZE] = [0; PROG_SIZE]).unwrap();
+    let bank = cortex_m::singleton!(: [u8; PROG_SIZE] = [0; PROG_SIZE]).unwrap();
+    let mut env = LedEnv::new(&mut ram[..], &mut led_ram[..], ColorOrder::RGB);
+    env.output_mut().set_power_budget(PowerBudget {
+      limit_ma: SUPPLY_MA,
+      ..PowerBudget::default()
+    });
+    #[cfg(not(feature = "split"))]
+    env.set_leds(LEDS);
+    #[cfg(feature = "split")]
+    env.set_channels(&CHANNELS).unwrap();
+    #[cfg(feature = "double-buffer")]
+    {
+      let front = cortex_m::singleton!(: [u8; LEDS * PIXEL_SIZE] = [0; LEDS * PIXEL_SIZE]).unwrap();
+      env.set_front(&mut front[..]).unwrap();
     }
+    let mut runtime = Runtime::new(env, DRIVER, output, input);
+    // Spins run in the timer interrupt, a runaway program is stopped within its frame period.
+    runtime.supervisor_mut().tick_budget = Some(tick_budget(CPU_HZ));
+    runtime.load(BLINKY).unwrap();
+    let strip = LedStrip {
+      runtime,
+      decoder: Decoder::new(),
+      upload: Receiver::new(&mut staging[..]),
+      #[cfg(feature = "storage")]
+      storage: Storage::mount(flash, SLOT_SIZE).ok(),
+      #[cfg(feature = "storage")]
+      slot: None,
+      bank: &mut bank[..],
+    };
+    #[cfg(feature = "storage")]
+    let strip = strip.with_default_slot();
+    strip
   }
 
-  fn mem_fetch(&self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
-    let offset = addr as usize;
-    if offset >= 0x1000 {
-      let offset = offset - 0x1000;
-      let end = offset + buf.len();
-      buf.copy_from_slice(&self.led_ram[offset..end]);
-      return Ok(());
-    }
-    let end = offset + buf.len();
-    if end > self.ram.len() {
-      return Err(StripError::MemoryOverread);
-    }
-    buf.copy_from_slice(&self.ram[offset..end]);
-    Ok(())
+  pub fn receive(&mut self, byte: u8) -> Option<Packet<'_>> {
+    let packet = self.decoder.feed(byte)?;
+    let res: Result<(), Nack> = match packet {
+      #[cfg(feature = "storage")]
+      Ok(Packet::Save { slot, name }) => {
+        // Slot pages are about to be rewritten, the running program moves to its RAM copy first.
+        if let (Some(prog), true) = (self.upload.program(), self.slot == Some(slot)) {
+          run_staged(&mut self.runtime, self.bank, prog);
+          self.slot = None;
+        }
+        match (self.storage.as_mut(), self.upload.program()) {
+          (Some(storage), Some(prog)) => storage.store(slot, name, prog).map_err(to_nack),
+          (None, _) => Err(Nack::StorageFailed),
+          (_, None) => Err(Nack::BadState),
+        }
+      }
+      #[cfg(feature = "storage")]
+      Ok(Packet::SetDefault(slot)) => match self.storage.as_mut() {
+        Some(storage) => storage.set_default(slot).map_err(to_nack),
+        None => Err(Nack::StorageFailed),
+      },
+      #[cfg(feature = "storage")]
+      Ok(Packet::Select(slot)) => self.select(slot),
+      #[cfg(feature = "storage")]
+      Ok(Packet::Next) => self.step(Storage::next),
+      #[cfg(feature = "storage")]
+      Ok(Packet::Prev) => self.step(Storage::prev),
+      #[cfg(not(feature = "storage"))]
+      Ok(Packet::Save { .. })
+      | Ok(Packet::SetDefault(_))
+      | Ok(Packet::Select(_))
+      | Ok(Packet::Next)
+      | Ok(Packet::Prev) => Err(Nack::StorageFailed),
+      #[cfg(feature = "monitor")]
+      Ok(packet) => {
+        #[cfg(feature = "storage")]
+        let name = match (self.storage.as_ref(), self.slot) {
+          (Some(storage), Some(slot)) => storage.info(slot).map_or(&[][..], |info| info.name()),
+          _ => &[],
+        };
+        #[cfg(feature = "storage")]
+        let program = Program {
+          slot: self.slot,
+          name,
+        };
+        #[cfg(not(feature = "storage"))]
+        let program = Program {
+          slot: None,
+          name: &[],
+        };
+        if let Some(reply) = self.runtime.handle(packet, program) {
+          return Some(reply);
+        }
+        return Some(self.upload.handle(Ok(packet)));
+      }
+      packet => return Some(self.upload.handle(packet)),
+    };
+    Some(res.err().map_or(Packet::Ack, Packet::Nack))
   }
 
-  fn mem_set(&mut self, addr: u16, val: &[u8]) -> Result<(), Self::Error> {
-    let offset = addr as usize;
-    if offset >= 0x1000 {
-      let offset = offset - 0x1000;
-      let end = offset + val.len();
-      self.led_ram[offset..end].copy_from_slice(val);
-      return Ok(());
-    }
+  /// Renders a frame when one is due, returns whether it did.
+  pub fn refresh(&mut self) -> bool {
+    self.swap_program();
+    self.runtime.refresh()
+  }
+
+  pub fn set_spin_us(&mut self, spin_us: u32) {
+    self.runtime.set_spin_us(spin_us);
+  }
 
-    let end = offset + val.len();
-    if end > self.ram.len() {
-      return Err(StripError::MemoryOverread);
+  #[cfg(feature = "storage")]
+  fn with_default_slot(mut self) -> Self {
+    if let Some(slot) = self.storage.as_ref().and_then(|s| s.default_slot()) {
+      self.select(slot).ok();
     }
-    self.ram[offset..end].copy_from_slice(val);
+    self
+  }
+
+  #[cfg(feature = "storage")]
+  fn step(&mut self, find: fn(&Storage<Flash>, Option<u8>) -> Option<u8>) -> Result<(), Nack> {
+    let slot = self.storage.as_ref().ok_or(Nack::StorageFailed)?;
+    let slot = find(slot, self.slot).ok_or(Nack::BadSlot)?;
+    self.select(slot)
+  }
+
+  /// Switches to the program in the slot with a hard cut, there is no RAM to run a transition.
+  #[cfg(feature = "storage")]
+  fn select(&mut self, slot: u8) -> Result<(), Nack> {
+    let storage = self.storage.as_ref().ok_or(Nack::StorageFailed)?;
+    let range = storage.locate(slot).map_err(to_nack)?;
+    let prog = storage.flash().mapped(range);
+    self.runtime.load(prog).map_err(|_| Nack::BadProgram)?;
+    self.slot = Some(slot);
     Ok(())
   }
 
-  fn ecall(&mut self, ecall: u8, param: i32) -> Result<i32, Self::Error> {
-    match ecall {
-      0 => {
-        self.psc = param as u32;
+  fn swap_program(&mut self) {
+    if let Some(size) = self.upload.take() {
+      run_staged(&mut self.runtime, self.bank, &self.upload.buffer()[..size]);
+      #[cfg(feature = "storage")]
+      {
+        self.slot = None;
       }
-      1 => {
-        let offset = param as usize - 0x1000;
-        hsv2grb(&mut self.led_ram[offset..(offset + 3)]);
-      }
-      _ => {}
     }
-    Ok(0)
   }
 }
 
-fn hsv2grb(buf: &mut [u8]) {
-  let hue = buf[0];
-  let sat = buf[1] as u16;
-  let val = buf[2] as u16;
-  let f = (hue as u16 * 2 % 85) * 3;
-  let p: u16 = val * (255 - sat) / 255;
-  let q: u16 = val * (255 - (sat * f) / 255) / 255;
-  let t: u16 = val * (255 - (sat * (255 - f)) / 255) / 255;
-  let rgb = match hue {
-    0..=42 => (val, t, p),
-    43..=84 => (q, val, p),
-    85..=127 => (p, val, t),
-    128..=169 => (p, q, val),
-    170..=212 => (t, p, val),
-    213..=254 => (val, p, q),
-    255 => (t, val, p),
-  };
-  buf[0] = rgb.0 as u8;
-  buf[1] = rgb.1 as u8;
-  buf[2] = rgb.2 as u8;
+/// Copies the staged program into the bank and runs it.
+fn run_staged<O: Output, IN: Input>(
+  runtime: &mut StripRuntime<O, IN>,
+  bank: &mut [u8],
+  staged: &[u8],
+) {
+  // Runtime lets go of the bank before it is overwritten.
+  runtime.load(BLINKY).ok();
+  bank[..staged.len()].copy_from_slice(staged);
+  // The bank is written only above, while the runtime does not hold it.
+  let prog = unsafe { &*(&bank[..staged.len()] as *const [u8]) };
+  runtime.load(prog).ok();
+}
+
+#[cfg(feature = "storage")]
+fn to_nack<E>(err: StorageError<E>) -> Nack {
+  match err {
+    StorageError::InvalidSlot | StorageError::Empty => Nack::BadSlot,
+    StorageError::TooLarge => Nack::TooLarge,
+    StorageError::Flash(_) | StorageError::Corrupt => Nack::StorageFailed,
+  }
 }
@@ -2,30 +2,74 @@
 #![no_main]
 #![deny(warnings)]
 
+#[cfg(not(feature = "semihosting"))]
+extern crate panic_halt;
+#[cfg(feature = "semihosting")]
 extern crate panic_semihosting;
 extern crate rtfm;
 extern crate stm32g0xx_hal as hal;
 
+#[cfg(feature = "storage")]
+mod flash;
+mod input;
 mod led_strip;
 
+#[cfg(feature = "storage")]
+use flash::Flash;
+use hal::analog::adc::AdcExt;
 use hal::gpio::*;
 use hal::prelude::*;
 use hal::rcc::{self, PllConfig};
+use hal::serial::{self, Config};
 use hal::spi;
 use hal::stm32;
-use hal::time::Hertz;
 use hal::timer;
-use led_strip::LedStrip;
+use input::Panel;
+use led_strip::*;
+use nb::block;
 use rtfm::app;
+use strip_shared::led::TIMER_HZ;
+use strip_shared::link::MAX_FRAME;
 
 type AnimationTimer = timer::Timer<stm32::TIM17>;
+type SpinStopwatch = timer::stopwatch::Stopwatch<stm32::TIM2>;
+#[cfg(not(any(feature = "apa102", feature = "sk9822")))]
 type SPIBus = spi::Spi<stm32::SPI2, (spi::NoSck, spi::NoMiso, gpioa::PA10<Input<Floating>>)>;
+#[cfg(any(feature = "apa102", feature = "sk9822"))]
+type SPIBus = spi::Spi<
+  stm32::SPI2,
+  (
+    gpioa::PA0<Input<Floating>>,
+    spi::NoMiso,
+    gpioa::PA10<Input<Floating>>,
+  ),
+>;
+#[cfg(all(feature = "split", not(any(feature = "apa102", feature = "sk9822"))))]
+type SplitBus = spi::Spi<stm32::SPI1, (spi::NoSck, spi::NoMiso, gpioa::PA7<Input<Floating>>)>;
+#[cfg(all(feature = "split", any(feature = "apa102", feature = "sk9822")))]
+type SplitBus = spi::Spi<
+  stm32::SPI1,
+  (
+    gpioa::PA5<Input<Floating>>,
+    spi::NoMiso,
+    gpioa::PA7<Input<Floating>>,
+  ),
+>;
+#[cfg(not(feature = "split"))]
+type StripOutput = SpiOutput<SPIBus>;
+#[cfg(feature = "split")]
+type StripOutput = SplitOutput<SplitBus, SPIBus>;
+type SerialRx = serial::Rx<stm32::USART2>;
+type SerialTx = serial::Tx<stm32::USART2>;
 
 #[app(device = hal::stm32, peripherals = true)]
 const APP: () = {
   struct Resources {
-    strip: LedStrip<SPIBus>,
+    strip: LedStrip<StripOutput, Panel>,
     timer: AnimationTimer,
+    stopwatch: SpinStopwatch,
+    rx: SerialRx,
+    tx: SerialTx,
   }
 
   #[init]
@@ -35,32 +79,90 @@ const APP: () = {
     let mut rcc = ctx.device.RCC.freeze(rcc_cfg);
 
     let mut timer = ctx.device.TIM17.timer(&mut rcc);
-    timer.start(1024.hz());
+    timer.start(TIMER_HZ.hz());
     timer.listen();
 
     let port_a = ctx.device.GPIOA.split(&mut rcc);
-    let spi = ctx.device.SPI2.spi(
-      (spi::NoSck, spi::NoMiso, port_a.pa10),
-      spi::MODE_0,
-      3.mhz(),
-      &mut rcc,
+    // Clocked strips take SCK from PA0, WS2812 only needs MOSI.
+    #[cfg(not(any(feature = "apa102", feature = "sk9822")))]
+    let pins = (spi::NoSck, spi::NoMiso, port_a.pa10);
+    #[cfg(any(feature = "apa102", feature = "sk9822"))]
+    let pins = (port_a.pa0, spi::NoMiso, port_a.pa10);
+    let spi = ctx.device.SPI2.spi(pins, spi::MODE_0, 3.mhz(), &mut rcc);
+    #[cfg(not(feature = "split"))]
+    let output = SpiOutput(spi);
+    // The second half of a split strip goes out on PA7, clocked from PA5.
+    #[cfg(all(feature = "split", not(any(feature = "apa102", feature = "sk9822"))))]
+    let pins = (spi::NoSck, spi::NoMiso, port_a.pa7);
+    #[cfg(all(feature = "split", any(feature = "apa102", feature = "sk9822")))]
+    let pins = (port_a.pa5, spi::NoMiso, port_a.pa7);
+    #[cfg(feature = "split")]
+    let output = SplitOutput(
+      spi,
+      ctx.device.SPI1.spi(pins, spi::MODE_0, 3.mhz(), &mut rcc),
     );
 
-    let mut strip = LedStrip::new(spi);
-    let stopwatch = ctx.device.TIM2.stopwatch(&mut rcc);
-    let elapsed_us = stopwatch.trace(|| {
-      strip.refresh();
-    });
-    let max_fps: Hertz = elapsed_us.into();
-    cortex_m_semihosting::hprintln!(">>> spin: {}us, max fps: {}Hz", elapsed_us.0, max_fps.0)
+    let mut usart = ctx
+      .device
+      .USART2
+      .usart(
+        port_a.pa2,
+        port_a.pa3,
+        Config::default().baudrate(115_200.bps()),
+        &mut rcc,
+      )
       .unwrap();
+    usart.listen(serial::Event::Rxne);
+    let (tx, rx) = usart.split();
+
+    let port_b = ctx.device.GPIOB.split(&mut rcc);
+    let buttons = (
+      port_b.pb0.into_pull_up_input(),
+      port_b.pb1.into_pull_up_input(),
+    );
+    let adc = ctx.device.ADC.constrain(&mut rcc);
+    let panel = Panel::new(buttons, adc, port_a.pa1.into_analog());
 
-    init::LateResources { timer, strip }
+    #[cfg(feature = "storage")]
+    let strip = LedStrip::new(output, panel, Flash::new(ctx.device.FLASH));
+    #[cfg(not(feature = "storage"))]
+    let strip = LedStrip::new(output, panel);
+    // Frame refresh time is reported over the link.
+    let stopwatch = ctx.device.TIM2.stopwatch(&mut rcc);
+
+    init::LateResources {
+      timer,
+      stopwatch,
+      strip,
+      rx,
+      tx,
+    }
   }
 
-  #[task(binds = TIM17, resources = [timer, strip])]
+  #[task(binds = TIM17, resources = [timer, stopwatch, strip])]
   fn timer_tick(ctx: timer_tick::Context) {
-    ctx.resources.strip.refresh();
+    let strip = ctx.resources.strip;
+    let mut rendered = false;
+    let elapsed_us = ctx.resources.stopwatch.trace(|| rendered = strip.refresh());
+    if rendered {
+      strip.set_spin_us(elapsed_us.0);
+    }
     ctx.resources.timer.clear_irq();
   }
+
+  #[task(binds = USART2, resources = [strip, rx, tx])]
+  fn serial_rx(ctx: serial_rx::Context) {
+    let mut buf = [0; MAX_FRAME];
+    while let Ok(byte) = ctx.resources.rx.read() {
+      let reply = match ctx.resources.strip.receive(byte) {
+        Some(reply) => reply,
+        None => continue,
+      };
+      if let Ok(len) = reply.encode(&mut buf) {
+        for byte in buf[..len].iter() {
+          block!(ctx.resources.tx.write(*byte)).ok();
+        }
+      }
+    }
+  }
 };
@@ -0,0 +1,227 @@
+/// Samples per spectrum, bands span from zero to half the sample rate.
+pub const FFT_SIZE: usize = 256;
+/// Spectrum bands of the analyzer, programs may merge them into fewer.
+pub const BANDS: usize = 16;
+/// Frames of bass energy a beat is compared against.
+const HISTORY: usize = 32;
+/// Frames after a beat before the next one may start.
+const BEAT_HOLD: u8 = 8;
+/// Bits of the largest band magnitude, a full scale sine in the middle of a bin.
+const BAND_BITS: u32 = 13;
+/// Bits of the largest level, a full scale square wave.
+const LEVEL_BITS: u32 = 15;
+/// Bass energy below which no beat is detected.
+const BEAT_FLOOR: u32 = 64;
+
+/// Sound of a frame as programs read it.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Sound {
+  /// Loudness of the samples since the previous frame, 0 to 255 on a log scale.
+  pub level: u8,
+  /// Whether a beat started in this frame.
+  pub beat: bool,
+  /// Band magnitudes from low to high frequencies, 0 to 255 on a log scale.
+  pub bands: [u8; BANDS],
+}
+
+/// Fixed point spectrum analyzer fed with 16-bit mono samples.
+///
+/// Samples are kept in a ring of `FFT_SIZE`, every frame they go through a Hann window and
+/// radix-2 FFT with Q15 twiddles. Bins are grouped into log spaced bands. Beats are bass energy
+/// peaks well above the average of the last frames.
+pub struct Analyzer {
+  samples: [i16; FFT_SIZE],
+  pos: usize,
+  fresh: usize,
+  window: [i16; FFT_SIZE],
+  twiddles: [(i16, i16); FFT_SIZE / 2],
+  edges: [usize; BANDS + 1],
+  history: [u32; HISTORY],
+  frame: usize,
+  hold: u8,
+}
+
+impl Analyzer {
+  pub fn new() -> Self {
+    let mut analyzer = Analyzer {
+      samples: [0; FFT_SIZE],
+      pos: 0,
+      fresh: 0,
+      window: [0; FFT_SIZE],
+      twiddles: [(0, 0); FFT_SIZE / 2],
+      edges: [0; BANDS + 1],
+      history: [0; HISTORY],
+      frame: 0,
+      hold: 0,
+    };
+    let turn = 2.0 * core::f32::consts::PI / FFT_SIZE as f32;
+    for (idx, val) in analyzer.window.iter_mut().enumerate() {
+      *val = (0.5 * (1.0 - libm::cosf(turn * idx as f32)) * 32767.0) as i16;
+    }
+    for (idx, val) in analyzer.twiddles.iter_mut().enumerate() {
+      let angle = turn * idx as f32;
+      *val = (
+        libm::roundf(libm::cosf(angle) * 32767.0) as i16,
+        libm::roundf(-libm::sinf(angle) * 32767.0) as i16,
+      );
+    }
+    // Bin 0 is the DC offset, bands start one bin above and grow by the same ratio.
+    let bins = (FFT_SIZE / 2) as f32;
+    for (band, edge) in analyzer.edges.iter_mut().enumerate() {
+      *edge = libm::roundf(libm::powf(bins, band as f32 / BANDS as f32)) as usize;
+    }
+    for band in 1..=BANDS {
+      let edge = analyzer.edges[band].max(analyzer.edges[band - 1] + 1);
+      analyzer.edges[band] = edge;
+    }
+    analyzer.edges[BANDS] = FFT_SIZE / 2;
+    analyzer
+  }
+
+  pub fn feed(&mut self, samples: &[i16]) {
+    for sample in samples {
+      self.samples[self.pos] = *sample;
+      self.pos = (self.pos + 1) % FFT_SIZE;
+    }
+    self.fresh = (self.fresh + samples.len()).min(FFT_SIZE);
+  }
+
+  /// Analyzes the latest samples, called once per frame.
+  pub fn analyze(&mut self) -> Sound {
+    let mut sound = Sound {
+      level: self.level(),
+      ..Sound::default()
+    };
+    self.fresh = 0;
+
+    let mut re = [0; FFT_SIZE];
+    let mut im = [0; FFT_SIZE];
+    for (idx, val) in re.iter_mut().enumerate() {
+      let sample = self.samples[(self.pos + idx) % FFT_SIZE] as i32;
+      *val = (sample * self.window[idx] as i32) >> 15;
+    }
+    fft(&mut re, &mut im, &self.twiddles);
+
+    let mut bass = 0;
+    for (band, val) in sound.bands.iter_mut().enumerate() {
+      let peak = (self.edges[band]..self.edges[band + 1])
+        .map(|bin| isqrt((re[bin] as i64).pow(2) as u64 + (im[bin] as i64).pow(2) as u64))
+        .max()
+        .unwrap_or(0);
+      if band < 3 {
+        bass += peak;
+      }
+      *val = log_scale(peak, BAND_BITS);
+    }
+    sound.beat = self.detect_beat(bass);
+    sound
+  }
+
+  fn level(&self) -> u8 {
+    if self.fresh == 0 {
+      return 0;
+    }
+    let squares: u64 = (0..self.fresh)
+      .map(|idx| {
+        let sample = self.samples[(self.pos + FFT_SIZE - 1 - idx) % FFT_SIZE] as i64;
+        (sample * sample) as u64
+      })
+      .sum();
+    log_scale(isqrt(squares / self.fresh as u64), LEVEL_BITS)
+  }
+
+  fn detect_beat(&mut self, energy: u32) -> bool {
+    let average = self.history.iter().sum::<u32>() / HISTORY as u32;
+    self.history[self.frame % HISTORY] = energy;
+    self.frame += 1;
+    if self.hold > 0 {
+      self.hold -= 1;
+      return false;
+    }
+    let beat = energy > BEAT_FLOOR && energy * 2 > average * 3;
+    if beat {
+      self.hold = BEAT_HOLD;
+    }
+    beat
+  }
+}
+
+impl Default for Analyzer {
+  fn default() -> Self {
+    Analyzer::new()
+  }
+}
+
+/// In place radix-2 FFT halving values every stage, so results are scaled down by the size.
+pub fn fft(re: &mut [i32], im: &mut [i32], twiddles: &[(i16, i16)]) {
+  let len = re.len();
+  let mut rev = 0;
+  for idx in 1..len {
+    let mut bit = len >> 1;
+    while rev & bit != 0 {
+      rev ^= bit;
+      bit >>= 1;
+    }
+    rev |= bit;
+    if idx < rev {
+      re.swap(idx, rev);
+      im.swap(idx, rev);
+    }
+  }
+
+  let mut size = 2;
+  while size <= len {
+    let step = twiddles.len() * 2 / size;
+    for start in (0..len).step_by(size) {
+      for k in 0..(size / 2) {
+        let (cos, sin) = twiddles[k * step];
+        let (a, b) = (start + k, start + k + size / 2);
+        let tr = q15(re[b] as i64 * cos as i64 - im[b] as i64 * sin as i64);
+        let ti = q15(re[b] as i64 * sin as i64 + im[b] as i64 * cos as i64);
+        re[b] = (re[a] - tr) >> 1;
+        im[b] = (im[a] - ti) >> 1;
+        re[a] = (re[a] + tr) >> 1;
+        im[a] = (im[a] + ti) >> 1;
+      }
+    }
+    size <<= 1;
+  }
+}
+
+/// Rounds a product with a Q15 factor back to an integer.
+fn q15(val: i64) -> i32 {
+  ((val + (1 << 14)) >> 15) as i32
+}
+
+fn isqrt(val: u64) -> u32 {
+  let mut root = 0u64;
+  let mut bit = 1u64 << 62;
+  let mut rem = val;
+  while bit > rem {
+    bit >>= 2;
+  }
+  while bit != 0 {
+    if rem >= root + bit {
+      rem -= root + bit;
+      root = (root >> 1) + bit;
+    } else {
+      root >>= 1;
+    }
+    bit >>= 2;
+  }
+  root as u32
+}
+
+/// Maps `val` of up to `bits` bits to 0-255 by its log2 with 4 fractional bits.
+fn log_scale(val: u32, bits: u32) -> u8 {
+  if val == 0 {
+    return 0;
+  }
+  let exp = 31 - val.leading_zeros();
+  let frac = if exp >= 4 {
+    (val >> (exp - 4)) & 0xf
+  } else {
+    (val << (4 - exp)) & 0xf
+  };
+  ((exp * 16 + frac + 1) * 255 / (bits * 16 + 1)).min(255) as u8
+}
@@ -0,0 +1,196 @@
+/// RGB color, also used for HSV and HSL triplets.
+pub type Rgb = (u8, u8, u8);
+
+/// Blackbody colors from 1000K to 12000K in 500K steps.
+const KELVIN_TABLE: [Rgb; 23] = [
+  (255, 56, 0),
+  (255, 109, 0),
+  (255, 137, 18),
+  (255, 161, 72),
+  (255, 180, 107),
+  (255, 196, 137),
+  (255, 209, 163),
+  (255, 219, 186),
+  (255, 228, 206),
+  (255, 236, 224),
+  (255, 243, 239),
+  (255, 249, 253),
+  (245, 243, 255),
+  (235, 238, 255),
+  (227, 233, 255),
+  (220, 229, 255),
+  (214, 225, 255),
+  (208, 222, 255),
+  (204, 219, 255),
+  (200, 217, 255),
+  (196, 215, 255),
+  (193, 213, 255),
+  (191, 211, 255),
+];
+
+pub const MIN_KELVIN: u16 = 1000;
+pub const MAX_KELVIN: u16 = 12000;
+
+/// Original firmware conversion behind the `HSV2RGB` ecall, kept bit exact for old programs.
+pub fn hsv2rgb(hue: u8, sat: u8, val: u8) -> Rgb {
+  let sat = sat as u16;
+  let val = val as u16;
+  let f = (hue as u16 * 2 % 85) * 3;
+  let p: u16 = val * (255 - sat) / 255;
+  let q: u16 = val * (255 - (sat * f) / 255) / 255;
+  let t: u16 = val * (255 - (sat * (255 - f)) / 255) / 255;
+  let rgb = match hue {
+    0..=42 => (val, t, p),
+    43..=84 => (q, val, p),
+    85..=127 => (p, val, t),
+    128..=169 => (p, q, val),
+    170..=212 => (t, p, val),
+    213..=254 => (val, p, q),
+    255 => (t, val, p),
+  };
+  (rgb.0 as u8, rgb.1 as u8, rgb.2 as u8)
+}
+
+/// Mathematically even HSV wheel, six sections of equal width.
+pub fn spectrum(hue: u8, sat: u8, val: u8) -> Rgb {
+  if sat == 0 {
+    return (val, val, val);
+  }
+  let (sat, val) = (sat as u32, val as u32);
+  let region = hue / 43;
+  let rem = (hue as u32 - region as u32 * 43) * 6;
+  let p = (val * (255 - sat) / 255) as u8;
+  let q = (val * (255 - sat * rem / 255) / 255) as u8;
+  let t = (val * (255 - sat * (255 - rem) / 255) / 255) as u8;
+  let val = val as u8;
+  match region {
+    0 => (val, t, p),
+    1 => (q, val, p),
+    2 => (p, val, t),
+    3 => (p, q, val),
+    4 => (t, p, val),
+    _ => (val, p, q),
+  }
+}
+
+/// Visually even HSV wheel with wider yellow and orange, eight sections of 32 hues.
+pub fn rainbow(hue: u8, sat: u8, val: u8) -> Rgb {
+  let offset = (hue & 0x1f) << 3;
+  let third = scale8(offset, 85);
+  let two_thirds = scale8(offset, 170);
+  let (r, g, b) = match hue >> 5 {
+    0 => (255 - third, third, 0),
+    1 => (171, 85 + third, 0),
+    2 => (171 - two_thirds, 170 + third, 0),
+    3 => (0, 255 - third, third),
+    4 => (0, 171 - two_thirds, 85 + two_thirds),
+    5 => (third, 0, 255 - third),
+    6 => (85 + third, 0, 171 - third),
+    _ => (170 + third, 0, 85 - third),
+  };
+  let desat = |c: u8| scale8(c, sat) + (255 - sat);
+  dim((desat(r), desat(g), desat(b)), val)
+}
+
+/// Inverse of `spectrum`.
+pub fn rgb2hsv(rgb: Rgb) -> Rgb {
+  let (r, g, b) = (rgb.0 as i32, rgb.1 as i32, rgb.2 as i32);
+  let max = r.max(g).max(b);
+  let min = r.min(g).min(b);
+  let delta = max - min;
+  if delta == 0 {
+    return (0, 0, max as u8);
+  }
+  let sat = (255 * delta / max) as u8;
+  let hue = if max == r {
+    43 * (g - b) / delta
+  } else if max == g {
+    86 + 43 * (b - r) / delta
+  } else {
+    172 + 43 * (r - g) / delta
+  };
+  // Six sections of 43 hues span 258, the reds past 255 are clamped.
+  (hue.rem_euclid(258).min(255) as u8, sat, max as u8)
+}
+
+pub fn hsl2rgb(hue: u8, sat: u8, light: u8) -> Rgb {
+  let (sat, light) = (sat as u32, light as u32);
+  let val = if light < 128 {
+    light * (255 + sat) / 255
+  } else {
+    light + sat - light * sat / 255
+  };
+  if val == 0 {
+    return (0, 0, 0);
+  }
+  let hsv_sat = 2 * (val - light) * 255 / val;
+  spectrum(hue, hsv_sat.min(255) as u8, val.min(255) as u8)
+}
+
+pub fn rgb2hsl(rgb: Rgb) -> Rgb {
+  let (hue, sat, val) = rgb2hsv(rgb);
+  let (sat, val) = (sat as u32, val as u32);
+  let light = val * (510 - sat) / 510;
+  let hsl_sat = match light {
+    0 | 255 => 0,
+    light => (val - light) * 255 / light.min(255 - light),
+  };
+  (hue, hsl_sat.min(255) as u8, light as u8)
+}
+
+/// Scales value by `scale / 256`, with 255 keeping the value as is.
+pub fn scale8(val: u8, scale: u8) -> u8 {
+  ((val as u16 * (scale as u16 + 1)) >> 8) as u8
+}
+
+/// Scales every channel with `scale8`.
+pub fn dim(rgb: Rgb, scale: u8) -> Rgb {
+  (
+    scale8(rgb.0, scale),
+    scale8(rgb.1, scale),
+    scale8(rgb.2, scale),
+  )
+}
+
+/// Additive blend, saturates at full brightness.
+pub fn add(a: Rgb, b: Rgb) -> Rgb {
+  (
+    a.0.saturating_add(b.0),
+    a.1.saturating_add(b.1),
+    a.2.saturating_add(b.2),
+  )
+}
+
+/// Interpolates between `a` and `b`, fraction 0 gives `a` and 255 gives `b`.
+pub fn lerp8(a: u8, b: u8, frac: u8) -> u8 {
+  let frac = frac as u16;
+  ((a as u16 * (255 - frac) + b as u16 * frac + 127) / 255) as u8
+}
+
+/// Alpha blend of `src` over `dst`, amount 0 keeps `dst` and 255 gives `src`.
+pub fn blend(dst: Rgb, src: Rgb, amount: u8) -> Rgb {
+  (
+    lerp8(dst.0, src.0, amount),
+    lerp8(dst.1, src.1, amount),
+    lerp8(dst.2, src.2, amount),
+  )
+}
+
+/// Blackbody color, clamped to `MIN_KELVIN..=MAX_KELVIN`.
+pub fn kelvin(temp: u16) -> Rgb {
+  let temp = temp.clamp(MIN_KELVIN, MAX_KELVIN) - MIN_KELVIN;
+  let idx = (temp / 500) as usize;
+  if idx + 1 == KELVIN_TABLE.len() {
+    return KELVIN_TABLE[idx];
+  }
+  let frac = ((temp % 500) as u32 * 255 / 500) as u8;
+  blend(KELVIN_TABLE[idx], KELVIN_TABLE[idx + 1], frac)
+}
+
+pub fn pack_rgb(rgb: Rgb) -> i32 {
+  (rgb.0 as i32) << 16 | (rgb.1 as i32) << 8 | rgb.2 as i32
+}
+
+pub fn unpack_rgb(color: i32) -> Rgb {
+  ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
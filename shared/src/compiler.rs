@@ -2,17 +2,47 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 
+use crate::ecall::{self, EcallDef};
+use crate::led::{ECALLS, STRIP_BASE};
 use crate::parser::*;
+use crate::pixel::PixelFormat;
+use crate::vm::{MAGIC, MAGIC_FORMAT, MAGIC_PLAIN};
 use crate::*;
 use byteorder::{BigEndian, ByteOrder};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+  UnknownEcall { pc: usize, number: u8 },
+}
+
+impl core::fmt::Display for Warning {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    match self {
+      Warning::UnknownEcall { pc, number } => {
+        write!(f, "warning: unknown ecall 0x{:x} at pc {}", number, pc)
+      }
+    }
+  }
+}
+
+/// Compiles program against the LED strip ecalls, use `compile_with` to get the warnings.
 pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
+  compile_with(exprs, ECALLS).map(|(prog, _)| prog)
+}
+
+/// Compiles program with ecall names from the registry predefined as constants, along with
+/// `STRIP_BASE` and `PIXEL_SIZE` of the declared pixel format.
+pub fn compile_with(
+  exprs: &[Exp],
+  registry: &'static [EcallDef],
+) -> Result<(Vec<u8>, Vec<Warning>), Error> {
   let mut aliases: HashMap<&str, Reg> = HashMap::new();
   let mut consts: HashMap<&str, i16> = HashMap::new();
   let mut labels: HashMap<&str, i16> = HashMap::new();
   let mut words: Vec<&Word> = Vec::with_capacity(2048);
   let mut mem: Vec<u8> = Vec::with_capacity(4096);
   let mut prog_started = false;
+  let mut format = None;
 
   for exp in exprs {
     match exp {
@@ -34,9 +64,7 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
             aliases.insert(ident, *reg);
           }
           Directive::Zero(size) => {
-            for _ in 0..*size {
-              mem.push(0);
-            }
+            mem.resize(mem.len() + *size as usize, 0);
           }
           Directive::Byte(data) => {
             mem.extend(data);
@@ -55,9 +83,14 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
               mem.extend(&buf);
             }
           }
+          Directive::Format(name) => {
+            let parsed =
+              PixelFormat::parse(name).ok_or(Error::CompilerError(CompilerError::UnknownFormat))?;
+            format = Some(parsed);
+          }
           Directive::IncBin(file) => {
-            let mut file = File::open(file)
-              .map_err(|_| Error::CompilerError(CompilerError::FileReadFailed))?;
+            let mut file =
+              File::open(file).map_err(|_| Error::CompilerError(CompilerError::FileReadFailed))?;
             let mut buf = Vec::new();
             file
               .read_to_end(&mut buf)
@@ -70,7 +103,7 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
   }
 
   if words.is_empty() {
-    return Ok(vec![]);
+    return Ok((vec![], vec![]));
   }
 
   let resolve_reg = |reg_link| match reg_link {
@@ -82,13 +115,18 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
   };
 
   let mut prog: Vec<u8> = Vec::with_capacity(4096);
-  prog.extend(&[0xaf, 0xaf]);
+  match format {
+    Some(format) => prog.extend(&[MAGIC, MAGIC_FORMAT, format.to_byte()]),
+    None => prog.extend(&[MAGIC, MAGIC_PLAIN]),
+  }
+  let pixel_size = format.map_or(3, |format| format.size()) as i16;
 
   let mut buf = [0; 2];
   BigEndian::write_u16(&mut buf, mem.len() as u16);
   prog.extend(&buf);
   prog.extend(&mem);
 
+  let mut warnings = vec![];
   let mut buf = [0; 4];
   for (pc, word) in words.iter().enumerate() {
     let (r3, imm) = if let Some(imm) = &word.imm {
@@ -100,6 +138,12 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
           val += offset;
         } else if let Some(offset) = labels.get(ident) {
           val += offset;
+        } else if let Some(def) = ecall::find(registry, ident) {
+          val += def.number as i16;
+        } else if ident == "STRIP_BASE" {
+          val += STRIP_BASE as i16;
+        } else if ident == "PIXEL_SIZE" {
+          val += pixel_size;
         } else {
           return Err(Error::CompilerError(CompilerError::AliasNotFound));
         }
@@ -108,6 +152,12 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
     } else {
       (word.r3, 0)
     };
+    if let Opcode::ecall = word.opcode {
+      let number = imm as u8;
+      if ecall::lookup(registry, number).is_none() {
+        warnings.push(Warning::UnknownEcall { pc, number });
+      }
+    }
 
     let inst = Instruction::new(
       word.opcode,
@@ -120,5 +170,5 @@ pub fn compile(exprs: &[Exp]) -> Result<Vec<u8>, Error> {
     prog.extend(&buf);
   }
 
-  Ok(prog)
+  Ok((prog, warnings))
 }
@@ -0,0 +1,378 @@
+use crate::audio::Sound;
+use crate::color::{add, blend, dim, scale8, Rgb};
+use crate::input::{Controls, Input};
+use crate::led::LedEnv;
+use crate::supervisor::Supervisor;
+use crate::transition::Transition;
+use crate::vm::{VMError, VM};
+
+/// Programs that can run on a strip at once.
+pub const MAX_LAYERS: usize = 4;
+
+/// How a layer combines with the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+  /// Covers the layers below.
+  Normal,
+  /// Adds to the layers below, saturating at full brightness.
+  Add,
+  /// Multiplies with the layers below, black layer pixels darken them.
+  Multiply,
+  /// Brightest of the layer and the layers below, per channel.
+  Max,
+  /// Draws nothing itself, the brightest channel of a layer pixel scales the layers below.
+  AlphaMask,
+}
+
+impl Blend {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "normal" => Some(Blend::Normal),
+      "add" => Some(Blend::Add),
+      "multiply" => Some(Blend::Multiply),
+      "max" => Some(Blend::Max),
+      "mask" => Some(Blend::AlphaMask),
+      _ => None,
+    }
+  }
+
+  /// Combines layer pixel `top` with `bottom`, opacity 0 keeps `bottom` as is.
+  pub fn apply(self, bottom: Rgb, top: Rgb, opacity: u8) -> Rgb {
+    let mixed = match self {
+      Blend::Normal => top,
+      Blend::Add => add(bottom, top),
+      Blend::Multiply => (
+        scale8(bottom.0, top.0),
+        scale8(bottom.1, top.1),
+        scale8(bottom.2, top.2),
+      ),
+      Blend::Max => (
+        bottom.0.max(top.0),
+        bottom.1.max(top.1),
+        bottom.2.max(top.2),
+      ),
+      Blend::AlphaMask => dim(bottom, top.0.max(top.1).max(top.2)),
+    };
+    blend(bottom, mixed, opacity)
+  }
+}
+
+/// Program rendering into its own LED RAM, shown on a range of the strip.
+///
+/// The layer environment is sized to the range, its prescaler paces the layer independently of
+/// the others. Gamma and brightness set by the program apply to the layer before blending.
+pub struct Layer<'prog, B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  vm: VM<'prog, LedEnv<B>>,
+  supervisor: Supervisor,
+  blend: Blend,
+  opacity: u8,
+  start: usize,
+  due: bool,
+}
+
+impl<'prog, B> Layer<'prog, B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  /// Opaque layer over the start of the strip, as long as `env` has LEDs.
+  pub fn new(env: LedEnv<B>, prog: &'prog [u8]) -> Result<Self, VMError> {
+    let mut vm = VM::new(env);
+    vm.load(prog)?;
+    Ok(Layer {
+      vm,
+      supervisor: Supervisor::default(),
+      blend: Blend::Normal,
+      opacity: 255,
+      start: 0,
+      due: false,
+    })
+  }
+
+  /// Replaces the program, it starts from the next frame.
+  pub fn load(&mut self, prog: &'prog [u8]) -> Result<(), VMError> {
+    self.vm.load(prog)?;
+    self.supervisor.reset();
+    Ok(())
+  }
+
+  pub fn blend(&self) -> Blend {
+    self.blend
+  }
+
+  pub fn set_blend(&mut self, blend: Blend) {
+    self.blend = blend;
+  }
+
+  pub fn opacity(&self) -> u8 {
+    self.opacity
+  }
+
+  pub fn set_opacity(&mut self, opacity: u8) {
+    self.opacity = opacity;
+  }
+
+  /// First strip LED the layer covers.
+  pub fn start(&self) -> usize {
+    self.start
+  }
+
+  /// Moves the layer to `leds` LEDs from `start` on, clamped to its LED RAM.
+  pub fn set_range(&mut self, start: usize, leds: usize) {
+    self.start = start;
+    self.vm.get_env().set_leds(leds);
+  }
+
+  /// Overrides the prescaler until the program sets its own.
+  pub fn set_prescaler(&mut self, psc: u32) {
+    self.vm.get_env().set_prescaler(psc);
+  }
+
+  pub fn env(&mut self) -> &mut LedEnv<B> {
+    self.vm.get_env()
+  }
+
+  pub fn supervisor(&self) -> &Supervisor {
+    &self.supervisor
+  }
+
+  /// Pixels of the presented frame through the layer's gamma and brightness, in strip order.
+  fn draw(&mut self) -> Draw<impl Iterator<Item = Rgb> + '_> {
+    let (start, blend, opacity) = (self.start, self.blend, self.opacity);
+    let env = self.vm.get_env();
+    let output = env.output();
+    Draw {
+      start,
+      blend,
+      opacity,
+      pixels: env.presented_pixels().map(move |rgb| output.apply(rgb)),
+    }
+  }
+}
+
+struct Draw<I> {
+  start: usize,
+  blend: Blend,
+  opacity: u8,
+  pixels: I,
+}
+
+impl<I: Iterator<Item = Rgb>> Draw<I> {
+  /// Blends the layer over strip LED `idx`, called for every LED in order.
+  fn over(&mut self, bottom: Rgb, idx: usize) -> Rgb {
+    if idx < self.start {
+      return bottom;
+    }
+    self
+      .pixels
+      .next()
+      .map_or(bottom, |top| self.blend.apply(bottom, top, self.opacity))
+  }
+}
+
+/// Transition running on a layer, `step` counts its frames shown so far.
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+  layer: usize,
+  transition: Transition,
+  step: u16,
+}
+
+/// Runs up to `MAX_LAYERS` programs at once and blends them into one strip, bottom layer first.
+///
+/// Every timer tick goes to all layers, those with a frame due are spun on the next `spin`.
+/// Controls are sampled once per spin and shared, edges stay relative to each layer's own
+/// previous frame.
+///
+/// A layer replaced with a transition keeps running in place until the incoming one fully shows,
+/// then it is parked for reuse, so switching programs does not take another set of buffers.
+pub struct Compositor<'prog, B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  layers: [Option<Layer<'prog, B>>; MAX_LAYERS],
+  outgoing: Option<Layer<'prog, B>>,
+  fade: Option<Fade>,
+  controls: Controls,
+}
+
+impl<'prog, B> Compositor<'prog, B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  pub fn new() -> Self {
+    Compositor {
+      layers: [None, None, None, None],
+      outgoing: None,
+      fade: None,
+      controls: Controls::default(),
+    }
+  }
+
+  /// Puts a layer at `idx`, higher ones are drawn on top. Returns the layer it replaced.
+  pub fn set_layer(
+    &mut self,
+    idx: usize,
+    layer: Option<Layer<'prog, B>>,
+  ) -> Option<Layer<'prog, B>> {
+    core::mem::replace(&mut self.layers[idx], layer)
+  }
+
+  /// Puts a layer on top of all others and returns its index, `None` when every one is taken.
+  pub fn push(&mut self, layer: Layer<'prog, B>) -> Option<usize> {
+    let idx = self
+      .layers
+      .iter()
+      .rposition(Option::is_some)
+      .map_or(0, |idx| idx + 1);
+    *self.layers.get_mut(idx)? = Some(layer);
+    Some(idx)
+  }
+
+  pub fn layer(&self, idx: usize) -> Option<&Layer<'prog, B>> {
+    self.layers.get(idx)?.as_ref()
+  }
+
+  pub fn layer_mut(&mut self, idx: usize) -> Option<&mut Layer<'prog, B>> {
+    self.layers.get_mut(idx)?.as_mut()
+  }
+
+  /// Replaces layer `idx` with `layer`, the current one fades out over `transition`.
+  ///
+  /// A transition still running is cut short. Returns the layer it faded out, or the one parked
+  /// by an earlier transition, its buffers are free to load the next program.
+  pub fn start_transition(
+    &mut self,
+    idx: usize,
+    layer: Layer<'prog, B>,
+    transition: Transition,
+  ) -> Option<Layer<'prog, B>> {
+    let done = self.outgoing.take();
+    self.outgoing = self.layers[idx].replace(layer);
+    self.fade = match self.outgoing {
+      Some(_) if transition.frames > 0 => Some(Fade {
+        layer: idx,
+        transition,
+        step: 0,
+      }),
+      _ => None,
+    };
+    done
+  }
+
+  /// Layer index and frames left of the running transition.
+  pub fn transition(&self) -> Option<(usize, u16)> {
+    self
+      .fade
+      .map(|fade| (fade.layer, fade.transition.frames - fade.step))
+  }
+
+  /// Shows the incoming layer of a running transition in full from now on.
+  pub fn finish_transition(&mut self) {
+    self.fade = None;
+  }
+
+  /// Takes the layer a finished transition replaced.
+  pub fn take_outgoing(&mut self) -> Option<Layer<'prog, B>> {
+    match self.fade {
+      Some(_) => None,
+      None => self.outgoing.take(),
+    }
+  }
+
+  /// Layers drawn on the strip, including one fading out.
+  fn running(&mut self) -> impl Iterator<Item = &mut Layer<'prog, B>> + '_ {
+    let fading = self.fade.is_some();
+    let outgoing = self.outgoing.iter_mut().filter(move |_| fading);
+    self.layers.iter_mut().flatten().chain(outgoing)
+  }
+
+  /// Advances every layer's prescaler by one tick, returns whether any layer has a frame due.
+  pub fn tick(&mut self) -> bool {
+    let mut due = false;
+    for layer in self.running() {
+      layer.due |= layer.vm.get_env().tick();
+      due |= layer.due;
+    }
+    due
+  }
+
+  /// Spins the layers with a frame due, returns whether any did.
+  ///
+  /// Every spin is a strip frame and moves a running transition on by one.
+  pub fn spin<I: Input + ?Sized>(&mut self, input: &mut I) -> bool {
+    if !self.running().any(|layer| layer.due) {
+      return false;
+    }
+    if let Some(fade) = self.fade.as_mut() {
+      fade.step += 1;
+      if fade.step > fade.transition.frames {
+        self.fade = None;
+      }
+    }
+    self.controls.sample(input);
+    let controls = self.controls;
+    for layer in self.running().filter(|layer| layer.due) {
+      layer.due = false;
+      let mut controls = controls;
+      layer.vm.get_env().sample_input(&mut controls);
+      // Same as a single program, a faulting layer keeps what it drew and falls back on repeats.
+      layer.supervisor.spin(&mut layer.vm).ok();
+      layer.vm.get_env().finish_frame();
+    }
+    true
+  }
+
+  /// Sets the sound every layer reads in its next frame.
+  pub fn set_sound(&mut self, sound: Sound) {
+    for layer in self.running() {
+      layer.vm.get_env().set_sound(sound);
+    }
+  }
+
+  /// Blends presented frames of all layers into LED RAM of `strip` and presents it.
+  ///
+  /// The strip is black where no layer covers it, its own pipeline applies on output.
+  pub fn compose<S>(&mut self, strip: &mut LedEnv<S>)
+  where
+    S: AsRef<[u8]> + AsMut<[u8]>,
+  {
+    let leds = strip.leds();
+    for idx in 0..leds {
+      strip.set_pixel(idx, (0, 0, 0));
+    }
+    let fade = self.fade;
+    for (slot, layer) in self.layers.iter_mut().enumerate() {
+      let mut draw = match layer {
+        Some(layer) => layer.draw(),
+        None => continue,
+      };
+      // The outgoing program is drawn in place of the incoming one, where it still shows.
+      let mut outgoing = match (fade, self.outgoing.as_mut()) {
+        (Some(fade), Some(layer)) if fade.layer == slot => Some((fade, layer.draw())),
+        _ => None,
+      };
+      for idx in 0..leds {
+        let bottom = strip.pixel(idx);
+        let mut rgb = draw.over(bottom, idx);
+        if let Some((fade, outgoing)) = outgoing.as_mut() {
+          let amount = fade.transition.amount(fade.step, idx, leds);
+          rgb = blend(outgoing.over(bottom, idx), rgb, amount);
+        }
+        strip.set_pixel(idx, rgb);
+      }
+    }
+    strip.finish_frame();
+  }
+}
+
+impl<'prog, B> Default for Compositor<'prog, B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  fn default() -> Self {
+    Compositor::new()
+  }
+}
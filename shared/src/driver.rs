@@ -0,0 +1,175 @@
+use crate::color::Rgb;
+use crate::pixel::{widen, Channels, ColorOrder, Levels, PixelFormat, MAX_PIXEL_SIZE};
+
+/// SPI patterns of two WS2812 data bits, every bit takes 4 SPI bits at 3 MHz.
+const WS2812_PATTERNS: [u8; 4] = [0b1000_1000, 0b1000_1110, 0b1110_1000, 0b1110_1110];
+/// Zero bytes holding the line low long enough to latch a WS2812 frame.
+pub const WS2812_RESET_LEN: usize = 20;
+
+/// Largest APA102 global brightness.
+pub const MAX_GLOBAL_BRIGHTNESS: u8 = 0x1f;
+
+/// Turns output pixels into the byte stream of a strip chipset.
+pub trait Encoder {
+  /// Streams a frame of pixels with `channels` through `write`, stops at the first write error.
+  fn encode_levels<I, W, E>(&self, channels: Channels, pixels: I, write: W) -> Result<(), E>
+  where
+    I: ExactSizeIterator<Item = Levels>,
+    W: FnMut(u8) -> Result<(), E>;
+
+  /// Streams a frame of 8-bit RGB pixels.
+  fn encode<I, W, E>(&self, pixels: I, write: W) -> Result<(), E>
+  where
+    I: ExactSizeIterator<Item = Rgb>,
+    W: FnMut(u8) -> Result<(), E>,
+  {
+    self.encode_levels(Channels::Rgb, pixels.map(widen), write)
+  }
+}
+
+/// WS2812 and compatible one-wire chipsets driven from the MOSI line of a 3 MHz SPI bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ws2812 {
+  pub order: ColorOrder,
+}
+
+impl Ws2812 {
+  pub const fn new(order: ColorOrder) -> Self {
+    Ws2812 { order }
+  }
+}
+
+impl Default for Ws2812 {
+  fn default() -> Self {
+    Ws2812::new(ColorOrder::GRB)
+  }
+}
+
+impl Encoder for Ws2812 {
+  /// Sends every channel of the pixel, white last as SK6812 RGBW expects.
+  fn encode_levels<I, W, E>(&self, channels: Channels, pixels: I, mut write: W) -> Result<(), E>
+  where
+    I: ExactSizeIterator<Item = Levels>,
+    W: FnMut(u8) -> Result<(), E>,
+  {
+    let format = PixelFormat::new(self.order, channels);
+    let mut buf = [0; MAX_PIXEL_SIZE];
+    for levels in pixels {
+      format.encode_levels(levels, &mut buf);
+      for byte in buf[..format.size()].iter() {
+        for shift in [6, 4, 2, 0].iter() {
+          write(WS2812_PATTERNS[((byte >> shift) & 0b11) as usize])?;
+        }
+      }
+    }
+    for _ in 0..WS2812_RESET_LEN {
+      write(0)?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EndFrame {
+  /// APA102: ones for half a clock per LED, at least 32 of them.
+  Apa102,
+  /// SK9822: 32 zero bits of reset frame, then zeros for half a clock per LED.
+  Sk9822,
+}
+
+/// APA102 and SK9822 clocked chipsets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Apa102 {
+  pub order: ColorOrder,
+  /// 5-bit global brightness sent with every pixel.
+  pub brightness: u8,
+  pub end: EndFrame,
+}
+
+impl Apa102 {
+  pub const fn new() -> Self {
+    Apa102 {
+      order: ColorOrder::BGR,
+      brightness: MAX_GLOBAL_BRIGHTNESS,
+      end: EndFrame::Apa102,
+    }
+  }
+
+  pub const fn sk9822() -> Self {
+    Apa102 {
+      order: ColorOrder::BGR,
+      brightness: MAX_GLOBAL_BRIGHTNESS,
+      end: EndFrame::Sk9822,
+    }
+  }
+}
+
+impl Default for Apa102 {
+  fn default() -> Self {
+    Apa102::new()
+  }
+}
+
+impl Encoder for Apa102 {
+  /// Sends 8-bit RGB only, white is added to the colors and 16-bit levels lose their low byte.
+  fn encode_levels<I, W, E>(&self, _: Channels, pixels: I, mut write: W) -> Result<(), E>
+  where
+    I: ExactSizeIterator<Item = Levels>,
+    W: FnMut(u8) -> Result<(), E>,
+  {
+    // Data is shifted on by one LED every clock edge, half a bit per LED lags behind.
+    let lag = pixels.len().div_ceil(16);
+    for _ in 0..4 {
+      write(0)?;
+    }
+    let global = 0xe0 | self.brightness.min(MAX_GLOBAL_BRIGHTNESS);
+    for [r, g, b, w] in pixels {
+      write(global)?;
+      let level = |val: u16| ((val >> 8) as u8).saturating_add((w >> 8) as u8);
+      let wire = self.order.permute((level(r), level(g), level(b)));
+      for byte in wire.iter() {
+        write(*byte)?;
+      }
+    }
+    let (fill, len) = match self.end {
+      EndFrame::Apa102 => (0xff, lag.max(4)),
+      EndFrame::Sk9822 => (0, 4 + lag),
+    };
+    for _ in 0..len {
+      write(fill)?;
+    }
+    Ok(())
+  }
+}
+
+/// Strip driver chosen by configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Driver {
+  Ws2812(Ws2812),
+  Apa102(Apa102),
+}
+
+impl Driver {
+  /// Parses chipset name, each with its usual color order.
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "ws2812" | "WS2812" => Some(Driver::Ws2812(Ws2812::default())),
+      "apa102" | "APA102" => Some(Driver::Apa102(Apa102::new())),
+      "sk9822" | "SK9822" => Some(Driver::Apa102(Apa102::sk9822())),
+      _ => None,
+    }
+  }
+}
+
+impl Encoder for Driver {
+  fn encode_levels<I, W, E>(&self, channels: Channels, pixels: I, write: W) -> Result<(), E>
+  where
+    I: ExactSizeIterator<Item = Levels>,
+    W: FnMut(u8) -> Result<(), E>,
+  {
+    match self {
+      Driver::Ws2812(driver) => driver.encode_levels(channels, pixels, write),
+      Driver::Apa102(driver) => driver.encode_levels(channels, pixels, write),
+    }
+  }
+}
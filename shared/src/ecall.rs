@@ -0,0 +1,76 @@
+use crate::vm::Args;
+use core::fmt;
+
+/// Highest ecall number a dispatcher can bind plus one.
+pub const MAX_ECALLS: usize = 64;
+
+/// How an ecall reads the register passed in parentheses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Param {
+  None,
+  Value,
+  /// LED RAM address of the first pixel the ecall works on.
+  Address,
+  /// Reads given number of argument registers starting at `a0`.
+  Args(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EcallDef {
+  pub name: &'static str,
+  pub number: u8,
+  pub param: Param,
+  /// Whether the ecall writes a result into the destination register.
+  pub returns: bool,
+  /// Number of extra results left in argument registers starting at `a0`.
+  pub results: u8,
+  pub help: &'static str,
+}
+
+pub fn find(registry: &'static [EcallDef], name: &str) -> Option<&'static EcallDef> {
+  registry.iter().find(|def| def.name == name)
+}
+
+pub fn lookup(registry: &'static [EcallDef], number: u8) -> Option<&'static EcallDef> {
+  registry.iter().find(|def| def.number == number)
+}
+
+pub type Handler<E, Err> = fn(&mut E, i32, &mut Args) -> Result<i32, Err>;
+
+/// Ecall table of an environment, handlers are bound by registry name.
+pub struct Dispatcher<E, Err> {
+  registry: &'static [EcallDef],
+  handlers: [Option<Handler<E, Err>>; MAX_ECALLS],
+}
+
+impl<E, Err> Dispatcher<E, Err> {
+  /// Panics on names missing from the registry, that is a bug in the environment.
+  pub fn new(registry: &'static [EcallDef], bindings: &[(&str, Handler<E, Err>)]) -> Self {
+    let mut handlers = [None; MAX_ECALLS];
+    for (name, handler) in bindings {
+      let def = find(registry, name).expect("ecall is not in registry");
+      handlers[def.number as usize] = Some(*handler);
+    }
+    Dispatcher { registry, handlers }
+  }
+
+  pub fn registry(&self) -> &'static [EcallDef] {
+    self.registry
+  }
+
+  pub fn handler(&self, number: u8) -> Option<Handler<E, Err>> {
+    self.handlers.get(number as usize).copied().flatten()
+  }
+}
+
+impl fmt::Display for EcallDef {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.param {
+      Param::None => write!(f, "{}", self.name),
+      Param::Value => write!(f, "{}(n)", self.name),
+      Param::Address => write!(f, "{}(addr)", self.name),
+      Param::Args(1) => write!(f, "{}(a0)", self.name),
+      Param::Args(n) => write!(f, "{}(a0..a{})", self.name, n - 1),
+    }
+  }
+}
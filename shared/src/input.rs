@@ -0,0 +1,98 @@
+/// Buttons programs can read, one bit each.
+pub const MAX_BUTTONS: usize = 16;
+pub const MAX_ANALOG: usize = 4;
+pub const MAX_ENCODERS: usize = 2;
+
+/// Controls of the device, implemented over GPIO and ADC by the firmware and over keyboard and
+/// scripts by the simulator.
+pub trait Input {
+  /// Called once per frame before the controls are read.
+  fn poll(&mut self) {}
+
+  /// Bit mask of buttons held down.
+  fn buttons(&mut self) -> u16;
+
+  /// Level of analog input from 0 to 255.
+  fn analog(&mut self, idx: usize) -> u8;
+
+  /// Encoder steps since the previous call, clockwise is positive.
+  fn encoder(&mut self, idx: usize) -> i32;
+}
+
+/// Device without controls.
+pub struct NoInput;
+
+impl Input for NoInput {
+  fn buttons(&mut self) -> u16 {
+    0
+  }
+
+  fn analog(&mut self, _: usize) -> u8 {
+    0
+  }
+
+  fn encoder(&mut self, _: usize) -> i32 {
+    0
+  }
+}
+
+/// Controls as sampled for the current frame, edges are relative to the previous one.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Controls {
+  held: u16,
+  prev: u16,
+  analog: [u8; MAX_ANALOG],
+  encoders: [i32; MAX_ENCODERS],
+}
+
+impl Controls {
+  pub fn sample<I: Input + ?Sized>(&mut self, input: &mut I) {
+    input.poll();
+    self.prev = self.held;
+    self.held = input.buttons();
+    for (idx, val) in self.analog.iter_mut().enumerate() {
+      *val = input.analog(idx);
+    }
+    for (idx, val) in self.encoders.iter_mut().enumerate() {
+      *val = input.encoder(idx);
+    }
+  }
+
+  pub fn held(&self, button: usize) -> bool {
+    self.held >> button & 1 == 1
+  }
+
+  /// Whether the button went down since the previous frame.
+  pub fn pressed(&self, button: usize) -> bool {
+    (self.held & !self.prev) >> button & 1 == 1
+  }
+
+  /// Whether the button went up since the previous frame.
+  pub fn released(&self, button: usize) -> bool {
+    (!self.held & self.prev) >> button & 1 == 1
+  }
+
+  pub fn analog(&self, idx: usize) -> u8 {
+    self.analog[idx]
+  }
+
+  /// Encoder steps since the previous frame.
+  pub fn encoder(&self, idx: usize) -> i32 {
+    self.encoders[idx]
+  }
+}
+
+/// Replays sampled controls, to share one sample between several programs.
+impl Input for Controls {
+  fn buttons(&mut self) -> u16 {
+    self.held
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    self.analog[idx]
+  }
+
+  fn encoder(&mut self, idx: usize) -> i32 {
+    self.encoders[idx]
+  }
+}
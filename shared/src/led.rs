@@ -0,0 +1,1222 @@
+use crate::audio::{Sound, BANDS};
+use crate::color::*;
+use crate::ecall::{Dispatcher, EcallDef, Param};
+use crate::input::*;
+pub use crate::matrix::{Matrix, Wiring};
+use crate::output::{Pipeline, Power};
+pub use crate::pixel::{Channels, ColorOrder, Levels, PixelFormat, MAX_PIXEL_SIZE};
+use crate::vm::{Args, Env};
+use core::convert::TryFrom;
+use core::ops::Range;
+
+pub const STRIP_BASE: u16 = 0x1000;
+pub const TIMER_HZ: u32 = 1024;
+
+pub const SET_PSC: u8 = 0x0;
+pub const HSV2RGB: u8 = 0x1;
+pub const FRAME: u8 = 0x2;
+pub const ELAPSED_MS: u8 = 0x3;
+pub const DELTA_MS: u8 = 0x4;
+pub const RAND: u8 = 0x5;
+pub const SEED: u8 = 0x6;
+pub const SCALE8: u8 = 0x7;
+pub const FILL: u8 = 0x8;
+pub const BLEND: u8 = 0x9;
+pub const PIXEL: u8 = 0xa;
+pub const RAINBOW: u8 = 0xb;
+pub const SPECTRUM: u8 = 0xc;
+pub const RGB2HSV: u8 = 0xd;
+pub const HSL2RGB: u8 = 0xe;
+pub const RGB2HSL: u8 = 0xf;
+pub const DIM: u8 = 0x10;
+pub const ADD: u8 = 0x11;
+pub const MIX: u8 = 0x12;
+pub const GRADIENT: u8 = 0x13;
+pub const KELVIN: u8 = 0x14;
+pub const GAMMA: u8 = 0x15;
+pub const BRIGHTNESS: u8 = 0x16;
+pub const DITHER: u8 = 0x17;
+pub const POWER: u8 = 0x18;
+pub const CHANNEL: u8 = 0x19;
+pub const MATRIX: u8 = 0x1a;
+pub const XY_LUT: u8 = 0x1b;
+pub const XY: u8 = 0x1c;
+pub const SET_XY: u8 = 0x1d;
+pub const GET_XY: u8 = 0x1e;
+pub const MATRIX_SIZE: u8 = 0x1f;
+pub const BUFFER: u8 = 0x20;
+pub const PRESENT: u8 = 0x21;
+pub const BUTTON: u8 = 0x22;
+pub const PRESSED: u8 = 0x23;
+pub const RELEASED: u8 = 0x24;
+pub const ANALOG: u8 = 0x25;
+pub const ENCODER: u8 = 0x26;
+pub const LEVEL: u8 = 0x27;
+pub const BEAT: u8 = 0x28;
+pub const SET_BANDS: u8 = 0x29;
+pub const BAND: u8 = 0x2a;
+
+pub const ECALLS: &[EcallDef] = &[
+  EcallDef {
+    name: "SET_PSC",
+    number: SET_PSC,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Run every n+1 timer ticks",
+  },
+  EcallDef {
+    name: "HSV2RGB",
+    number: HSV2RGB,
+    param: Param::Address,
+    returns: false,
+    results: 0,
+    help: "Convert HSV pixel to RGB in place",
+  },
+  EcallDef {
+    name: "FRAME",
+    number: FRAME,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "Frame number since program start",
+  },
+  EcallDef {
+    name: "ELAPSED_MS",
+    number: ELAPSED_MS,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "Milliseconds from program start to this frame",
+  },
+  EcallDef {
+    name: "DELTA_MS",
+    number: DELTA_MS,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "Milliseconds since previous frame",
+  },
+  EcallDef {
+    name: "RAND",
+    number: RAND,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "Random number, below n when n > 0",
+  },
+  EcallDef {
+    name: "SEED",
+    number: SEED,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Reseed random number generator",
+  },
+  EcallDef {
+    name: "SCALE8",
+    number: SCALE8,
+    param: Param::Args(2),
+    returns: true,
+    results: 1,
+    help: "Scale 8-bit value a0 by a1/256",
+  },
+  EcallDef {
+    name: "FILL",
+    number: FILL,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Fill a1 LEDs from LED a0 with 0xRRGGBB color a2",
+  },
+  EcallDef {
+    name: "BLEND",
+    number: BLEND,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Blend LED a1 into LED a0 by amount a2 out of 255",
+  },
+  EcallDef {
+    name: "PIXEL",
+    number: PIXEL,
+    param: Param::Args(1),
+    returns: true,
+    results: 3,
+    help: "Read LED a0 as 0xRRGGBB, components go to a0..a2",
+  },
+  EcallDef {
+    name: "RAINBOW",
+    number: RAINBOW,
+    param: Param::Args(2),
+    returns: false,
+    results: 0,
+    help: "Convert a1 HSV LEDs from LED a0 to RGB, visually even hues",
+  },
+  EcallDef {
+    name: "SPECTRUM",
+    number: SPECTRUM,
+    param: Param::Args(2),
+    returns: false,
+    results: 0,
+    help: "Convert a1 HSV LEDs from LED a0 to RGB, evenly spaced hues",
+  },
+  EcallDef {
+    name: "RGB2HSV",
+    number: RGB2HSV,
+    param: Param::Args(2),
+    returns: false,
+    results: 0,
+    help: "Convert a1 RGB LEDs from LED a0 to HSV",
+  },
+  EcallDef {
+    name: "HSL2RGB",
+    number: HSL2RGB,
+    param: Param::Args(2),
+    returns: false,
+    results: 0,
+    help: "Convert a1 HSL LEDs from LED a0 to RGB",
+  },
+  EcallDef {
+    name: "RGB2HSL",
+    number: RGB2HSL,
+    param: Param::Args(2),
+    returns: false,
+    results: 0,
+    help: "Convert a1 RGB LEDs from LED a0 to HSL",
+  },
+  EcallDef {
+    name: "DIM",
+    number: DIM,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Scale a1 LEDs from LED a0 by a2/256",
+  },
+  EcallDef {
+    name: "ADD",
+    number: ADD,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Add 0xRRGGBB color a2 to a1 LEDs from LED a0, saturating",
+  },
+  EcallDef {
+    name: "MIX",
+    number: MIX,
+    param: Param::Args(4),
+    returns: false,
+    results: 0,
+    help: "Blend 0xRRGGBB color a2 into a1 LEDs from LED a0 by amount a3 out of 255",
+  },
+  EcallDef {
+    name: "GRADIENT",
+    number: GRADIENT,
+    param: Param::Args(4),
+    returns: false,
+    results: 0,
+    help: "Fill a1 LEDs from LED a0 with gradient from color a2 to color a3",
+  },
+  EcallDef {
+    name: "KELVIN",
+    number: KELVIN,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Fill a1 LEDs from LED a0 with white of a2 kelvin",
+  },
+  EcallDef {
+    name: "GAMMA",
+    number: GAMMA,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Set output gamma of red, green and blue to a0..a2 tenths",
+  },
+  EcallDef {
+    name: "BRIGHTNESS",
+    number: BRIGHTNESS,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Set output brightness to n out of 255",
+  },
+  EcallDef {
+    name: "DITHER",
+    number: DITHER,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Enable temporal dithering of output when n is not zero",
+  },
+  EcallDef {
+    name: "POWER",
+    number: POWER,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "Estimated draw of LED RAM in mA before power limiting",
+  },
+  EcallDef {
+    name: "CHANNEL",
+    number: CHANNEL,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "Select channel n for LED index ecalls, returns its length",
+  },
+  EcallDef {
+    name: "MATRIX",
+    number: MATRIX,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Map a0 by a1 panel with wiring, rotation and flip flags a2",
+  },
+  EcallDef {
+    name: "XY_LUT",
+    number: XY_LUT,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Read LUT wiring from RAM address n",
+  },
+  EcallDef {
+    name: "XY",
+    number: XY,
+    param: Param::Args(2),
+    returns: true,
+    results: 0,
+    help: "LED index at x a0 and y a1, -1 when there is none",
+  },
+  EcallDef {
+    name: "SET_XY",
+    number: SET_XY,
+    param: Param::Args(3),
+    returns: false,
+    results: 0,
+    help: "Set LED at x a0 and y a1 to 0xRRGGBB color a2",
+  },
+  EcallDef {
+    name: "GET_XY",
+    number: GET_XY,
+    param: Param::Args(2),
+    returns: true,
+    results: 3,
+    help: "Read LED at x a0 and y a1 as 0xRRGGBB, components go to a0..a2",
+  },
+  EcallDef {
+    name: "MATRIX_SIZE",
+    number: MATRIX_SIZE,
+    param: Param::None,
+    returns: true,
+    results: 2,
+    help: "Number of matrix positions, width and height go to a0 and a1",
+  },
+  EcallDef {
+    name: "BUFFER",
+    number: BUFFER,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Keep LED RAM between frames when n is 0, clear it after every present when 1",
+  },
+  EcallDef {
+    name: "PRESENT",
+    number: PRESENT,
+    param: Param::None,
+    returns: false,
+    results: 0,
+    help: "Show LED RAM now, frames are no longer shown when the program ends",
+  },
+  EcallDef {
+    name: "BUTTON",
+    number: BUTTON,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "1 while button n is held down, otherwise 0",
+  },
+  EcallDef {
+    name: "PRESSED",
+    number: PRESSED,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "1 when button n went down since the previous frame",
+  },
+  EcallDef {
+    name: "RELEASED",
+    number: RELEASED,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "1 when button n went up since the previous frame",
+  },
+  EcallDef {
+    name: "ANALOG",
+    number: ANALOG,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "Level of analog input n from 0 to 255",
+  },
+  EcallDef {
+    name: "ENCODER",
+    number: ENCODER,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "Steps of encoder n since the previous frame, clockwise is positive",
+  },
+  EcallDef {
+    name: "LEVEL",
+    number: LEVEL,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "Sound level of this frame from 0 to 255",
+  },
+  EcallDef {
+    name: "BEAT",
+    number: BEAT,
+    param: Param::None,
+    returns: true,
+    results: 0,
+    help: "1 when a beat started in this frame",
+  },
+  EcallDef {
+    name: "SET_BANDS",
+    number: SET_BANDS,
+    param: Param::Value,
+    returns: false,
+    results: 0,
+    help: "Split the spectrum into n bands, 1 to 16",
+  },
+  EcallDef {
+    name: "BAND",
+    number: BAND,
+    param: Param::Value,
+    returns: true,
+    results: 0,
+    help: "Magnitude of spectrum band n from 0 to 255, low frequencies first",
+  },
+];
+
+/// Most strip channels LED RAM can be split into.
+pub const MAX_CHANNELS: usize = 8;
+/// Address space between LED RAM windows of consecutive channels, the last one extends to the end.
+pub const CHANNEL_WINDOW: u16 = 0x1000;
+
+/// Strip run driven by its own output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+  pub leds: usize,
+  pub format: PixelFormat,
+}
+
+/// What happens to LED RAM once a frame is presented.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferMode {
+  /// Programs draw over the previous frame.
+  Persist,
+  /// Programs start every frame from dark LEDs.
+  Clear,
+}
+
+/// Seed used after every program load unless overridden with `LedEnv::set_seed`.
+pub const DEFAULT_SEED: u32 = 0x2545_f491;
+
+#[derive(Debug)]
+pub enum LedError {
+  MemoryOverread,
+  InvalidAddress,
+  InvalidFormat,
+  InvalidChannel,
+  InvalidMatrix,
+  InvalidMode,
+  InvalidInput,
+}
+
+pub struct LedEnv<B> {
+  channels: [Channel; MAX_CHANNELS],
+  count: usize,
+  selected: usize,
+  /// Pixel format declared by the program, overrides formats of all channels.
+  format: Option<PixelFormat>,
+  matrix: Option<Matrix>,
+  default_matrix: Option<Matrix>,
+  /// RAM address of the program LUT, otherwise `lut` is used.
+  lut_addr: Option<u16>,
+  lut: Option<B>,
+  ops: u32,
+  psc: u32,
+  ticks: u32,
+  frames: u32,
+  frame_tick: u32,
+  delta_ticks: u32,
+  seed: u32,
+  rng: XorShift,
+  output: Pipeline,
+  ecalls: Dispatcher<Self, LedError>,
+  ram: B,
+  led_ram: B,
+  /// Frame shown on the strip, outputs read LED RAM directly without it.
+  front: Option<B>,
+  mode: BufferMode,
+  /// Set once the program presents frames itself.
+  explicit: bool,
+  clear_pending: bool,
+  controls: Controls,
+  sound: Sound,
+  /// Spectrum bands programs read, analyzer bands are merged into them.
+  bands: usize,
+}
+
+impl<B> LedEnv<B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  /// Single channel strip as long as LED RAM fits in the default pixel format.
+  pub fn new<F: Into<PixelFormat>>(ram: B, led_ram: B, format: F) -> Self {
+    let format = format.into();
+    let channel = Channel {
+      leds: led_ram.as_ref().len() / format.size(),
+      format,
+    };
+    LedEnv {
+      channels: [channel; MAX_CHANNELS],
+      count: 1,
+      selected: 0,
+      format: None,
+      matrix: None,
+      default_matrix: None,
+      lut_addr: None,
+      lut: None,
+      ops: 0,
+      psc: 0,
+      ticks: 0,
+      frames: 0,
+      frame_tick: 0,
+      delta_ticks: 0,
+      seed: DEFAULT_SEED,
+      rng: XorShift::new(DEFAULT_SEED),
+      output: Pipeline::new(),
+      ecalls: Dispatcher::new(
+        ECALLS,
+        &[
+          ("SET_PSC", Self::set_psc),
+          ("HSV2RGB", Self::hsv2rgb),
+          ("FRAME", |env, _, _| Ok(env.frame() as i32)),
+          ("ELAPSED_MS", |env, _, _| Ok(env.elapsed_ms() as i32)),
+          ("DELTA_MS", |env, _, _| Ok(env.delta_ms() as i32)),
+          ("RAND", Self::rand),
+          ("SEED", Self::seed),
+          ("SCALE8", Self::scale8),
+          ("FILL", Self::fill),
+          ("BLEND", Self::blend),
+          ("PIXEL", Self::read_pixel),
+          ("RAINBOW", |env, _, args| env.decode_hsv(args, rainbow)),
+          ("SPECTRUM", |env, _, args| env.decode_hsv(args, spectrum)),
+          ("RGB2HSV", |env, _, args| env.encode_hsv(args, rgb2hsv)),
+          ("HSL2RGB", |env, _, args| env.decode_hsv(args, hsl2rgb)),
+          ("RGB2HSL", |env, _, args| env.encode_hsv(args, rgb2hsl)),
+          ("DIM", |env, _, args| {
+            let scale = args[2] as u8;
+            env.map_range(args, |rgb, _| dim(rgb, scale))
+          }),
+          ("ADD", |env, _, args| {
+            let color = unpack_rgb(args[2]);
+            env.map_range(args, |rgb, _| add(rgb, color))
+          }),
+          ("MIX", |env, _, args| {
+            let (color, amount) = (unpack_rgb(args[2]), args[3] as u8);
+            env.map_range(args, |rgb, _| blend(rgb, color, amount))
+          }),
+          ("GRADIENT", Self::gradient),
+          ("GAMMA", |env, _, args| {
+            let gamma = |val: i32| val.clamp(0, 255) as u8;
+            env
+              .output
+              .set_gamma([gamma(args[0]), gamma(args[1]), gamma(args[2])]);
+            Ok(0)
+          }),
+          ("BRIGHTNESS", |env, param, _| {
+            env.output.set_brightness(param.clamp(0, 255) as u8);
+            Ok(0)
+          }),
+          ("DITHER", |env, param, _| {
+            env.output.set_dither(param != 0);
+            Ok(0)
+          }),
+          ("POWER", |env, _, _| {
+            Ok(env.output.power(env.pixels()).ma as i32)
+          }),
+          ("CHANNEL", |env, param, _| {
+            if param < 0 || param as usize >= env.count {
+              return Err(LedError::InvalidChannel);
+            }
+            env.selected = param as usize;
+            Ok(env.channels[env.selected].leds as i32)
+          }),
+          ("BUFFER", |env, param, _| {
+            env.mode = match param {
+              0 => BufferMode::Persist,
+              1 => BufferMode::Clear,
+              _ => return Err(LedError::InvalidMode),
+            };
+            Ok(0)
+          }),
+          ("PRESENT", |env, _, _| {
+            env.explicit = true;
+            env.present();
+            Ok(0)
+          }),
+          ("BUTTON", |env, param, _| {
+            let button = input_index(param, MAX_BUTTONS)?;
+            Ok(env.controls.held(button) as i32)
+          }),
+          ("PRESSED", |env, param, _| {
+            let button = input_index(param, MAX_BUTTONS)?;
+            Ok(env.controls.pressed(button) as i32)
+          }),
+          ("RELEASED", |env, param, _| {
+            let button = input_index(param, MAX_BUTTONS)?;
+            Ok(env.controls.released(button) as i32)
+          }),
+          ("ANALOG", |env, param, _| {
+            Ok(env.controls.analog(input_index(param, MAX_ANALOG)?) as i32)
+          }),
+          ("ENCODER", |env, param, _| {
+            Ok(env.controls.encoder(input_index(param, MAX_ENCODERS)?))
+          }),
+          ("LEVEL", |env, _, _| Ok(env.sound.level as i32)),
+          ("BEAT", |env, _, _| Ok(env.sound.beat as i32)),
+          ("SET_BANDS", |env, param, _| {
+            if param < 1 || param as usize > BANDS {
+              return Err(LedError::InvalidInput);
+            }
+            env.bands = param as usize;
+            Ok(0)
+          }),
+          ("BAND", |env, param, _| {
+            Ok(env.band(input_index(param, env.bands)?) as i32)
+          }),
+          ("MATRIX", |env, _, args| {
+            let size = |val: i32| u16::try_from(val).map_err(|_| LedError::InvalidMatrix);
+            let matrix = Matrix::from_flags(size(args[0])?, size(args[1])?, args[2] as u32);
+            env.matrix = Some(matrix.ok_or(LedError::InvalidMatrix)?);
+            Ok(0)
+          }),
+          ("XY_LUT", |env, param, _| {
+            env.lut_addr = Some(param as u16);
+            Ok(0)
+          }),
+          ("XY", |env, _, args| {
+            Ok(env.xy(args[0], args[1]).map_or(-1, |idx| idx as i32))
+          }),
+          ("SET_XY", |env, _, args| {
+            if let Some(idx) = env.xy(args[0], args[1]) {
+              env.set_pixel(idx, unpack_rgb(args[2]));
+            }
+            Ok(0)
+          }),
+          ("GET_XY", |env, _, args| {
+            let (r, g, b) = env
+              .xy(args[0], args[1])
+              .map_or((0, 0, 0), |idx| env.pixel(idx));
+            args[..3].copy_from_slice(&[r as i32, g as i32, b as i32]);
+            Ok(pack_rgb((r, g, b)))
+          }),
+          ("MATRIX_SIZE", |env, _, args| {
+            let (width, height) = env.matrix().size();
+            args[..2].copy_from_slice(&[width as i32, height as i32]);
+            Ok(width as i32 * height as i32)
+          }),
+          ("KELVIN", |env, _, args| {
+            let rgb = kelvin(args[2].clamp(0, u16::MAX as i32) as u16);
+            env.map_range(args, |_, _| rgb)
+          }),
+        ],
+      ),
+      ram,
+      led_ram,
+      front: None,
+      mode: BufferMode::Persist,
+      explicit: false,
+      clear_pending: false,
+      controls: Controls::default(),
+      sound: Sound::default(),
+      bands: BANDS,
+    }
+  }
+
+  /// LEDs of all channels.
+  pub fn leds(&self) -> usize {
+    self.channels[..self.count].iter().map(|ch| ch.leds).sum()
+  }
+
+  /// Sets the strip length, LED RAM has to fit it in every pixel format programs declare.
+  pub fn set_leds(&mut self, leds: usize) {
+    let format = self.channels[0].format;
+    let leds = leds.min(self.led_ram.as_ref().len() / format.size());
+    self.set_channels(&[Channel { leds, format }]).ok();
+  }
+
+  /// Splits LED RAM into channels, laid out one after another.
+  pub fn set_channels(&mut self, channels: &[Channel]) -> Result<(), LedError> {
+    if channels.is_empty() || channels.len() > MAX_CHANNELS {
+      return Err(LedError::InvalidChannel);
+    }
+    let len: usize = channels.iter().map(|ch| ch.leds * ch.format.size()).sum();
+    if len > self.led_ram.as_ref().len() {
+      return Err(LedError::MemoryOverread);
+    }
+    self.channels[..channels.len()].copy_from_slice(channels);
+    self.count = channels.len();
+    self.selected = 0;
+    Ok(())
+  }
+
+  pub fn channels(&self) -> usize {
+    self.count
+  }
+
+  /// Channel as the current program sees it.
+  pub fn channel(&self, ch: usize) -> Channel {
+    Channel {
+      leds: self.channels[ch].leds,
+      format: self.channel_format(ch),
+    }
+  }
+
+  /// Pixel format of the first channel.
+  pub fn format(&self) -> PixelFormat {
+    self.channel_format(0)
+  }
+
+  pub fn order(&self) -> ColorOrder {
+    self.format().order
+  }
+
+  /// Matrix programs start with, `None` maps the strip as a single row.
+  pub fn set_matrix(&mut self, matrix: Option<Matrix>) {
+    self.default_matrix = matrix;
+    self.matrix = matrix;
+  }
+
+  /// Sets the table of `Wiring::Lut` unless the program points `XY_LUT` to its own.
+  pub fn set_lut(&mut self, lut: B) {
+    self.lut = Some(lut);
+  }
+
+  /// Matrix as the current program sees it.
+  pub fn matrix(&self) -> Matrix {
+    self.matrix.unwrap_or_else(|| {
+      let leds = self.leds().min(u16::MAX as usize) as u16;
+      Matrix::new(leds, 1, Wiring::Rows)
+    })
+  }
+
+  /// Whether the strip is mapped as a panel rather than a single row.
+  pub fn is_matrix(&self) -> bool {
+    self.matrix.is_some()
+  }
+
+  /// LED index over all channels at program coordinates.
+  pub fn xy(&self, x: i32, y: i32) -> Option<usize> {
+    let lut = match (self.lut_addr, self.lut.as_ref()) {
+      (Some(addr), _) => self.ram.as_ref().get(addr as usize..).unwrap_or(&[]),
+      (None, Some(lut)) => lut.as_ref(),
+      (None, None) => &[],
+    };
+    self.matrix().xy(x, y, lut).filter(|idx| *idx < self.leds())
+  }
+
+  pub fn prescaler(&self) -> u32 {
+    self.psc
+  }
+
+  /// Sets the prescaler from the host, programs override it with `SET_PSC`.
+  pub fn set_prescaler(&mut self, psc: u32) {
+    self.psc = psc;
+  }
+
+  /// Index of the current frame, counted from program start.
+  pub fn frame(&self) -> u32 {
+    self.frames.saturating_sub(1)
+  }
+
+  /// Time from program start to the current frame.
+  pub fn elapsed_ms(&self) -> u32 {
+    ticks_to_ms(self.frame_tick)
+  }
+
+  /// Time between the previous and the current frame.
+  pub fn delta_ms(&self) -> u32 {
+    ticks_to_ms(self.delta_ticks)
+  }
+
+  /// Sets the seed programs start with, takes effect on the next program load.
+  pub fn set_seed(&mut self, seed: u32) {
+    self.seed = seed;
+  }
+
+  pub fn ram(&self) -> &[u8] {
+    self.ram.as_ref()
+  }
+
+  /// LED RAM used by all channels in their current pixel formats.
+  pub fn led_ram(&self) -> &[u8] {
+    &self.led_ram.as_ref()[..self.channel_bytes(self.count - 1).end]
+  }
+
+  /// LED RAM of a single channel.
+  pub fn channel_ram(&self, ch: usize) -> &[u8] {
+    &self.led_ram.as_ref()[self.channel_bytes(ch)]
+  }
+
+  /// Pixel by index over all channels, black past the last LED.
+  pub fn pixel(&self, idx: usize) -> Rgb {
+    self.decode_pixel(self.led_ram.as_ref(), idx)
+  }
+
+  /// Writes pixel by index over all channels, in the pixel format of its channel. Pixels past the
+  /// last LED are ignored.
+  pub fn set_pixel(&mut self, idx: usize, rgb: Rgb) {
+    if let Some((format, bytes)) = self.pixel_bytes(idx) {
+      format.encode(rgb, &mut self.led_ram.as_mut()[bytes]);
+    }
+  }
+
+  pub fn pixels(&self) -> impl ExactSizeIterator<Item = Rgb> + '_ {
+    (0..self.leds()).map(move |idx| self.pixel(idx))
+  }
+
+  pub fn channel_pixels(&self, ch: usize) -> impl ExactSizeIterator<Item = Rgb> + '_ {
+    let format = self.channel_format(ch);
+    self
+      .channel_ram(ch)
+      .chunks_exact(format.size())
+      .map(move |px| format.decode(px))
+  }
+
+  /// Output stage the strip driver gets pixels through.
+  pub fn output(&self) -> &Pipeline {
+    &self.output
+  }
+
+  pub fn output_mut(&mut self) -> &mut Pipeline {
+    &mut self.output
+  }
+
+  /// Samples controls programs read in the next frame, called once per frame before the spin.
+  pub fn sample_input<I: Input + ?Sized>(&mut self, input: &mut I) {
+    self.controls.sample(input);
+  }
+
+  pub fn controls(&self) -> &Controls {
+    &self.controls
+  }
+
+  /// Sets the sound programs read in the next frame, called once per frame before the spin.
+  pub fn set_sound(&mut self, sound: Sound) {
+    self.sound = sound;
+  }
+
+  pub fn sound(&self) -> &Sound {
+    &self.sound
+  }
+
+  /// Loudest analyzer band within band `idx` of the bands the program split the spectrum into.
+  pub fn band(&self, idx: usize) -> u8 {
+    let bands = &self.sound.bands[(idx * BANDS / self.bands)..((idx + 1) * BANDS / self.bands)];
+    bands.iter().copied().max().unwrap_or(0)
+  }
+
+  /// Enables double buffering, outputs then only see presented frames.
+  pub fn set_front(&mut self, front: B) -> Result<(), LedError> {
+    if front.as_ref().len() < self.led_ram.as_ref().len() {
+      return Err(LedError::MemoryOverread);
+    }
+    self.front = Some(front);
+    Ok(())
+  }
+
+  pub fn buffer_mode(&self) -> BufferMode {
+    self.mode
+  }
+
+  /// Shows LED RAM on the strip, then clears it in `BufferMode::Clear`.
+  ///
+  /// Without front buffer clearing waits for the next frame, so outputs still get the presented one.
+  pub fn present(&mut self) {
+    let len = self.led_ram().len();
+    match self.front.as_mut() {
+      Some(front) => {
+        front.as_mut()[..len].copy_from_slice(&self.led_ram.as_ref()[..len]);
+        if self.mode == BufferMode::Clear {
+          self.clear_led_ram();
+        }
+      }
+      None => self.clear_pending = self.mode == BufferMode::Clear,
+    }
+  }
+
+  /// Ends the frame after a spin, presents LED RAM unless the program does it with `PRESENT`.
+  pub fn finish_frame(&mut self) {
+    if !self.explicit {
+      self.present();
+    }
+  }
+
+  /// Pixels of the presented frame by index over all channels.
+  pub fn presented_pixels(&self) -> impl ExactSizeIterator<Item = Rgb> + '_ {
+    (0..self.leds()).map(move |idx| self.decode_pixel(self.presented(), idx))
+  }
+
+  /// Estimated draw of the presented frame through the output stage.
+  pub fn power(&self) -> Power {
+    self.output.power(self.presented_pixels())
+  }
+
+  /// Pixels as sent to the strip, after gamma, brightness, dithering and power limiting.
+  pub fn output_pixels(&self) -> impl ExactSizeIterator<Item = Rgb> + '_ {
+    let scale = self.power().scale;
+    self
+      .presented_pixels()
+      .map(move |rgb| self.output.apply_scaled(rgb, scale))
+  }
+
+  /// Same as `output_pixels` for a single channel, power is limited over all of them.
+  pub fn channel_output_pixels(&self, ch: usize) -> impl ExactSizeIterator<Item = Rgb> + '_ {
+    let scale = self.power().scale;
+    let format = self.channel_format(ch);
+    self.presented()[self.channel_bytes(ch)]
+      .chunks_exact(format.size())
+      .map(move |px| self.output.apply_scaled(format.decode(px), scale))
+  }
+
+  /// Levels of a channel as sent to its driver, at the width of the channel's pixels.
+  pub fn channel_levels(&self, ch: usize) -> impl ExactSizeIterator<Item = Levels> + '_ {
+    let scale = self.power().scale;
+    let format = self.channel_format(ch);
+    self.presented()[self.channel_bytes(ch)]
+      .chunks_exact(format.size())
+      .map(move |px| {
+        let levels = format.decode_levels(px);
+        self.output.apply_levels(levels, format.channels, scale)
+      })
+  }
+
+  /// Advances the prescaler by one `TIMER_HZ` tick, returns true when a new frame is due.
+  pub fn tick(&mut self) -> bool {
+    let ticks = self.ticks;
+    self.ticks = self.ticks.wrapping_add(1);
+    if self.psc > 0 && self.ops < self.psc {
+      self.ops += 1;
+      return false;
+    }
+    self.ops = 0;
+    if self.frames > 0 {
+      self.delta_ticks = ticks.wrapping_sub(self.frame_tick);
+    }
+    self.frame_tick = ticks;
+    self.frames = self.frames.wrapping_add(1);
+    self.output.advance();
+    if self.clear_pending {
+      self.clear_pending = false;
+      self.clear_led_ram();
+    }
+    true
+  }
+
+  fn set_psc(&mut self, param: i32, _: &mut Args) -> Result<i32, LedError> {
+    self.psc = param as u32;
+    Ok(0)
+  }
+
+  fn hsv2rgb(&mut self, param: i32, _: &mut Args) -> Result<i32, LedError> {
+    let (format, px) = self.led_window(param as u16)?;
+    let rgb = hsv2rgb(px[0], px[1], px[2]);
+    format.encode(rgb, px);
+    Ok(0)
+  }
+
+  fn rand(&mut self, param: i32, _: &mut Args) -> Result<i32, LedError> {
+    let val = self.rng.next_u32();
+    if param > 0 {
+      return Ok((val % param as u32) as i32);
+    }
+    Ok(val as i32)
+  }
+
+  fn seed(&mut self, param: i32, _: &mut Args) -> Result<i32, LedError> {
+    self.rng = XorShift::new(param as u32);
+    Ok(0)
+  }
+
+  fn scale8(&mut self, _: i32, args: &mut Args) -> Result<i32, LedError> {
+    args[0] = scale8(args[0] as u8, args[1] as u8) as i32;
+    Ok(args[0])
+  }
+
+  fn fill(&mut self, _: i32, args: &mut Args) -> Result<i32, LedError> {
+    let rgb = unpack_rgb(args[2]);
+    let (format, range) = self.led_range(args[0], args[1])?;
+    for px in range.chunks_exact_mut(format.size()) {
+      format.encode(rgb, px);
+    }
+    Ok(0)
+  }
+
+  fn blend(&mut self, _: i32, args: &mut Args) -> Result<i32, LedError> {
+    let (format, src) = self.led_range(args[1], 1)?;
+    let src = format.decode(src);
+    let (_, dst) = self.led_range(args[0], 1)?;
+    let rgb = blend(format.decode(dst), src, args[2] as u8);
+    format.encode(rgb, dst);
+    Ok(0)
+  }
+
+  fn read_pixel(&mut self, _: i32, args: &mut Args) -> Result<i32, LedError> {
+    let (format, px) = self.led_range(args[0], 1)?;
+    let (r, g, b) = format.decode(px);
+    args[..3].copy_from_slice(&[r as i32, g as i32, b as i32]);
+    Ok(pack_rgb((r, g, b)))
+  }
+
+  fn gradient(&mut self, _: i32, args: &mut Args) -> Result<i32, LedError> {
+    let (from, to) = (unpack_rgb(args[2]), unpack_rgb(args[3]));
+    let last = args[1].saturating_sub(1).max(1) as u32;
+    self.map_range(args, |_, idx| {
+      let frac = (idx as u32 * 255 / last) as u8;
+      blend(from, to, frac)
+    })
+  }
+
+  /// Converts raw HSV or HSL triplets leading the pixels of the LED range in `a0`, `a1` to RGB in strip order.
+  fn decode_hsv(&mut self, args: &mut Args, f: fn(u8, u8, u8) -> Rgb) -> Result<i32, LedError> {
+    let (format, range) = self.led_range(args[0], args[1])?;
+    for px in range.chunks_exact_mut(format.size()) {
+      format.encode(f(px[0], px[1], px[2]), px);
+    }
+    Ok(0)
+  }
+
+  /// Replaces RGB pixels of the LED range in `a0`, `a1` with raw HSV or HSL triplets, zero padded.
+  fn encode_hsv(&mut self, args: &mut Args, f: fn(Rgb) -> Rgb) -> Result<i32, LedError> {
+    let (format, range) = self.led_range(args[0], args[1])?;
+    for px in range.chunks_exact_mut(format.size()) {
+      let (x, y, z) = f(format.decode(px));
+      px[..3].copy_from_slice(&[x, y, z]);
+      for byte in px[3..].iter_mut() {
+        *byte = 0;
+      }
+    }
+    Ok(0)
+  }
+
+  /// Applies `f` to every pixel of the LED range in `a0`, `a1`, also passing its index in the range.
+  fn map_range<F>(&mut self, args: &mut Args, f: F) -> Result<i32, LedError>
+  where
+    F: Fn(Rgb, usize) -> Rgb,
+  {
+    let (format, range) = self.led_range(args[0], args[1])?;
+    for (idx, px) in range.chunks_exact_mut(format.size()).enumerate() {
+      format.encode(f(format.decode(px), idx), px);
+    }
+    Ok(0)
+  }
+
+  /// Front buffer, or LED RAM when there is none.
+  fn presented(&self) -> &[u8] {
+    self.front.as_ref().unwrap_or(&self.led_ram).as_ref()
+  }
+
+  fn clear_led_ram(&mut self) {
+    for byte in self.led_ram.as_mut().iter_mut() {
+      *byte = 0;
+    }
+  }
+
+  /// Pixel format and LED RAM bytes of a pixel by index over all channels.
+  fn pixel_bytes(&self, mut idx: usize) -> Option<(PixelFormat, Range<usize>)> {
+    let mut ch = 0;
+    while idx >= self.channels[ch].leds {
+      idx -= self.channels[ch].leds;
+      ch += 1;
+      if ch == self.count {
+        return None;
+      }
+    }
+    let format = self.channel_format(ch);
+    let offset = self.channel_bytes(ch).start + idx * format.size();
+    Some((format, offset..(offset + format.size())))
+  }
+
+  fn decode_pixel(&self, ram: &[u8], idx: usize) -> Rgb {
+    self
+      .pixel_bytes(idx)
+      .map_or((0, 0, 0), |(format, bytes)| format.decode(&ram[bytes]))
+  }
+
+  /// Pixel format of channel `ch`, the program's when it declares one.
+  pub fn channel_format(&self, ch: usize) -> PixelFormat {
+    self.format.unwrap_or(self.channels[ch].format)
+  }
+
+  fn channel_bytes(&self, ch: usize) -> Range<usize> {
+    let len = |ch: usize| self.channels[ch].leds * self.channel_format(ch).size();
+    let offset = (0..ch).map(len).sum();
+    offset..(offset + len(ch))
+  }
+
+  /// Channel and offset in its LED RAM of an address in the LED RAM windows.
+  fn window(&self, addr: u16) -> Option<(usize, usize)> {
+    let rel = addr.checked_sub(STRIP_BASE)?;
+    let ch = ((rel / CHANNEL_WINDOW) as usize).min(self.count - 1);
+    Some((ch, rel as usize - ch * CHANNEL_WINDOW as usize))
+  }
+
+  /// LED RAM bytes of `count` LEDs of the selected channel starting at LED index `first`.
+  fn led_range(&mut self, first: i32, count: i32) -> Result<(PixelFormat, &mut [u8]), LedError> {
+    if first < 0 || count < 0 {
+      return Err(LedError::InvalidAddress);
+    }
+    let (first, count) = (first as usize, count as usize);
+    let ch = self.selected;
+    if first + count > self.channels[ch].leds {
+      return Err(LedError::MemoryOverread);
+    }
+    let format = self.channel_format(ch);
+    let offset = self.channel_bytes(ch).start + first * format.size();
+    let range = &mut self.led_ram.as_mut()[offset..(offset + count * format.size())];
+    Ok((format, range))
+  }
+
+  /// LED RAM bytes of the pixel at `addr`.
+  fn led_window(&mut self, addr: u16) -> Result<(PixelFormat, &mut [u8]), LedError> {
+    let (ch, offset) = self.window(addr).ok_or(LedError::InvalidAddress)?;
+    let format = self.channel_format(ch);
+    let bytes = self.channel_bytes(ch);
+    if offset + format.size() > bytes.len() {
+      return Err(LedError::MemoryOverread);
+    }
+    let start = bytes.start + offset;
+    Ok((
+      format,
+      &mut self.led_ram.as_mut()[start..(start + format.size())],
+    ))
+  }
+}
+
+impl<B> Env for LedEnv<B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  type Error = LedError;
+
+  fn reset(&mut self) {
+    self.ops = 0;
+    self.psc = 0;
+    self.ticks = 0;
+    self.frames = 0;
+    self.frame_tick = 0;
+    self.delta_ticks = 0;
+    self.rng = XorShift::new(self.seed);
+    self.format = None;
+    self.selected = 0;
+    self.matrix = self.default_matrix;
+    self.lut_addr = None;
+    self.mode = BufferMode::Persist;
+    self.explicit = false;
+    self.clear_pending = false;
+    self.bands = BANDS;
+    self.output.reset();
+    for byte in self.ram.as_mut().iter_mut() {
+      *byte = 0;
+    }
+    // The front buffer keeps showing the last frame until the new program presents one.
+    self.clear_led_ram();
+  }
+
+  fn mem_fetch(&self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
+    let (mem, offset) = match self.window(addr) {
+      Some((ch, offset)) => (self.channel_ram(ch), offset),
+      None => (self.ram.as_ref(), addr as usize),
+    };
+    let end = offset + buf.len();
+    if end > mem.len() {
+      return Err(LedError::MemoryOverread);
+    }
+    buf.copy_from_slice(&mem[offset..end]);
+    Ok(())
+  }
+
+  fn mem_set(&mut self, addr: u16, val: &[u8]) -> Result<(), Self::Error> {
+    let (mem, offset) = match self.window(addr) {
+      Some((ch, offset)) => {
+        let bytes = self.channel_bytes(ch);
+        (&mut self.led_ram.as_mut()[bytes], offset)
+      }
+      None => (self.ram.as_mut(), addr as usize),
+    };
+    let end = offset + val.len();
+    if end > mem.len() {
+      return Err(LedError::MemoryOverread);
+    }
+    mem[offset..end].copy_from_slice(val);
+    Ok(())
+  }
+
+  fn ecall(&mut self, ecall: u8, param: i32) -> Result<i32, Self::Error> {
+    self.ecall_args(ecall, param, &mut [0; 8])
+  }
+
+  fn set_pixel_format(&mut self, format: u8) -> Result<(), Self::Error> {
+    let format = PixelFormat::from_byte(format).ok_or(LedError::InvalidFormat)?;
+    if self.leds() * format.size() > self.led_ram.as_ref().len() {
+      return Err(LedError::InvalidFormat);
+    }
+    self.format = Some(format);
+    Ok(())
+  }
+
+  fn ecall_args(&mut self, ecall: u8, param: i32, args: &mut Args) -> Result<i32, Self::Error> {
+    match self.ecalls.handler(ecall) {
+      Some(handler) => handler(self, param, args),
+      None => Ok(0),
+    }
+  }
+}
+
+fn input_index(param: i32, len: usize) -> Result<usize, LedError> {
+  if param < 0 || param as usize >= len {
+    return Err(LedError::InvalidInput);
+  }
+  Ok(param as usize)
+}
+
+fn ticks_to_ms(ticks: u32) -> u32 {
+  (ticks as u64 * 1000 / TIMER_HZ as u64) as u32
+}
+
+/// Xorshift32 generator, zero seed is replaced with `DEFAULT_SEED` as it would get stuck.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XorShift(u32);
+
+impl XorShift {
+  pub fn new(seed: u32) -> Self {
+    XorShift(if seed == 0 { DEFAULT_SEED } else { seed })
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    let mut x = self.0;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    self.0 = x;
+    x
+  }
+}
@@ -1,9 +1,26 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod audio;
+pub mod color;
 #[cfg(feature = "std")]
 pub mod compiler;
+pub mod compositor;
+pub mod driver;
+pub mod ecall;
+pub mod input;
+pub mod led;
+pub mod link;
+pub mod matrix;
+pub mod output;
 #[cfg(feature = "std")]
 pub mod parser;
+pub mod pixel;
+pub mod runtime;
+pub mod storage;
+pub mod supervisor;
+pub mod telemetry;
+pub mod transition;
+pub mod upload;
 pub mod vm;
 
 #[derive(Debug)]
@@ -17,6 +34,7 @@ pub enum Error {
 pub enum CompilerError {
   AliasNotFound,
   FileReadFailed,
+  UnknownFormat,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -59,7 +77,7 @@ pub enum Reg {
 impl Reg {
   pub fn parse(val: u8) -> Result<Self, Error> {
     if val <= 31 {
-      return Ok(unsafe { core::mem::transmute(val) });
+      return Ok(unsafe { core::mem::transmute::<u8, Reg>(val) });
     }
     Err(Error::ParseError)
   }
@@ -111,7 +129,7 @@ pub enum Opcode {
 impl Opcode {
   pub fn parse(val: u8) -> Result<Self, Error> {
     // TODO: implement safe parsing
-    Ok(unsafe { core::mem::transmute(val) })
+    Ok(unsafe { core::mem::transmute::<u8, Opcode>(val) })
   }
 }
 
@@ -0,0 +1,414 @@
+use crate::telemetry::{Status, STATUS_LEN};
+use byteorder::{BigEndian, ByteOrder};
+
+pub const SYNC: u8 = 0xa5;
+pub const CHUNK_SIZE: usize = 64;
+pub const MAX_PAYLOAD: usize = CHUNK_SIZE + 2;
+pub const MAX_FRAME: usize = MAX_PAYLOAD + 6;
+
+const CMD_START: u8 = 0x01;
+const CMD_DATA: u8 = 0x02;
+const CMD_COMMIT: u8 = 0x03;
+const CMD_SAVE: u8 = 0x04;
+const CMD_SELECT: u8 = 0x05;
+const CMD_NEXT: u8 = 0x06;
+const CMD_PREV: u8 = 0x07;
+const CMD_SET_DEFAULT: u8 = 0x08;
+const CMD_STATUS: u8 = 0x09;
+const CMD_PAUSE: u8 = 0x0a;
+const CMD_RESUME: u8 = 0x0b;
+const CMD_STEP: u8 = 0x0c;
+const CMD_READ: u8 = 0x0d;
+const CMD_WRITE: u8 = 0x0e;
+const CMD_ACK: u8 = 0x80;
+const CMD_NACK: u8 = 0x81;
+const CMD_REPORT: u8 = 0x82;
+const CMD_MEMORY: u8 = 0x83;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkError {
+  BadChecksum,
+  BadLength,
+  UnknownCommand,
+  BufferOverflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Nack {
+  BadFrame = 1,
+  BadState = 2,
+  OutOfOrder = 3,
+  TooLarge = 4,
+  BadChecksum = 5,
+  BadProgram = 6,
+  BadSlot = 7,
+  StorageFailed = 8,
+  BadAddress = 9,
+  /// Previous upload was not taken by the device yet, retry later.
+  Busy = 10,
+}
+
+impl Nack {
+  fn parse(val: u8) -> Result<Self, LinkError> {
+    match val {
+      1 => Ok(Nack::BadFrame),
+      2 => Ok(Nack::BadState),
+      3 => Ok(Nack::OutOfOrder),
+      4 => Ok(Nack::TooLarge),
+      5 => Ok(Nack::BadChecksum),
+      6 => Ok(Nack::BadProgram),
+      7 => Ok(Nack::BadSlot),
+      8 => Ok(Nack::StorageFailed),
+      9 => Ok(Nack::BadAddress),
+      10 => Ok(Nack::Busy),
+      _ => Err(LinkError::UnknownCommand),
+    }
+  }
+}
+
+/// Link frame: `SYNC | command | payload length (u16) | payload | CRC-16 (u16)`,
+/// checksum covers everything between sync byte and checksum itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Packet<'a> {
+  Start {
+    size: u16,
+    crc: u32,
+  },
+  Data {
+    offset: u16,
+    data: &'a [u8],
+  },
+  Commit,
+  /// Stores the last committed upload into a slot.
+  Save {
+    slot: u8,
+    name: &'a [u8],
+  },
+  Select(u8),
+  Next,
+  Prev,
+  SetDefault(u8),
+  /// Asks for a `Report` of the device state.
+  Status,
+  /// Stops rendering frames until `Resume`.
+  Pause,
+  Resume,
+  /// Renders one frame and pauses.
+  Step,
+  /// Asks for `Memory` with `len` bytes of VM memory.
+  Read {
+    addr: u16,
+    len: u8,
+  },
+  /// Writes VM memory.
+  Write {
+    addr: u16,
+    data: &'a [u8],
+  },
+  Ack,
+  Nack(Nack),
+  Report(Status),
+  Memory {
+    addr: u16,
+    data: &'a [u8],
+  },
+}
+
+impl<'a> Packet<'a> {
+  pub fn parse(cmd: u8, payload: &'a [u8]) -> Result<Self, LinkError> {
+    let expect_len = |len: usize| {
+      if payload.len() == len {
+        Ok(())
+      } else {
+        Err(LinkError::BadLength)
+      }
+    };
+    match cmd {
+      CMD_START => {
+        expect_len(6)?;
+        Ok(Packet::Start {
+          size: BigEndian::read_u16(&payload[0..2]),
+          crc: BigEndian::read_u32(&payload[2..6]),
+        })
+      }
+      CMD_DATA => {
+        if payload.len() < 2 {
+          return Err(LinkError::BadLength);
+        }
+        Ok(Packet::Data {
+          offset: BigEndian::read_u16(&payload[0..2]),
+          data: &payload[2..],
+        })
+      }
+      CMD_COMMIT => {
+        expect_len(0)?;
+        Ok(Packet::Commit)
+      }
+      CMD_SAVE => {
+        if payload.is_empty() {
+          return Err(LinkError::BadLength);
+        }
+        Ok(Packet::Save {
+          slot: payload[0],
+          name: &payload[1..],
+        })
+      }
+      CMD_SELECT => {
+        expect_len(1)?;
+        Ok(Packet::Select(payload[0]))
+      }
+      CMD_NEXT => {
+        expect_len(0)?;
+        Ok(Packet::Next)
+      }
+      CMD_PREV => {
+        expect_len(0)?;
+        Ok(Packet::Prev)
+      }
+      CMD_SET_DEFAULT => {
+        expect_len(1)?;
+        Ok(Packet::SetDefault(payload[0]))
+      }
+      CMD_STATUS => {
+        expect_len(0)?;
+        Ok(Packet::Status)
+      }
+      CMD_PAUSE => {
+        expect_len(0)?;
+        Ok(Packet::Pause)
+      }
+      CMD_RESUME => {
+        expect_len(0)?;
+        Ok(Packet::Resume)
+      }
+      CMD_STEP => {
+        expect_len(0)?;
+        Ok(Packet::Step)
+      }
+      CMD_READ => {
+        expect_len(3)?;
+        Ok(Packet::Read {
+          addr: BigEndian::read_u16(&payload[0..2]),
+          len: payload[2],
+        })
+      }
+      CMD_WRITE | CMD_MEMORY => {
+        if payload.len() < 2 {
+          return Err(LinkError::BadLength);
+        }
+        let (addr, data) = (BigEndian::read_u16(&payload[0..2]), &payload[2..]);
+        Ok(match cmd {
+          CMD_WRITE => Packet::Write { addr, data },
+          _ => Packet::Memory { addr, data },
+        })
+      }
+      CMD_ACK => {
+        expect_len(0)?;
+        Ok(Packet::Ack)
+      }
+      CMD_NACK => {
+        expect_len(1)?;
+        Ok(Packet::Nack(Nack::parse(payload[0])?))
+      }
+      CMD_REPORT => Ok(Packet::Report(Status::parse(payload)?)),
+      _ => Err(LinkError::UnknownCommand),
+    }
+  }
+
+  /// Writes framed packet into the buffer, returns frame length.
+  pub fn encode(&self, buf: &mut [u8]) -> Result<usize, LinkError> {
+    let (cmd, payload_len) = match self {
+      Packet::Start { .. } => (CMD_START, 6),
+      Packet::Data { data, .. } => (CMD_DATA, data.len() + 2),
+      Packet::Commit => (CMD_COMMIT, 0),
+      Packet::Save { name, .. } => (CMD_SAVE, name.len() + 1),
+      Packet::Select(_) => (CMD_SELECT, 1),
+      Packet::Next => (CMD_NEXT, 0),
+      Packet::Prev => (CMD_PREV, 0),
+      Packet::SetDefault(_) => (CMD_SET_DEFAULT, 1),
+      Packet::Status => (CMD_STATUS, 0),
+      Packet::Pause => (CMD_PAUSE, 0),
+      Packet::Resume => (CMD_RESUME, 0),
+      Packet::Step => (CMD_STEP, 0),
+      Packet::Read { .. } => (CMD_READ, 3),
+      Packet::Write { data, .. } => (CMD_WRITE, data.len() + 2),
+      Packet::Ack => (CMD_ACK, 0),
+      Packet::Nack(_) => (CMD_NACK, 1),
+      Packet::Report(_) => (CMD_REPORT, STATUS_LEN),
+      Packet::Memory { data, .. } => (CMD_MEMORY, data.len() + 2),
+    };
+    if payload_len > MAX_PAYLOAD {
+      return Err(LinkError::BadLength);
+    }
+    let frame_len = payload_len + 6;
+    if buf.len() < frame_len {
+      return Err(LinkError::BufferOverflow);
+    }
+    buf[0] = SYNC;
+    buf[1] = cmd;
+    BigEndian::write_u16(&mut buf[2..4], payload_len as u16);
+    let payload = &mut buf[4..(4 + payload_len)];
+    match self {
+      Packet::Start { size, crc } => {
+        BigEndian::write_u16(&mut payload[0..2], *size);
+        BigEndian::write_u32(&mut payload[2..6], *crc);
+      }
+      Packet::Data { offset: addr, data }
+      | Packet::Write { addr, data }
+      | Packet::Memory { addr, data } => {
+        BigEndian::write_u16(&mut payload[0..2], *addr);
+        payload[2..].copy_from_slice(data);
+      }
+      Packet::Read { addr, len } => {
+        BigEndian::write_u16(&mut payload[0..2], *addr);
+        payload[2] = *len;
+      }
+      Packet::Report(status) => status.encode(payload),
+      Packet::Save { slot, name } => {
+        payload[0] = *slot;
+        payload[1..].copy_from_slice(name);
+      }
+      Packet::Select(slot) | Packet::SetDefault(slot) => {
+        payload[0] = *slot;
+      }
+      Packet::Nack(reason) => {
+        payload[0] = *reason as u8;
+      }
+      Packet::Commit
+      | Packet::Next
+      | Packet::Prev
+      | Packet::Status
+      | Packet::Pause
+      | Packet::Resume
+      | Packet::Step
+      | Packet::Ack => {}
+    }
+    let crc = crc16(&buf[1..(4 + payload_len)]);
+    BigEndian::write_u16(&mut buf[(4 + payload_len)..frame_len], crc);
+    Ok(frame_len)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecoderState {
+  Sync,
+  Header,
+  Payload,
+  Checksum,
+}
+
+pub struct Decoder {
+  state: DecoderState,
+  buf: [u8; MAX_FRAME],
+  len: usize,
+  payload_len: usize,
+}
+
+impl Default for Decoder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Decoder {
+  pub fn new() -> Self {
+    Decoder {
+      state: DecoderState::Sync,
+      buf: [0; MAX_FRAME],
+      len: 0,
+      payload_len: 0,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.state = DecoderState::Sync;
+    self.len = 0;
+  }
+
+  /// Feeds received byte, returns a packet once a complete frame is received.
+  pub fn feed(&mut self, byte: u8) -> Option<Result<Packet<'_>, LinkError>> {
+    match self.state {
+      DecoderState::Sync => {
+        if byte == SYNC {
+          self.state = DecoderState::Header;
+          self.len = 0;
+        }
+        None
+      }
+      DecoderState::Header => {
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len == 3 {
+          self.payload_len = BigEndian::read_u16(&self.buf[1..3]) as usize;
+          if self.payload_len > MAX_PAYLOAD {
+            self.reset();
+            return Some(Err(LinkError::BadLength));
+          }
+          self.state = if self.payload_len > 0 {
+            DecoderState::Payload
+          } else {
+            DecoderState::Checksum
+          };
+        }
+        None
+      }
+      DecoderState::Payload => {
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len == 3 + self.payload_len {
+          self.state = DecoderState::Checksum;
+        }
+        None
+      }
+      DecoderState::Checksum => {
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < 5 + self.payload_len {
+          return None;
+        }
+        self.state = DecoderState::Sync;
+        let end = 3 + self.payload_len;
+        if crc16(&self.buf[..end]) != BigEndian::read_u16(&self.buf[end..(end + 2)]) {
+          return Some(Err(LinkError::BadChecksum));
+        }
+        Some(Packet::parse(self.buf[0], &self.buf[3..end]))
+      }
+    }
+  }
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+  let mut crc: u16 = 0xffff;
+  for byte in data {
+    crc ^= (*byte as u16) << 8;
+    for _ in 0..8 {
+      crc = if crc & 0x8000 != 0 {
+        (crc << 1) ^ 0x1021
+      } else {
+        crc << 1
+      };
+    }
+  }
+  crc
+}
+
+/// CRC-32/ISO-HDLC, same as zlib and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+  !crc32_update(0xffff_ffff, data)
+}
+
+/// Feeds more data into a running CRC-32 register, for data that does not fit into one buffer.
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+  for byte in data {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 {
+        (crc >> 1) ^ 0xedb8_8320
+      } else {
+        crc >> 1
+      };
+    }
+  }
+  crc
+}
@@ -0,0 +1,150 @@
+/// Lookup table entry of a panel position without LED.
+pub const NO_LED: u16 = 0xffff;
+
+/// Order LEDs are chained in on a panel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wiring {
+  /// Rows left to right, top to bottom.
+  Rows,
+  /// Rows alternating direction, the first one left to right.
+  Serpentine,
+  /// Columns top to bottom, left to right.
+  Columns,
+  /// Columns alternating direction, the first one top to bottom.
+  ColumnSerpentine,
+  /// Big endian 16-bit LED indices of panel positions in row order, same as `.half` data.
+  Lut,
+}
+
+const WIRINGS: [Wiring; 5] = [
+  Wiring::Rows,
+  Wiring::Serpentine,
+  Wiring::Columns,
+  Wiring::ColumnSerpentine,
+  Wiring::Lut,
+];
+
+/// Mapping of 2D coordinates to LED indices of a matrix panel.
+///
+/// Programs see the panel rotated clockwise by `rotation` quarter turns, then flipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix {
+  /// Panel width as wired, before rotation.
+  pub width: u16,
+  /// Panel height as wired, before rotation.
+  pub height: u16,
+  pub wiring: Wiring,
+  pub rotation: u8,
+  pub flip_x: bool,
+  pub flip_y: bool,
+}
+
+impl Matrix {
+  pub const fn new(width: u16, height: u16, wiring: Wiring) -> Self {
+    Matrix {
+      width,
+      height,
+      wiring,
+      rotation: 0,
+      flip_x: false,
+      flip_y: false,
+    }
+  }
+
+  /// Parses panel size followed by options, like `16x16,serpentine,rot90,flipx`.
+  pub fn parse(spec: &str) -> Option<Self> {
+    let mut parts = spec.split(',').map(str::trim);
+    let (width, height) = parts.next()?.split_once('x')?;
+    let mut matrix = Matrix::new(width.parse().ok()?, height.parse().ok()?, Wiring::Rows);
+    for part in parts {
+      match part {
+        "rows" => matrix.wiring = Wiring::Rows,
+        "serpentine" => matrix.wiring = Wiring::Serpentine,
+        "columns" => matrix.wiring = Wiring::Columns,
+        "column-serpentine" => matrix.wiring = Wiring::ColumnSerpentine,
+        "lut" => matrix.wiring = Wiring::Lut,
+        "rot90" => matrix.rotation = 1,
+        "rot180" => matrix.rotation = 2,
+        "rot270" => matrix.rotation = 3,
+        "flipx" => matrix.flip_x = true,
+        "flipy" => matrix.flip_y = true,
+        _ => return None,
+      }
+    }
+    Some(matrix)
+  }
+
+  /// Decodes `MATRIX` ecall flags: wiring in bits 0-2, quarter turns in bits 3-4, then flip x and y.
+  pub fn from_flags(width: u16, height: u16, flags: u32) -> Option<Self> {
+    let wiring = *WIRINGS.get((flags & 0x7) as usize)?;
+    if flags >> 7 != 0 {
+      return None;
+    }
+    Some(Matrix {
+      width,
+      height,
+      wiring,
+      rotation: ((flags >> 3) & 0x3) as u8,
+      flip_x: flags & 0x20 != 0,
+      flip_y: flags & 0x40 != 0,
+    })
+  }
+
+  pub fn flags(self) -> u32 {
+    let wiring = WIRINGS.iter().position(|w| *w == self.wiring).unwrap() as u32;
+    wiring
+      | (self.rotation as u32 & 0x3) << 3
+      | (self.flip_x as u32) << 5
+      | (self.flip_y as u32) << 6
+  }
+
+  /// Width and height as programs see them, after rotation.
+  pub fn size(self) -> (u16, u16) {
+    if self.rotation % 2 == 1 {
+      (self.height, self.width)
+    } else {
+      (self.width, self.height)
+    }
+  }
+
+  /// LED index at program coordinates, `lut` is only read by `Wiring::Lut`.
+  pub fn xy(self, x: i32, y: i32, lut: &[u8]) -> Option<usize> {
+    let (px, py) = self.panel(x, y)?;
+    let (width, height) = (self.width as usize, self.height as usize);
+    let idx = match self.wiring {
+      Wiring::Rows => py * width + px,
+      Wiring::Serpentine if py % 2 == 1 => py * width + width - 1 - px,
+      Wiring::Serpentine => py * width + px,
+      Wiring::Columns => px * height + py,
+      Wiring::ColumnSerpentine if px % 2 == 1 => px * height + height - 1 - py,
+      Wiring::ColumnSerpentine => px * height + py,
+      Wiring::Lut => {
+        let offset = (py * width + px) * 2;
+        let entry = lut.get(offset..(offset + 2))?;
+        match u16::from_be_bytes([entry[0], entry[1]]) {
+          NO_LED => return None,
+          idx => idx as usize,
+        }
+      }
+    };
+    Some(idx)
+  }
+
+  /// Panel position of program coordinates.
+  fn panel(self, x: i32, y: i32) -> Option<(usize, usize)> {
+    let (width, height) = self.size();
+    let (width, height) = (width as i32, height as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+      return None;
+    }
+    let x = if self.flip_x { width - 1 - x } else { x };
+    let y = if self.flip_y { height - 1 - y } else { y };
+    let (px, py) = match self.rotation & 0x3 {
+      0 => (x, y),
+      1 => (y, self.height as i32 - 1 - x),
+      2 => (self.width as i32 - 1 - x, self.height as i32 - 1 - y),
+      _ => (self.width as i32 - 1 - y, x),
+    };
+    Some((px as usize, py as usize))
+  }
+}
@@ -0,0 +1,233 @@
+use crate::color::Rgb;
+use crate::pixel::{widen, Channels, Levels};
+
+/// Gamma of 1.0 in tenths, keeps LED RAM values as they are.
+pub const LINEAR_GAMMA: u8 = 10;
+
+/// Typical WS2812 draw of a fully lit channel.
+pub const DEFAULT_CHANNEL_MA: u16 = 20;
+/// Typical WS2812 draw of a dark LED.
+pub const DEFAULT_IDLE_MA: u16 = 1;
+
+/// Temporal dithering thresholds, bit reversed so consecutive frames spread evenly.
+const DITHER: [u16; 8] = [0x10, 0x90, 0x50, 0xd0, 0x30, 0xb0, 0x70, 0xf0];
+
+/// Current draw model of the strip and the supply it runs from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerBudget {
+  /// Draw of one channel at full level.
+  pub channel_ma: u16,
+  /// Draw of one LED with all channels off.
+  pub idle_ma: u16,
+  /// Supply limit, zero for none.
+  pub limit_ma: u32,
+}
+
+/// Estimated draw of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Power {
+  /// Draw of the frame as rendered.
+  pub ma: u32,
+  /// Draw after scaling down to the supply limit.
+  pub limited_ma: u32,
+  /// Scale applied to the frame to stay within the limit, 255 when it already fits.
+  pub scale: u8,
+}
+
+impl PowerBudget {
+  /// Estimates draw of `leds` LEDs with channel levels adding up to `levels`.
+  pub fn estimate(&self, leds: usize, levels: u32) -> Power {
+    let idle = leds as u32 * self.idle_ma as u32;
+    let drive = (levels as u64 * self.channel_ma as u64 / 255) as u32;
+    let ma = idle + drive;
+    if self.limit_ma == 0 || ma <= self.limit_ma {
+      return Power {
+        ma,
+        limited_ma: ma,
+        scale: 255,
+      };
+    }
+    // Dark frames over the limit draw idle current only, there is nothing to scale.
+    if drive == 0 {
+      return Power {
+        ma,
+        limited_ma: idle,
+        scale: 0,
+      };
+    }
+    let scale = (self.limit_ma.saturating_sub(idle) as u64 * 255 / drive as u64) as u8;
+    Power {
+      ma,
+      limited_ma: idle + (drive as u64 * scale as u64 / 255) as u32,
+      scale,
+    }
+  }
+}
+
+impl Default for PowerBudget {
+  fn default() -> Self {
+    PowerBudget {
+      channel_ma: DEFAULT_CHANNEL_MA,
+      idle_ma: DEFAULT_IDLE_MA,
+      limit_ma: 0,
+    }
+  }
+}
+
+/// Output stage between LED RAM and the strip driver.
+///
+/// Every channel goes through its own gamma LUT with 8 fractional bits, then global
+/// brightness. The fraction is either rounded or dithered over consecutive frames. Frames
+/// drawing more than the power budget allows are scaled down as a whole.
+pub struct Pipeline {
+  gamma: [u8; 3],
+  lut: [[u16; 256]; 3],
+  brightness: u8,
+  dither: bool,
+  frame: usize,
+  power: PowerBudget,
+}
+
+impl Pipeline {
+  pub fn new() -> Self {
+    let mut pipeline = Pipeline {
+      gamma: [LINEAR_GAMMA; 3],
+      lut: [[0; 256]; 3],
+      brightness: 255,
+      dither: false,
+      frame: 0,
+      power: PowerBudget::default(),
+    };
+    for lut in pipeline.lut.iter_mut() {
+      fill_lut(lut, LINEAR_GAMMA);
+    }
+    pipeline
+  }
+
+  /// Gamma of the red, green and blue channels in tenths.
+  pub fn gamma(&self) -> [u8; 3] {
+    self.gamma
+  }
+
+  /// Sets per channel gamma in tenths, zero is treated as 0.1.
+  pub fn set_gamma(&mut self, gamma: [u8; 3]) {
+    for (ch, &val) in gamma.iter().enumerate() {
+      let val = val.max(1);
+      if self.gamma[ch] != val {
+        self.gamma[ch] = val;
+        fill_lut(&mut self.lut[ch], val);
+      }
+    }
+  }
+
+  pub fn brightness(&self) -> u8 {
+    self.brightness
+  }
+
+  pub fn set_brightness(&mut self, brightness: u8) {
+    self.brightness = brightness;
+  }
+
+  pub fn dither(&self) -> bool {
+    self.dither
+  }
+
+  pub fn set_dither(&mut self, dither: bool) {
+    self.dither = dither;
+  }
+
+  pub fn power_budget(&self) -> PowerBudget {
+    self.power
+  }
+
+  pub fn set_power_budget(&mut self, power: PowerBudget) {
+    self.power = power;
+  }
+
+  /// Estimates draw of a frame of LED RAM pixels as it leaves the pipeline.
+  pub fn power<I>(&self, pixels: I) -> Power
+  where
+    I: Iterator<Item = Rgb>,
+  {
+    let (mut leds, mut levels) = (0, 0);
+    for rgb in pixels {
+      let (r, g, b) = self.apply(rgb);
+      leds += 1;
+      levels += r as u32 + g as u32 + b as u32;
+    }
+    self.power.estimate(leds, levels)
+  }
+
+  /// Restores linear gamma, full brightness and no dithering, power budget is kept.
+  pub fn reset(&mut self) {
+    self.set_gamma([LINEAR_GAMMA; 3]);
+    self.brightness = 255;
+    self.dither = false;
+    self.frame = 0;
+  }
+
+  /// Moves dithering on to the next frame.
+  pub fn advance(&mut self) {
+    self.frame = (self.frame + 1) % DITHER.len();
+  }
+
+  pub fn apply(&self, rgb: Rgb) -> Rgb {
+    self.apply_scaled(rgb, 255)
+  }
+
+  /// Same as `apply` with brightness scaled down further by `scale / 255`.
+  pub fn apply_scaled(&self, rgb: Rgb, scale: u8) -> Rgb {
+    let threshold = if self.dither {
+      DITHER[self.frame]
+    } else {
+      0x80
+    };
+    let scale = self.brightness as u32 * scale as u32;
+    let channel = |ch: usize, val: u8| {
+      let val = self.lut[ch][val as usize] as u32 * scale / (255 * 255);
+      ((val + threshold as u32) >> 8) as u8
+    };
+    (channel(0, rgb.0), channel(1, rgb.1), channel(2, rgb.2))
+  }
+
+  /// Driver levels of a pixel with `channels`, brightness scaled down further by `scale / 255`.
+  ///
+  /// 8-bit channels round and dither the same as `apply_scaled`, white follows the green curve.
+  /// 16-bit channels interpolate the gamma curve and keep their precision.
+  pub fn apply_levels(&self, levels: Levels, channels: Channels, scale: u8) -> Levels {
+    let [r, g, b, w] = levels;
+    if channels != Channels::Rgb16 {
+      let narrow = |val: u16| (val >> 8) as u8;
+      let rgb = self.apply_scaled((narrow(r), narrow(g), narrow(b)), scale);
+      let white = self.apply_scaled((0, narrow(w), 0), scale).1;
+      let mut levels = widen(rgb);
+      levels[3] = white as u16 * 257;
+      return levels;
+    }
+    let scale = self.brightness as u32 * scale as u32;
+    let channel = |ch: usize, val: u16| {
+      // Position on the 256 entry curve with 8 fractional bits.
+      let pos = val as u32 * 255 * 256 / 0xffff;
+      let (idx, frac) = ((pos >> 8) as usize, pos & 0xff);
+      let lo = self.lut[ch][idx] as u32;
+      let hi = self.lut[ch][(idx + 1).min(255)] as u32;
+      let val = (lo + (hi - lo) * frac / 256) * scale / (255 * 255);
+      (val + (val >> 8)) as u16
+    };
+    [channel(0, r), channel(1, g), channel(2, b), 0]
+  }
+}
+
+impl Default for Pipeline {
+  fn default() -> Self {
+    Pipeline::new()
+  }
+}
+
+fn fill_lut(lut: &mut [u16; 256], gamma: u8) {
+  let gamma = gamma as f32 / 10.0;
+  for (idx, val) in lut.iter_mut().enumerate() {
+    let linear = libm::powf(idx as f32 / 255.0, gamma);
+    *val = libm::roundf(linear * 255.0 * 256.0) as u16;
+  }
+}
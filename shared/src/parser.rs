@@ -1,12 +1,15 @@
 use crate::*;
 use lalrpop_util::lalrpop_mod;
 
-lalrpop_mod!(grammar);
+lalrpop_mod!(
+  #[allow(clippy::all)]
+  grammar
+);
 
 pub type Exprs<'a> = Vec<Exp<'a>>;
 pub type Parser = grammar::StripParser;
 
-pub fn parse(code: &str) -> Result<Exprs, Error> {
+pub fn parse(code: &str) -> Result<Exprs<'_>, Error> {
   Parser::new().parse(code).map_err(|err| {
     println!("Parser error: {:?}", err);
     Error::ParseError
@@ -22,6 +25,7 @@ pub enum Directive<'a> {
   Word(Vec<u32>),
   IncBin(&'a str),
   Zero(i16),
+  Format(&'a str),
 }
 
 #[derive(Debug)]
@@ -0,0 +1,228 @@
+use crate::color::Rgb;
+
+/// Largest pixel in LED RAM, 16 bits per channel.
+pub const MAX_PIXEL_SIZE: usize = 6;
+
+/// Red, green, blue and white levels of a pixel at 16 bits, whatever the width in LED RAM.
+pub type Levels = [u16; 4];
+
+/// Levels of an 8-bit RGB pixel without white.
+pub fn widen(rgb: Rgb) -> Levels {
+  let wide = |val: u8| val as u16 * 257;
+  [wide(rgb.0), wide(rgb.1), wide(rgb.2), 0]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorOrder {
+  RGB,
+  RBG,
+  GRB,
+  GBR,
+  BRG,
+  BGR,
+}
+
+const ORDERS: [ColorOrder; 6] = [
+  ColorOrder::RGB,
+  ColorOrder::RBG,
+  ColorOrder::GRB,
+  ColorOrder::GBR,
+  ColorOrder::BRG,
+  ColorOrder::BGR,
+];
+
+impl ColorOrder {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "rgb" | "RGB" => Some(ColorOrder::RGB),
+      "rbg" | "RBG" => Some(ColorOrder::RBG),
+      "grb" | "GRB" => Some(ColorOrder::GRB),
+      "gbr" | "GBR" => Some(ColorOrder::GBR),
+      "brg" | "BRG" => Some(ColorOrder::BRG),
+      "bgr" | "BGR" => Some(ColorOrder::BGR),
+      _ => None,
+    }
+  }
+
+  pub fn encode(self, rgb: Rgb, buf: &mut [u8]) {
+    buf[..3].copy_from_slice(&self.permute(rgb));
+  }
+
+  pub fn decode(self, buf: &[u8]) -> Rgb {
+    self.unpermute([buf[0], buf[1], buf[2]])
+  }
+
+  /// Channels in wire order.
+  pub fn permute<T: Copy>(self, rgb: (T, T, T)) -> [T; 3] {
+    let (r, g, b) = rgb;
+    match self {
+      ColorOrder::RGB => [r, g, b],
+      ColorOrder::RBG => [r, b, g],
+      ColorOrder::GRB => [g, r, b],
+      ColorOrder::GBR => [g, b, r],
+      ColorOrder::BRG => [b, r, g],
+      ColorOrder::BGR => [b, g, r],
+    }
+  }
+
+  /// Inverse of `permute`.
+  pub fn unpermute<T: Copy>(self, wire: [T; 3]) -> (T, T, T) {
+    let [x, y, z] = wire;
+    match self {
+      ColorOrder::RGB => (x, y, z),
+      ColorOrder::RBG => (x, z, y),
+      ColorOrder::GRB => (y, x, z),
+      ColorOrder::GBR => (z, x, y),
+      ColorOrder::BRG => (y, z, x),
+      ColorOrder::BGR => (z, y, x),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channels {
+  /// 8-bit red, green and blue.
+  Rgb,
+  /// 8-bit red, green and blue followed by 8-bit white, as on SK6812.
+  Rgbw,
+  /// Big endian 16-bit red, green and blue.
+  Rgb16,
+}
+
+/// Layout of a pixel in LED RAM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelFormat {
+  pub order: ColorOrder,
+  pub channels: Channels,
+}
+
+impl PixelFormat {
+  pub const fn new(order: ColorOrder, channels: Channels) -> Self {
+    PixelFormat { order, channels }
+  }
+
+  /// Parses color order with optional `w` or `16` suffix, like `grbw` or `rgb16`.
+  pub fn parse(name: &str) -> Option<Self> {
+    let (order, channels) = if let Some(order) = name.strip_suffix("16") {
+      (order, Channels::Rgb16)
+    } else if let Some(order) = name.strip_suffix(|c| c == 'w' || c == 'W') {
+      (order, Channels::Rgbw)
+    } else {
+      (name, Channels::Rgb)
+    };
+    ColorOrder::parse(order).map(|order| PixelFormat::new(order, channels))
+  }
+
+  /// Format byte of the program header, color order in the low nibble and channels in the high one.
+  pub fn from_byte(byte: u8) -> Option<Self> {
+    let order = *ORDERS.get((byte & 0xf) as usize)?;
+    let channels = match byte >> 4 {
+      0 => Channels::Rgb,
+      1 => Channels::Rgbw,
+      2 => Channels::Rgb16,
+      _ => return None,
+    };
+    Some(PixelFormat::new(order, channels))
+  }
+
+  pub fn to_byte(self) -> u8 {
+    let order = ORDERS
+      .iter()
+      .position(|order| *order == self.order)
+      .unwrap() as u8;
+    let channels = match self.channels {
+      Channels::Rgb => 0,
+      Channels::Rgbw => 1,
+      Channels::Rgb16 => 2,
+    };
+    channels << 4 | order
+  }
+
+  /// Bytes per pixel.
+  pub fn size(self) -> usize {
+    match self.channels {
+      Channels::Rgb => 3,
+      Channels::Rgbw => 4,
+      Channels::Rgb16 => 6,
+    }
+  }
+
+  /// Writes pixel, RGBW moves the common part of the channels to white.
+  pub fn encode(self, rgb: Rgb, buf: &mut [u8]) {
+    match self.channels {
+      Channels::Rgb => self.order.encode(rgb, buf),
+      Channels::Rgbw => {
+        let white = rgb.0.min(rgb.1).min(rgb.2);
+        self
+          .order
+          .encode((rgb.0 - white, rgb.1 - white, rgb.2 - white), buf);
+        buf[3] = white;
+      }
+      Channels::Rgb16 => {
+        let wide = |val: u8| val as u16 * 257;
+        let wire = self.order.permute((wide(rgb.0), wide(rgb.1), wide(rgb.2)));
+        for (idx, val) in wire.iter().enumerate() {
+          buf[idx * 2..idx * 2 + 2].copy_from_slice(&val.to_be_bytes());
+        }
+      }
+    }
+  }
+
+  /// Reads pixel as 8-bit RGB, white is added to every channel.
+  pub fn decode(self, buf: &[u8]) -> Rgb {
+    match self.channels {
+      Channels::Rgb => self.order.decode(buf),
+      Channels::Rgbw => {
+        let (r, g, b) = self.order.decode(buf);
+        let white = buf[3];
+        (
+          r.saturating_add(white),
+          g.saturating_add(white),
+          b.saturating_add(white),
+        )
+      }
+      Channels::Rgb16 => self.order.unpermute([buf[0], buf[2], buf[4]]),
+    }
+  }
+
+  /// Reads pixel at full precision, 8-bit channels are widened and white is kept apart.
+  pub fn decode_levels(self, buf: &[u8]) -> Levels {
+    match self.channels {
+      Channels::Rgb => widen(self.order.decode(buf)),
+      Channels::Rgbw => {
+        let mut levels = widen(self.order.decode(buf));
+        levels[3] = buf[3] as u16 * 257;
+        levels
+      }
+      Channels::Rgb16 => {
+        let wide = |idx: usize| u16::from_be_bytes([buf[idx], buf[idx + 1]]);
+        let (r, g, b) = self.order.unpermute([wide(0), wide(2), wide(4)]);
+        [r, g, b, 0]
+      }
+    }
+  }
+
+  /// Writes `format.size()` bytes of pixel levels, 8-bit channels take the high byte.
+  pub fn encode_levels(self, levels: Levels, buf: &mut [u8]) {
+    let [r, g, b, w] = levels;
+    let wire = self.order.permute((r, g, b));
+    match self.channels {
+      Channels::Rgb | Channels::Rgbw => {
+        for (idx, val) in wire.iter().chain(&[w]).enumerate().take(self.size()) {
+          buf[idx] = (val >> 8) as u8;
+        }
+      }
+      Channels::Rgb16 => {
+        for (idx, val) in wire.iter().enumerate() {
+          buf[idx * 2..idx * 2 + 2].copy_from_slice(&val.to_be_bytes());
+        }
+      }
+    }
+  }
+}
+
+impl From<ColorOrder> for PixelFormat {
+  fn from(order: ColorOrder) -> Self {
+    PixelFormat::new(order, Channels::Rgb)
+  }
+}
@@ -0,0 +1,115 @@
+use crate::driver::{Driver, Encoder};
+use crate::input::Input;
+use crate::led::LedEnv;
+use crate::link::Packet;
+use crate::supervisor::Supervisor;
+use crate::telemetry::{Monitor, Program};
+use crate::vm::{VMError, VM};
+
+/// Byte streams of strip channels, SPI buses on the device.
+pub trait Output {
+  type Error;
+
+  fn write(&mut self, ch: usize, byte: u8) -> Result<(), Self::Error>;
+}
+
+/// Strip runtime driven by the device timer: frame scheduling with the program prescaler, the
+/// supervised VM and the output pipeline down to chipset bytes.
+///
+/// Programs are loaded by the device, which owns program memory and the link.
+pub struct Runtime<'prog, B, O, I>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  vm: VM<'prog, LedEnv<B>>,
+  supervisor: Supervisor,
+  monitor: Monitor,
+  driver: Driver,
+  output: O,
+  input: I,
+}
+
+impl<'prog, B, O, I> Runtime<'prog, B, O, I>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+  O: Output,
+  I: Input,
+{
+  pub fn new(env: LedEnv<B>, driver: Driver, output: O, input: I) -> Self {
+    Runtime {
+      vm: VM::new(env),
+      supervisor: Supervisor::default(),
+      monitor: Monitor::new(),
+      driver,
+      output,
+      input,
+    }
+  }
+
+  /// Runs a program from the next frame on.
+  pub fn load(&mut self, prog: &'prog [u8]) -> Result<(), VMError> {
+    self.vm.load(prog)?;
+    self.supervisor.reset();
+    self.monitor.reset();
+    Ok(())
+  }
+
+  /// Called every timer tick, renders and sends a frame when one is due. Returns whether it did.
+  pub fn refresh(&mut self) -> bool {
+    // Program time stands still while paused.
+    if !self.monitor.is_running() || !self.vm.get_env().tick() {
+      return false;
+    }
+    self.vm.get_env().sample_input(&mut self.input);
+    // Faults keep whatever was drawn so far, repeated ones fall back to the safe program.
+    let ops = self.supervisor.spin(&mut self.vm).ok();
+    self.monitor.record(ops);
+    let env = self.vm.get_env();
+    env.finish_frame();
+    for ch in 0..env.channels() {
+      let output = &mut self.output;
+      let channels = env.channel_format(ch).channels;
+      self
+        .driver
+        .encode_levels(channels, env.channel_levels(ch), |byte| {
+          output.write(ch, byte)
+        })
+        .ok();
+    }
+    true
+  }
+
+  /// Handles a monitor command, `None` for packets of other protocols.
+  pub fn handle(&mut self, packet: Packet, program: Program) -> Option<Packet<'_>> {
+    let env = self.vm.get_env();
+    self.monitor.handle(packet, env, &self.supervisor, program)
+  }
+
+  pub fn set_spin_us(&mut self, spin_us: u32) {
+    self.monitor.set_spin_us(spin_us);
+  }
+
+  pub fn env(&mut self) -> &mut LedEnv<B> {
+    self.vm.get_env()
+  }
+
+  pub fn supervisor(&self) -> &Supervisor {
+    &self.supervisor
+  }
+
+  pub fn supervisor_mut(&mut self) -> &mut Supervisor {
+    &mut self.supervisor
+  }
+
+  pub fn monitor(&self) -> &Monitor {
+    &self.monitor
+  }
+
+  pub fn output(&self) -> &O {
+    &self.output
+  }
+
+  pub fn output_mut(&mut self) -> &mut O {
+    &mut self.output
+  }
+}
@@ -0,0 +1,393 @@
+use crate::link::{crc32, crc32_update};
+use byteorder::{BigEndian, ByteOrder};
+use core::ops::Range;
+
+pub const MAX_SLOTS: usize = 8;
+pub const NAME_LEN: usize = 16;
+pub const MAX_WRITE_SIZE: usize = 16;
+
+const TABLE_MAGIC: u32 = 0x5354_5250;
+const ENTRY_SIZE: usize = 2 + 4 + 4 + NAME_LEN;
+const ENTRIES_OFFSET: usize = 10;
+const TABLE_SIZE: usize = 224;
+const TABLE_CRC: usize = TABLE_SIZE - 4;
+const NO_DEFAULT: u8 = 0xff;
+
+/// NOR flash region reserved for program storage, erased bytes read as `0xff`.
+pub trait FlashStorage {
+  type Error;
+
+  /// Erase granularity in bytes.
+  fn page_size(&self) -> usize;
+  /// Program granularity in bytes, write offsets and lengths are multiples of it.
+  fn write_size(&self) -> usize;
+  fn capacity(&self) -> usize;
+  fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+  /// Erases the page starting at `offset`.
+  fn erase(&mut self, offset: usize) -> Result<(), Self::Error>;
+  fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageError<E> {
+  Flash(E),
+  InvalidSlot,
+  Empty,
+  TooLarge,
+  Corrupt,
+}
+
+impl<E> From<E> for StorageError<E> {
+  fn from(err: E) -> Self {
+    StorageError::Flash(err)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlotInfo {
+  len: u16,
+  crc: u32,
+  erases: u32,
+  name: [u8; NAME_LEN],
+}
+
+impl SlotInfo {
+  const EMPTY: SlotInfo = SlotInfo {
+    len: 0,
+    crc: 0,
+    erases: 0,
+    name: [0; NAME_LEN],
+  };
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn len(&self) -> usize {
+    self.len as usize
+  }
+
+  pub fn crc(&self) -> u32 {
+    self.crc
+  }
+
+  /// Number of times the slot pages were erased.
+  pub fn erases(&self) -> u32 {
+    self.erases
+  }
+
+  pub fn name(&self) -> &[u8] {
+    let len = self.name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+    &self.name[..len]
+  }
+
+  fn read(buf: &[u8]) -> Self {
+    let mut name = [0; NAME_LEN];
+    name.copy_from_slice(&buf[10..ENTRY_SIZE]);
+    SlotInfo {
+      len: BigEndian::read_u16(&buf[0..2]),
+      crc: BigEndian::read_u32(&buf[2..6]),
+      erases: BigEndian::read_u32(&buf[6..10]),
+      name,
+    }
+  }
+
+  fn write(&self, buf: &mut [u8]) {
+    BigEndian::write_u16(&mut buf[0..2], self.len);
+    BigEndian::write_u32(&mut buf[2..6], self.crc);
+    BigEndian::write_u32(&mut buf[6..10], self.erases);
+    buf[10..ENTRY_SIZE].copy_from_slice(&self.name);
+  }
+}
+
+/// Slot table: `magic | generation | default slot | slot count | entries | CRC-32`,
+/// kept in two pages written alternately so a torn write leaves the previous table intact.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Table {
+  generation: u32,
+  default: u8,
+  entries: [SlotInfo; MAX_SLOTS],
+}
+
+impl Table {
+  fn parse(buf: &[u8]) -> Option<Self> {
+    if BigEndian::read_u32(&buf[0..4]) != TABLE_MAGIC
+      || BigEndian::read_u32(&buf[TABLE_CRC..TABLE_SIZE]) != crc32(&buf[..TABLE_CRC])
+    {
+      return None;
+    }
+    let mut entries = [SlotInfo::EMPTY; MAX_SLOTS];
+    for (idx, entry) in entries.iter_mut().enumerate() {
+      let offset = ENTRIES_OFFSET + idx * ENTRY_SIZE;
+      *entry = SlotInfo::read(&buf[offset..(offset + ENTRY_SIZE)]);
+    }
+    Some(Table {
+      generation: BigEndian::read_u32(&buf[4..8]),
+      default: buf[8],
+      entries,
+    })
+  }
+
+  fn write(&self, buf: &mut [u8]) {
+    buf.iter_mut().for_each(|b| *b = 0);
+    BigEndian::write_u32(&mut buf[0..4], TABLE_MAGIC);
+    BigEndian::write_u32(&mut buf[4..8], self.generation);
+    buf[8] = self.default;
+    buf[9] = MAX_SLOTS as u8;
+    for (idx, entry) in self.entries.iter().enumerate() {
+      let offset = ENTRIES_OFFSET + idx * ENTRY_SIZE;
+      entry.write(&mut buf[offset..(offset + ENTRY_SIZE)]);
+    }
+    let crc = crc32(&buf[..TABLE_CRC]);
+    BigEndian::write_u32(&mut buf[TABLE_CRC..TABLE_SIZE], crc);
+  }
+}
+
+/// Numbered program slots on top of a flash region: two table pages followed by slot pages.
+pub struct Storage<F> {
+  flash: F,
+  slot_size: usize,
+  slots: usize,
+  table: Table,
+  bank: usize,
+}
+
+impl<F> Storage<F>
+where
+  F: FlashStorage,
+{
+  /// Reads the newest valid slot table, blank or corrupted flash mounts as empty storage.
+  pub fn mount(flash: F, slot_size: usize) -> Result<Self, StorageError<F::Error>> {
+    let page_size = flash.page_size();
+    assert!(TABLE_SIZE <= page_size && flash.write_size() <= MAX_WRITE_SIZE);
+    if slot_size == 0 {
+      return Err(StorageError::InvalidSlot);
+    }
+    let slot_size = slot_size.div_ceil(page_size) * page_size;
+    let slots = (flash.capacity().saturating_sub(page_size * 2) / slot_size).min(MAX_SLOTS);
+
+    let mut buf = [0; TABLE_SIZE];
+    let mut current = None;
+    for bank in 0..2 {
+      flash.read(bank * page_size, &mut buf)?;
+      if let Some(table) = Table::parse(&buf) {
+        match current {
+          Some((_, Table { generation, .. })) if generation >= table.generation => {}
+          _ => current = Some((bank, table)),
+        }
+      }
+    }
+    let (bank, table) = current.unwrap_or((
+      1,
+      Table {
+        generation: 0,
+        default: NO_DEFAULT,
+        entries: [SlotInfo::EMPTY; MAX_SLOTS],
+      },
+    ));
+    Ok(Storage {
+      flash,
+      slot_size,
+      slots,
+      table,
+      bank,
+    })
+  }
+
+  pub fn flash(&self) -> &F {
+    &self.flash
+  }
+
+  pub fn release(self) -> F {
+    self.flash
+  }
+
+  pub fn slots(&self) -> usize {
+    self.slots
+  }
+
+  pub fn slot_size(&self) -> usize {
+    self.slot_size
+  }
+
+  pub fn info(&self, slot: u8) -> Option<&SlotInfo> {
+    self.entry(slot).ok().filter(|entry| !entry.is_empty())
+  }
+
+  /// Slot to run at boot, if it still holds a program.
+  pub fn default_slot(&self) -> Option<u8> {
+    let slot = self.table.default;
+    self.info(slot).map(|_| slot)
+  }
+
+  pub fn set_default(&mut self, slot: u8) -> Result<(), StorageError<F::Error>> {
+    if self.entry(slot)?.is_empty() {
+      return Err(StorageError::Empty);
+    }
+    if self.table.default == slot {
+      return Ok(());
+    }
+    let mut table = self.table;
+    table.default = slot;
+    self.commit(table)
+  }
+
+  /// Next occupied slot after `slot`, wrapping around.
+  pub fn next(&self, slot: Option<u8>) -> Option<u8> {
+    if self.slots == 0 {
+      return None;
+    }
+    let start = slot.map_or(self.slots - 1, |slot| slot as usize);
+    (1..=self.slots)
+      .map(|step| ((start + step) % self.slots) as u8)
+      .find(|slot| self.info(*slot).is_some())
+  }
+
+  /// Previous occupied slot before `slot`, wrapping around.
+  pub fn prev(&self, slot: Option<u8>) -> Option<u8> {
+    let start = slot.map_or(0, |slot| slot as usize);
+    (1..=self.slots)
+      .map(|step| ((start + self.slots - step) % self.slots) as u8)
+      .find(|slot| self.info(*slot).is_some())
+  }
+
+  /// Stores program into the slot, rewrites flash only when the program changed.
+  pub fn store(
+    &mut self,
+    slot: u8,
+    name: &[u8],
+    prog: &[u8],
+  ) -> Result<(), StorageError<F::Error>> {
+    let mut entry = *self.entry(slot)?;
+    if prog.is_empty() {
+      return Err(StorageError::Empty);
+    }
+    if prog.len() > self.slot_size || prog.len() > u16::MAX as usize || name.len() > NAME_LEN {
+      return Err(StorageError::TooLarge);
+    }
+    let crc = crc32(prog);
+    let base = self.slot_base(slot);
+    if entry.len() != prog.len() || entry.crc != crc || self.checksum(base, prog.len())? != crc {
+      let page_size = self.flash.page_size();
+      for page in 0..(self.slot_size / page_size) {
+        self.flash.erase(base + page * page_size)?;
+      }
+      self.write_padded(base, prog)?;
+      if self.checksum(base, prog.len())? != crc {
+        return Err(StorageError::Corrupt);
+      }
+      entry.len = prog.len() as u16;
+      entry.crc = crc;
+      entry.erases += 1;
+    }
+    entry.name = [0; NAME_LEN];
+    entry.name[..name.len()].copy_from_slice(name);
+    if self.table.entries[slot as usize] == entry {
+      return Ok(());
+    }
+    let mut table = self.table;
+    table.entries[slot as usize] = entry;
+    self.commit(table)
+  }
+
+  /// Loads program from the slot into the buffer, returns program length.
+  pub fn load(&self, slot: u8, buf: &mut [u8]) -> Result<usize, StorageError<F::Error>> {
+    let entry = self.entry(slot)?;
+    if entry.is_empty() {
+      return Err(StorageError::Empty);
+    }
+    let len = entry.len();
+    if len > buf.len() {
+      return Err(StorageError::TooLarge);
+    }
+    self.flash.read(self.slot_base(slot), &mut buf[..len])?;
+    if crc32(&buf[..len]) != entry.crc {
+      return Err(StorageError::Corrupt);
+    }
+    Ok(len)
+  }
+
+  /// Checks program in the slot and returns where it lies in the flash region, for flash mapped
+  /// into memory programs can run in place.
+  pub fn locate(&self, slot: u8) -> Result<Range<usize>, StorageError<F::Error>> {
+    let entry = self.entry(slot)?;
+    if entry.is_empty() {
+      return Err(StorageError::Empty);
+    }
+    let start = self.slot_base(slot);
+    if self.checksum(start, entry.len())? != entry.crc {
+      return Err(StorageError::Corrupt);
+    }
+    Ok(start..(start + entry.len()))
+  }
+
+  /// Frees the slot, its pages are erased lazily by the next store.
+  pub fn remove(&mut self, slot: u8) -> Result<(), StorageError<F::Error>> {
+    let entry = self.entry(slot)?;
+    if entry.is_empty() {
+      return Ok(());
+    }
+    let mut table = self.table;
+    table.entries[slot as usize] = SlotInfo {
+      erases: entry.erases,
+      ..SlotInfo::EMPTY
+    };
+    self.commit(table)
+  }
+
+  fn entry(&self, slot: u8) -> Result<&SlotInfo, StorageError<F::Error>> {
+    if slot as usize >= self.slots {
+      return Err(StorageError::InvalidSlot);
+    }
+    Ok(&self.table.entries[slot as usize])
+  }
+
+  fn slot_base(&self, slot: u8) -> usize {
+    self.flash.page_size() * 2 + slot as usize * self.slot_size
+  }
+
+  fn checksum(&self, offset: usize, len: usize) -> Result<u32, StorageError<F::Error>> {
+    let mut crc = 0xffff_ffff;
+    let mut buf = [0; 64];
+    for start in (0..len).step_by(buf.len()) {
+      let chunk = &mut buf[..(len - start).min(64)];
+      self.flash.read(offset + start, chunk)?;
+      crc = crc32_update(crc, chunk);
+    }
+    Ok(!crc)
+  }
+
+  fn write_padded(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError<F::Error>> {
+    let write_size = self.flash.write_size();
+    let aligned = data.len() / write_size * write_size;
+    if aligned > 0 {
+      self.flash.write(offset, &data[..aligned])?;
+    }
+    if aligned < data.len() {
+      let mut tail = [0xff; MAX_WRITE_SIZE];
+      tail[..(data.len() - aligned)].copy_from_slice(&data[aligned..]);
+      self.flash.write(offset + aligned, &tail[..write_size])?;
+    }
+    Ok(())
+  }
+
+  /// Writes `table` to the other bank under the next generation, it replaces the table in RAM
+  /// once flash holds it.
+  fn commit(&mut self, mut table: Table) -> Result<(), StorageError<F::Error>> {
+    let mut buf = [0; TABLE_SIZE];
+    table.generation = self.table.generation + 1;
+    table.write(&mut buf);
+    let bank = self.bank ^ 1;
+    let offset = bank * self.flash.page_size();
+    self.flash.erase(offset)?;
+    self.write_padded(offset, &buf)?;
+    let mut written = [0; TABLE_SIZE];
+    self.flash.read(offset, &mut written)?;
+    if written != buf {
+      return Err(StorageError::Corrupt);
+    }
+    self.table = table;
+    self.bank = bank;
+    Ok(())
+  }
+}
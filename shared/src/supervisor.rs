@@ -0,0 +1,131 @@
+use crate::color::Rgb;
+use crate::led::{LedEnv, TIMER_HZ};
+use crate::vm::{VMError, VM};
+
+/// Program shown after repeated faults, it keeps the strip dark.
+pub const SAFE_PROGRAM: &[u8] = include_bytes!("../../docs/safe.bin");
+/// Instructions a frame may run before the spin is stopped as a fault, whatever its period.
+pub const DEFAULT_BUDGET: u32 = 50_000;
+/// Core clock cycles a VM instruction takes on a Cortex-M0+, estimated with headroom from the
+/// fetch, decode and bounds checks of the interpreter. Ecalls over LED ranges take longer.
+pub const CYCLES_PER_OP: u32 = 64;
+/// Faults in a row that make the supervisor fall back to `SAFE_PROGRAM`.
+pub const DEFAULT_MAX_FAULTS: u32 = 3;
+/// LEDs at the start of the strip the fault pattern is drawn on.
+pub const FAULT_LEDS: usize = 4;
+/// Fault pattern blinks with this period.
+const BLINK_MS: u32 = 1000;
+
+/// Keeps a faulting or runaway program from freezing the strip.
+///
+/// Every frame is spun with an instruction budget. A spin that errors or runs out of budget is a
+/// fault, the frame keeps whatever was drawn so far. Once `max_faults` happen in a row the program
+/// is replaced with `SAFE_PROGRAM` and the first LEDs blink the fault until another program loads.
+pub struct Supervisor {
+  pub budget: u32,
+  /// Instructions per prescaler tick, limits each frame to its own period when set.
+  pub tick_budget: Option<u32>,
+  pub max_faults: u32,
+  faults: u32,
+  streak: u32,
+  last_error: Option<VMError>,
+  fallback: Option<VMError>,
+}
+
+impl Supervisor {
+  pub const fn new(budget: u32, max_faults: u32) -> Self {
+    Supervisor {
+      budget,
+      tick_budget: None,
+      max_faults,
+      faults: 0,
+      streak: 0,
+      last_error: None,
+      fallback: None,
+    }
+  }
+
+  /// Spins a frame, returns instructions run by a program that halted in time.
+  pub fn spin<'prog, B>(&mut self, vm: &mut VM<'prog, LedEnv<B>>) -> Result<u32, VMError>
+  where
+    B: AsRef<[u8]> + AsMut<[u8]>,
+  {
+    let psc = vm.get_env().prescaler().max(1);
+    let budget = match self.tick_budget {
+      Some(ops) => ops.saturating_mul(psc).min(self.budget),
+      None => self.budget,
+    };
+    let res = vm.respin_budget(budget);
+    match res {
+      Ok(_) => self.streak = 0,
+      Err(err) => {
+        self.faults = self.faults.saturating_add(1);
+        self.streak += 1;
+        self.last_error = Some(err);
+        if self.fallback.is_none() && self.streak >= self.max_faults {
+          self.fallback = Some(err);
+          vm.load(SAFE_PROGRAM).ok();
+        }
+      }
+    }
+    if let Some(err) = self.fallback {
+      show_fault(vm.get_env(), err);
+    }
+    res
+  }
+
+  /// Starts supervising a newly loaded program, the fault count is kept.
+  pub fn reset(&mut self) {
+    self.streak = 0;
+    self.fallback = None;
+  }
+
+  /// Faults since power on.
+  pub fn faults(&self) -> u32 {
+    self.faults
+  }
+
+  pub fn last_error(&self) -> Option<VMError> {
+    self.last_error
+  }
+
+  /// Fault the supervisor fell back to the safe program for.
+  pub fn fallback(&self) -> Option<VMError> {
+    self.fallback
+  }
+}
+
+/// Instructions a core clocked at `cpu_hz` runs in one prescaler tick.
+pub const fn tick_budget(cpu_hz: u32) -> u32 {
+  cpu_hz / TIMER_HZ / CYCLES_PER_OP
+}
+
+impl Default for Supervisor {
+  fn default() -> Self {
+    Supervisor::new(DEFAULT_BUDGET, DEFAULT_MAX_FAULTS)
+  }
+}
+
+/// Fault pattern: red, dark, red and a color telling the fault apart, blinking once a second.
+pub fn fault_pattern(err: VMError, elapsed_ms: u32) -> [Rgb; FAULT_LEDS] {
+  if elapsed_ms % BLINK_MS >= BLINK_MS / 2 {
+    return [(0, 0, 0); FAULT_LEDS];
+  }
+  let kind = match err {
+    VMError::Timeout => (64, 0, 64),
+    VMError::EnvFault => (64, 32, 0),
+    VMError::InvalidProg => (0, 0, 64),
+    VMError::EmptyProg => (64, 64, 64),
+  };
+  [(64, 0, 0), (0, 0, 0), (64, 0, 0), kind]
+}
+
+fn show_fault<B>(env: &mut LedEnv<B>, err: VMError)
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  let pattern = fault_pattern(err, env.elapsed_ms());
+  for (idx, rgb) in pattern.iter().enumerate().take(env.leds()) {
+    env.set_pixel(idx, *rgb);
+  }
+}
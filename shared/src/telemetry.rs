@@ -0,0 +1,260 @@
+use crate::led::LedEnv;
+use crate::link::{LinkError, Nack, Packet, CHUNK_SIZE};
+use crate::storage::NAME_LEN;
+use crate::supervisor::Supervisor;
+use crate::vm::{Env, VMError};
+use byteorder::{BigEndian, ByteOrder};
+
+/// Most memory bytes a single read returns.
+pub const MAX_READ: usize = CHUNK_SIZE;
+/// Encoded length of `Status`.
+pub const STATUS_LEN: usize = NAME_LEN + 28;
+/// Slot byte of programs not loaded from a slot.
+const NO_SLOT: u8 = 0xff;
+
+/// Device state reported over the link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Status {
+  /// Slot the program was loaded from, `None` for uploads and built-in programs.
+  pub slot: Option<u8>,
+  /// Slot name, zero padded.
+  pub name: [u8; NAME_LEN],
+  pub paused: bool,
+  /// Running the safe program after repeated faults.
+  pub fallback: bool,
+  /// Frames rendered since the program was loaded.
+  pub frames: u32,
+  /// Instructions per frame of frames that halted in time.
+  pub avg_ops: u32,
+  pub max_ops: u32,
+  /// Duration of the last frame refresh.
+  pub spin_us: u32,
+  /// Faulting frames since power on.
+  pub faults: u32,
+  pub last_error: Option<VMError>,
+  pub brightness: u8,
+  /// Estimated current draw of the shown frame.
+  pub power_ma: u32,
+}
+
+impl Status {
+  /// Program name up to the zero padding.
+  pub fn name(&self) -> &[u8] {
+    let len = self.name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+    &self.name[..len]
+  }
+
+  pub fn parse(payload: &[u8]) -> Result<Self, LinkError> {
+    if payload.len() != STATUS_LEN {
+      return Err(LinkError::BadLength);
+    }
+    let mut name = [0; NAME_LEN];
+    name.copy_from_slice(&payload[1..(NAME_LEN + 1)]);
+    let fields = &payload[(NAME_LEN + 1)..];
+    let word = |idx: usize| BigEndian::read_u32(&fields[(1 + idx * 4)..(5 + idx * 4)]);
+    Ok(Status {
+      slot: Some(payload[0]).filter(|slot| *slot != NO_SLOT),
+      name,
+      paused: fields[0] & 1 != 0,
+      fallback: fields[0] & 2 != 0,
+      frames: word(0),
+      avg_ops: word(1),
+      max_ops: word(2),
+      spin_us: word(3),
+      faults: word(4),
+      last_error: parse_error(fields[21])?,
+      brightness: fields[22],
+      power_ma: BigEndian::read_u32(&fields[23..27]),
+    })
+  }
+
+  /// Writes `STATUS_LEN` bytes of payload.
+  pub fn encode(&self, payload: &mut [u8]) {
+    payload[0] = self.slot.unwrap_or(NO_SLOT);
+    payload[1..(NAME_LEN + 1)].copy_from_slice(&self.name);
+    let fields = &mut payload[(NAME_LEN + 1)..STATUS_LEN];
+    fields[0] = self.paused as u8 | (self.fallback as u8) << 1;
+    let words = [
+      self.frames,
+      self.avg_ops,
+      self.max_ops,
+      self.spin_us,
+      self.faults,
+    ];
+    for (idx, word) in words.iter().enumerate() {
+      BigEndian::write_u32(&mut fields[(1 + idx * 4)..(5 + idx * 4)], *word);
+    }
+    fields[21] = self.last_error.map_or(0, error_code);
+    fields[22] = self.brightness;
+    BigEndian::write_u32(&mut fields[23..27], self.power_ma);
+  }
+}
+
+fn error_code(err: VMError) -> u8 {
+  match err {
+    VMError::EmptyProg => 1,
+    VMError::InvalidProg => 2,
+    VMError::EnvFault => 3,
+    VMError::Timeout => 4,
+  }
+}
+
+fn parse_error(code: u8) -> Result<Option<VMError>, LinkError> {
+  match code {
+    0 => Ok(None),
+    1 => Ok(Some(VMError::EmptyProg)),
+    2 => Ok(Some(VMError::InvalidProg)),
+    3 => Ok(Some(VMError::EnvFault)),
+    4 => Ok(Some(VMError::Timeout)),
+    _ => Err(LinkError::UnknownCommand),
+  }
+}
+
+/// Program the device runs, as reported in `Status`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Program<'a> {
+  pub slot: Option<u8>,
+  pub name: &'a [u8],
+}
+
+/// Device side of the monitor protocol: frame statistics, pausing and memory access.
+///
+/// Paused devices stop the frame timer, so program time only advances for frames requested with
+/// `Step`. Stepping a running device pauses it after the next frame.
+pub struct Monitor {
+  paused: bool,
+  steps: u32,
+  frames: u32,
+  ops: u64,
+  /// Frames that halted in time, the ones instruction counts are known for.
+  counted: u32,
+  max_ops: u32,
+  spin_us: u32,
+  mem: [u8; MAX_READ],
+}
+
+impl Monitor {
+  pub const fn new() -> Self {
+    Monitor {
+      paused: false,
+      steps: 0,
+      frames: 0,
+      ops: 0,
+      counted: 0,
+      max_ops: 0,
+      spin_us: 0,
+      mem: [0; MAX_READ],
+    }
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  /// Whether frames may run, paused devices only run requested steps.
+  pub fn is_running(&self) -> bool {
+    !self.paused || self.steps > 0
+  }
+
+  /// Records a rendered frame with instructions it ran, `None` for a faulting one.
+  pub fn record(&mut self, ops: Option<u32>) {
+    self.frames = self.frames.wrapping_add(1);
+    self.steps = self.steps.saturating_sub(1);
+    if let Some(ops) = ops {
+      self.ops += ops as u64;
+      self.counted += 1;
+      self.max_ops = self.max_ops.max(ops);
+    }
+  }
+
+  pub fn set_spin_us(&mut self, spin_us: u32) {
+    self.spin_us = spin_us;
+  }
+
+  /// Starts statistics over for a newly loaded program, a paused device stays paused.
+  pub fn reset(&mut self) {
+    self.frames = 0;
+    self.ops = 0;
+    self.counted = 0;
+    self.max_ops = 0;
+  }
+
+  pub fn status<B>(&self, env: &LedEnv<B>, supervisor: &Supervisor, program: Program) -> Status
+  where
+    B: AsRef<[u8]> + AsMut<[u8]>,
+  {
+    let mut name = [0; NAME_LEN];
+    let len = program.name.len().min(NAME_LEN);
+    name[..len].copy_from_slice(&program.name[..len]);
+    Status {
+      slot: program.slot,
+      name,
+      paused: self.paused,
+      fallback: supervisor.fallback().is_some(),
+      frames: self.frames,
+      avg_ops: self.ops.checked_div(self.counted as u64).unwrap_or(0) as u32,
+      max_ops: self.max_ops,
+      spin_us: self.spin_us,
+      faults: supervisor.faults(),
+      last_error: supervisor.last_error(),
+      brightness: env.output().brightness(),
+      power_ma: env.power().ma,
+    }
+  }
+
+  /// Handles a monitor command, `None` for packets of other protocols.
+  pub fn handle<'m, B>(
+    &'m mut self,
+    packet: Packet,
+    env: &mut LedEnv<B>,
+    supervisor: &Supervisor,
+    program: Program,
+  ) -> Option<Packet<'m>>
+  where
+    B: AsRef<[u8]> + AsMut<[u8]>,
+  {
+    let reply = match packet {
+      Packet::Status => Packet::Report(self.status(env, supervisor, program)),
+      Packet::Pause => {
+        self.paused = true;
+        self.steps = 0;
+        Packet::Ack
+      }
+      Packet::Resume => {
+        self.paused = false;
+        self.steps = 0;
+        Packet::Ack
+      }
+      Packet::Step => {
+        self.paused = true;
+        self.steps += 1;
+        Packet::Ack
+      }
+      Packet::Read { addr, len } => {
+        let len = len as usize;
+        if len > MAX_READ {
+          return Some(Packet::Nack(Nack::TooLarge));
+        }
+        match env.mem_fetch(addr, &mut self.mem[..len]) {
+          Ok(()) => Packet::Memory {
+            addr,
+            data: &self.mem[..len],
+          },
+          Err(_) => Packet::Nack(Nack::BadAddress),
+        }
+      }
+      Packet::Write { addr, data } => match env.mem_set(addr, data) {
+        Ok(()) => Packet::Ack,
+        Err(_) => Packet::Nack(Nack::BadAddress),
+      },
+      _ => return None,
+    };
+    Some(reply)
+  }
+}
+
+impl Default for Monitor {
+  fn default() -> Self {
+    Monitor::new()
+  }
+}
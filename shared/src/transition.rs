@@ -0,0 +1,71 @@
+/// How the incoming program replaces the outgoing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+  /// Fades every LED at once.
+  Crossfade,
+  /// Sweeps from the first LED to the last.
+  Wipe,
+  /// Switches LEDs one by one in a fixed pseudo-random order.
+  Dissolve,
+}
+
+impl Effect {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "crossfade" => Some(Effect::Crossfade),
+      "wipe" => Some(Effect::Wipe),
+      "dissolve" => Some(Effect::Dissolve),
+      _ => None,
+    }
+  }
+}
+
+/// Switch between two programs over `frames` frames, both keep running meanwhile.
+///
+/// The incoming program is fully shown on the last frame, no frames make it a hard cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+  pub effect: Effect,
+  pub frames: u16,
+}
+
+impl Transition {
+  pub const CUT: Transition = Transition {
+    effect: Effect::Crossfade,
+    frames: 0,
+  };
+
+  /// Parses `EFFECT:FRAMES`, `cut` for no transition.
+  pub fn parse(spec: &str) -> Option<Self> {
+    if spec == "cut" {
+      return Some(Transition::CUT);
+    }
+    let (effect, frames) = spec.split_at(spec.find(':')?);
+    Some(Transition {
+      effect: Effect::parse(effect)?,
+      frames: frames[1..].parse().ok()?,
+    })
+  }
+
+  /// Share of the incoming program at LED `idx` of `leds` on frame `step` from 1 to `frames`.
+  pub fn amount(&self, step: u16, idx: usize, leds: usize) -> u8 {
+    let (step, frames) = (step.min(self.frames) as u32, self.frames.max(1) as u32);
+    let level = (step * 255 / frames) as u8;
+    let shown = match self.effect {
+      Effect::Crossfade => return level,
+      Effect::Wipe => (idx + 1) * frames as usize <= step as usize * leds,
+      Effect::Dissolve => noise(idx) < level,
+    };
+    if shown {
+      255
+    } else {
+      0
+    }
+  }
+}
+
+/// Fixed level from 0 to 254 per LED, spread evenly over LEDs.
+fn noise(idx: usize) -> u8 {
+  let hash = (idx as u32).wrapping_add(1).wrapping_mul(0x9e37_79b9);
+  ((hash >> 24) % 255) as u8
+}
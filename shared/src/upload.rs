@@ -0,0 +1,232 @@
+use crate::link::*;
+use crate::vm::Header;
+
+pub const MAX_RETRIES: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReceiverState {
+  Idle,
+  Receiving {
+    size: usize,
+    crc: u32,
+    received: usize,
+  },
+  Ready {
+    size: usize,
+    taken: bool,
+  },
+}
+
+/// Device side of the upload protocol, collects program into the staging buffer.
+pub struct Receiver<B> {
+  buf: B,
+  state: ReceiverState,
+}
+
+impl<B> Receiver<B>
+where
+  B: AsRef<[u8]> + AsMut<[u8]>,
+{
+  pub fn new(buf: B) -> Self {
+    Receiver {
+      buf,
+      state: ReceiverState::Idle,
+    }
+  }
+
+  pub fn buffer(&self) -> &[u8] {
+    self.buf.as_ref()
+  }
+
+  /// Last committed program, stays available after `take` until the next upload starts.
+  ///
+  /// Uploads are refused with `Nack::Busy` until the program is taken.
+  pub fn program(&self) -> Option<&[u8]> {
+    match self.state {
+      ReceiverState::Ready { size, .. } => Some(&self.buf.as_ref()[..size]),
+      _ => None,
+    }
+  }
+
+  /// Returns committed program length once, the program stays in the staging buffer until next upload.
+  pub fn take(&mut self) -> Option<usize> {
+    match self.state {
+      ReceiverState::Ready { size, taken: false } => {
+        self.state = ReceiverState::Ready { size, taken: true };
+        Some(size)
+      }
+      _ => None,
+    }
+  }
+
+  pub fn handle(&mut self, packet: Result<Packet, LinkError>) -> Packet<'static> {
+    match packet {
+      Ok(packet) => self
+        .handle_packet(packet)
+        .err()
+        .map_or(Packet::Ack, Packet::Nack),
+      Err(_) => Packet::Nack(Nack::BadFrame),
+    }
+  }
+
+  fn handle_packet(&mut self, packet: Packet) -> Result<(), Nack> {
+    match (packet, self.state) {
+      // A committed program is kept until the device takes it.
+      (Packet::Start { .. }, ReceiverState::Ready { taken: false, .. }) => Err(Nack::Busy),
+      (Packet::Start { size, crc }, _) => {
+        let size = size as usize;
+        if size > self.buf.as_ref().len() {
+          self.state = ReceiverState::Idle;
+          return Err(Nack::TooLarge);
+        }
+        self.state = ReceiverState::Receiving {
+          size,
+          crc,
+          received: 0,
+        };
+        Ok(())
+      }
+      (
+        Packet::Data { offset, data },
+        ReceiverState::Receiving {
+          size,
+          crc,
+          received,
+        },
+      ) => {
+        // Chunks below the received mark are retransmits after a lost ack.
+        let offset = offset as usize;
+        let end = offset + data.len();
+        if offset > received {
+          return Err(Nack::OutOfOrder);
+        }
+        if end > size {
+          return Err(Nack::TooLarge);
+        }
+        self.buf.as_mut()[offset..end].copy_from_slice(data);
+        self.state = ReceiverState::Receiving {
+          size,
+          crc,
+          received: received.max(end),
+        };
+        Ok(())
+      }
+      (
+        Packet::Commit,
+        ReceiverState::Receiving {
+          size,
+          crc,
+          received,
+        },
+      ) => {
+        let prog = &self.buf.as_ref()[..size];
+        if received != size || crc32(prog) != crc {
+          return Err(Nack::BadChecksum);
+        }
+        if Header::parse(prog).is_none() {
+          self.state = ReceiverState::Idle;
+          return Err(Nack::BadProgram);
+        }
+        self.state = ReceiverState::Ready { size, taken: false };
+        Ok(())
+      }
+      // Commit retransmitted after a lost ack.
+      (Packet::Commit, ReceiverState::Ready { .. }) => Ok(()),
+      _ => Err(Nack::BadState),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SenderState {
+  Start,
+  Data(usize),
+  Commit,
+  Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadError {
+  TooLarge,
+  Rejected(Nack),
+  RetriesExceeded,
+}
+
+/// Host side of the upload protocol, yields packets to send and advances on device replies.
+pub struct Sender<'a> {
+  prog: &'a [u8],
+  state: SenderState,
+  retries: u8,
+}
+
+impl<'a> Sender<'a> {
+  pub fn new(prog: &'a [u8]) -> Result<Self, UploadError> {
+    if prog.len() > u16::MAX as usize {
+      return Err(UploadError::TooLarge);
+    }
+    Ok(Sender {
+      prog,
+      state: SenderState::Start,
+      retries: 0,
+    })
+  }
+
+  pub fn is_done(&self) -> bool {
+    self.state == SenderState::Done
+  }
+
+  pub fn packet(&self) -> Option<Packet<'a>> {
+    match self.state {
+      SenderState::Start => Some(Packet::Start {
+        size: self.prog.len() as u16,
+        crc: crc32(self.prog),
+      }),
+      SenderState::Data(offset) => {
+        let end = (offset + CHUNK_SIZE).min(self.prog.len());
+        Some(Packet::Data {
+          offset: offset as u16,
+          data: &self.prog[offset..end],
+        })
+      }
+      SenderState::Commit => Some(Packet::Commit),
+      SenderState::Done => None,
+    }
+  }
+
+  /// Handles device reply, `None` stands for a timed out or corrupted reply.
+  pub fn reply(&mut self, reply: Option<Packet>) -> Result<(), UploadError> {
+    match reply {
+      Some(Packet::Ack) => {
+        self.retries = 0;
+        self.state = match self.state {
+          SenderState::Start if self.prog.is_empty() => SenderState::Commit,
+          SenderState::Start => SenderState::Data(0),
+          SenderState::Data(offset) if offset + CHUNK_SIZE < self.prog.len() => {
+            SenderState::Data(offset + CHUNK_SIZE)
+          }
+          SenderState::Data(_) => SenderState::Commit,
+          SenderState::Commit | SenderState::Done => SenderState::Done,
+        };
+        Ok(())
+      }
+      Some(Packet::Nack(reason @ Nack::TooLarge))
+      | Some(Packet::Nack(reason @ Nack::BadProgram)) => Err(UploadError::Rejected(reason)),
+      Some(Packet::Nack(Nack::BadState))
+      | Some(Packet::Nack(Nack::OutOfOrder))
+      | Some(Packet::Nack(Nack::BadChecksum)) => {
+        // Device lost the session, start over.
+        self.state = SenderState::Start;
+        self.retry()
+      }
+      _ => self.retry(),
+    }
+  }
+
+  fn retry(&mut self) -> Result<(), UploadError> {
+    self.retries += 1;
+    if self.retries > MAX_RETRIES {
+      return Err(UploadError::RetriesExceeded);
+    }
+    Ok(())
+  }
+}
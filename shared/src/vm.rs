@@ -1,5 +1,53 @@
 use crate::{Instruction, Opcode, Reg};
 use byteorder::{BigEndian, ByteOrder};
+use core::convert::TryInto;
+
+/// First byte of every program.
+pub const MAGIC: u8 = 0xaf;
+/// Second byte of programs running in the default pixel format of the environment.
+pub const MAGIC_PLAIN: u8 = 0xaf;
+/// Second byte of programs followed by a pixel format byte.
+pub const MAGIC_FORMAT: u8 = 0xb0;
+
+/// Program header: magic, optional pixel format, RAM length and RAM contents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+  pub format: Option<u8>,
+  pub ram_len: usize,
+  /// Header size up to the RAM contents.
+  pub len: usize,
+}
+
+impl Header {
+  /// Parses header, checks that the program holds the whole RAM image.
+  pub fn parse(prog: &[u8]) -> Option<Self> {
+    let (format, len) = match prog {
+      [MAGIC, MAGIC_PLAIN, ..] => (None, 4),
+      [MAGIC, MAGIC_FORMAT, format, ..] => (Some(*format), 5),
+      _ => return None,
+    };
+    if prog.len() < len {
+      return None;
+    }
+    let ram_len = BigEndian::read_u16(&prog[(len - 2)..len]) as usize;
+    if prog.len() < len + ram_len {
+      return None;
+    }
+    Some(Header {
+      format,
+      ram_len,
+      len,
+    })
+  }
+
+  /// Offset of the first instruction.
+  pub fn code_offset(&self) -> usize {
+    self.len + self.ram_len
+  }
+}
+
+/// Argument registers `a0..a7` passed to an ecall, results are written back in place.
+pub type Args = [i32; 8];
 
 pub trait Env {
   type Error;
@@ -8,13 +56,28 @@ pub trait Env {
   fn mem_set(&mut self, addr: u16, val: &[u8]) -> Result<(), Self::Error>;
   fn mem_fetch(&self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error>;
   fn ecall(&mut self, ecall: u8, param: i32) -> Result<i32, Self::Error>;
+
+  /// Called on load of programs declaring a pixel format, after `reset`.
+  fn set_pixel_format(&mut self, format: u8) -> Result<(), Self::Error> {
+    let _ = format;
+    Ok(())
+  }
+
+  /// Same as `ecall` with access to the argument registers, which is what the VM calls.
+  /// Environments without multi-argument ecalls can leave the default.
+  fn ecall_args(&mut self, ecall: u8, param: i32, args: &mut Args) -> Result<i32, Self::Error> {
+    let _ = args;
+    self.ecall(ecall, param)
+  }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VMError {
   EmptyProg,
   InvalidProg,
   EnvFault,
+  /// Program did not halt within its instruction budget.
+  Timeout,
 }
 
 pub struct VM<'prog, E: Env> {
@@ -58,15 +121,19 @@ impl<'prog, E: Env> VM<'prog, E> {
   }
 
   pub fn load(&mut self, prog: &'prog [u8]) -> Result<(), VMError> {
-    if prog.len() < 4 || prog[0] != 0xaf || prog[1] != 0xaf {
-      return Err(VMError::InvalidProg);
-    }
+    let header = Header::parse(prog).ok_or(VMError::InvalidProg)?;
     self.reset();
-    let ram_end = BigEndian::read_u16(&prog[2..4]) as usize + 4;
-    if ram_end > 4 {
+    if let Some(format) = header.format {
       self
         .env
-        .mem_set(0, &prog[4..ram_end])
+        .set_pixel_format(format)
+        .map_err(|_| VMError::InvalidProg)?;
+    }
+    let ram_end = header.code_offset();
+    if header.ram_len > 0 {
+      self
+        .env
+        .mem_set(0, &prog[header.len..ram_end])
         .map_err(|_| VMError::EnvFault)?;
     }
     self.prog = Some(&prog[ram_end..]);
@@ -85,9 +152,12 @@ impl<'prog, E: Env> VM<'prog, E> {
         return Ok(true);
       }
       Opcode::ecall => {
+        let param = self.reg[r3];
+        let a0 = Reg::a0 as usize;
+        let args: &mut Args = (&mut self.reg[a0..(a0 + 8)]).try_into().unwrap();
         let res = self
           .env
-          .ecall(inst.imm as u8, self.reg[r3])
+          .ecall_args(inst.imm as u8, param, args)
           .map_err(|_| VMError::EnvFault)?;
         Some(res)
       }
@@ -225,7 +295,7 @@ impl<'prog, E: Env> VM<'prog, E> {
           .env
           .mem_fetch(offset as u16, &mut buf)
           .map_err(|_| VMError::EnvFault)?;
-        Some(BigEndian::read_i32(&buf) as i32)
+        Some(BigEndian::read_i32(&buf))
       }
     };
     self.pc += 1;
@@ -257,6 +327,17 @@ impl<'prog, E: Env> VM<'prog, E> {
     self.spin()
   }
 
+  /// Runs from the start for at most `budget` instructions, returns how many ran including halt.
+  pub fn respin_budget(&mut self, budget: u32) -> Result<u32, VMError> {
+    self.rewind();
+    for ops in 1..=budget {
+      if self.step()? {
+        return Ok(ops);
+      }
+    }
+    Err(VMError::Timeout)
+  }
+
   fn jump(&mut self, r: usize, offset: i16) -> Result<bool, VMError> {
     match self.prog {
       None => Err(VMError::EmptyProg),
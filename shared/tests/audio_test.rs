@@ -0,0 +1,94 @@
+use strip_shared::audio::*;
+
+fn tone(bin: f32, amplitude: f32, len: usize) -> Vec<i16> {
+  (0..len)
+    .map(|idx| {
+      let phase = 2.0 * std::f32::consts::PI * bin * idx as f32 / FFT_SIZE as f32;
+      (phase.sin() * amplitude) as i16
+    })
+    .collect()
+}
+
+fn loudest(sound: &Sound) -> usize {
+  (0..BANDS).max_by_key(|band| sound.bands[*band]).unwrap()
+}
+
+#[test]
+fn test_fft() {
+  let twiddles: Vec<(i16, i16)> = (0..4)
+    .map(|k| {
+      let angle = 2.0 * std::f32::consts::PI * k as f32 / 8.0;
+      (
+        (angle.cos() * 32767.0).round() as i16,
+        (-angle.sin() * 32767.0).round() as i16,
+      )
+    })
+    .collect();
+  let mut re = [800, 800, 800, 800, 800, 800, 800, 800];
+  let mut im = [0; 8];
+  fft(&mut re, &mut im, &twiddles);
+  assert_eq!(re, [800, 0, 0, 0, 0, 0, 0, 0]);
+  assert_eq!(im, [0; 8]);
+
+  let mut re = [0, 800, 0, -800, 0, 800, 0, -800];
+  let mut im = [0; 8];
+  fft(&mut re, &mut im, &twiddles);
+  assert_eq!(re, [0; 8]);
+  assert_eq!(im, [0, 0, -400, 0, 0, 0, 400, 0]);
+}
+
+#[test]
+fn test_silence() {
+  let mut analyzer = Analyzer::new();
+  assert_eq!(analyzer.analyze(), Sound::default());
+  analyzer.feed(&[0; 100]);
+  assert_eq!(analyzer.analyze(), Sound::default());
+}
+
+#[test]
+fn test_spectrum() {
+  let mut analyzer = Analyzer::new();
+  let mut peaks = vec![];
+  for bin in [2.0, 8.0, 32.0, 100.0].iter() {
+    analyzer.feed(&tone(*bin, 16000.0, FFT_SIZE));
+    let sound = analyzer.analyze();
+    assert!(sound.bands[loudest(&sound)] > 200);
+    peaks.push(loudest(&sound));
+  }
+  assert!(
+    peaks.windows(2).all(|pair| pair[0] < pair[1]),
+    "{:?}",
+    peaks
+  );
+  assert_eq!(peaks[3], BANDS - 1);
+}
+
+#[test]
+fn test_level() {
+  let mut analyzer = Analyzer::new();
+  let levels: Vec<u8> = [500.0, 4000.0, 32000.0]
+    .iter()
+    .map(|amplitude| {
+      analyzer.feed(&tone(8.0, *amplitude, 64));
+      analyzer.analyze().level
+    })
+    .collect();
+  assert!(levels[0] > 0 && levels[0] < levels[1] && levels[1] < levels[2]);
+  assert!(levels[2] > 240);
+  // Only samples fed since the previous frame count.
+  assert_eq!(analyzer.analyze().level, 0);
+}
+
+#[test]
+fn test_beat() {
+  let mut analyzer = Analyzer::new();
+  let mut beats = vec![];
+  for frame in 0..96 {
+    let amplitude = if frame % 24 < 2 { 20000.0 } else { 300.0 };
+    analyzer.feed(&tone(2.0, amplitude, 128));
+    if analyzer.analyze().beat {
+      beats.push(frame);
+    }
+  }
+  assert_eq!(beats, [0, 24, 48, 72]);
+}
@@ -0,0 +1,87 @@
+use strip_shared::color::*;
+
+#[test]
+fn test_spectrum() {
+  assert_eq!(spectrum(0, 255, 255), (255, 0, 0));
+  assert_eq!(spectrum(86, 255, 255), (0, 255, 0));
+  assert_eq!(spectrum(172, 255, 255), (0, 0, 255));
+  assert_eq!(spectrum(42, 0, 128), (128, 128, 128));
+  assert_eq!(spectrum(100, 255, 0), (0, 0, 0));
+}
+
+#[test]
+fn test_rainbow() {
+  assert_eq!(rainbow(0, 255, 255), (255, 0, 0));
+  assert_eq!(rainbow(64, 255, 255), (171, 170, 0));
+  assert_eq!(rainbow(96, 255, 255), (0, 255, 0));
+  assert_eq!(rainbow(160, 255, 255), (0, 0, 255));
+  assert_eq!(rainbow(33, 0, 200), (200, 200, 200));
+  assert_eq!(rainbow(210, 255, 0), (0, 0, 0));
+}
+
+#[test]
+fn test_rgb2hsv() {
+  assert_eq!(rgb2hsv((255, 0, 0)), (0, 255, 255));
+  assert_eq!(rgb2hsv((0, 0, 255)), (172, 255, 255));
+  assert_eq!(rgb2hsv((60, 60, 60)), (0, 0, 60));
+  for hue in 0..=255 {
+    let (back, sat, val) = rgb2hsv(spectrum(hue, 255, 255));
+    assert!((back as i16 - hue as i16).abs() <= 1, "hue {}", hue);
+    assert_eq!((sat, val), (255, 255));
+  }
+}
+
+#[test]
+fn test_hsl() {
+  assert_eq!(hsl2rgb(100, 0, 77), (77, 77, 77));
+  assert_eq!(hsl2rgb(100, 255, 255), (255, 255, 255));
+  assert_eq!(hsl2rgb(100, 255, 0), (0, 0, 0));
+  assert_eq!(rgb2hsl((255, 0, 0)), (0, 255, 127));
+  assert_eq!(rgb2hsl((77, 77, 77)), (0, 0, 77));
+  assert_eq!(rgb2hsl((255, 255, 255)), (0, 0, 255));
+  for &rgb in &[(200, 40, 40), (10, 120, 30), (90, 90, 250)] {
+    let (h, s, l) = rgb2hsl(rgb);
+    let back = hsl2rgb(h, s, l);
+    assert!(
+      (back.0 as i16 - rgb.0 as i16).abs() <= 4,
+      "{:?} {:?}",
+      rgb,
+      back
+    );
+    assert!(
+      (back.1 as i16 - rgb.1 as i16).abs() <= 4,
+      "{:?} {:?}",
+      rgb,
+      back
+    );
+    assert!(
+      (back.2 as i16 - rgb.2 as i16).abs() <= 4,
+      "{:?} {:?}",
+      rgb,
+      back
+    );
+  }
+}
+
+#[test]
+fn test_dim() {
+  assert_eq!(dim((255, 100, 0), 127), (127, 50, 0));
+}
+
+#[test]
+fn test_blending() {
+  assert_eq!(add((200, 10, 0), (100, 10, 0)), (255, 20, 0));
+  assert_eq!(lerp8(10, 20, 0), 10);
+  assert_eq!(lerp8(10, 20, 255), 20);
+  assert_eq!(lerp8(0, 255, 128), 128);
+}
+
+#[test]
+fn test_kelvin() {
+  assert_eq!(kelvin(MIN_KELVIN), (255, 56, 0));
+  assert_eq!(kelvin(0), kelvin(MIN_KELVIN));
+  assert_eq!(kelvin(6500), (255, 249, 253));
+  assert_eq!(kelvin(1250), (255, 82, 0));
+  assert_eq!(kelvin(MAX_KELVIN), (191, 211, 255));
+  assert_eq!(kelvin(u16::MAX), kelvin(MAX_KELVIN));
+}
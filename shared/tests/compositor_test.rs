@@ -0,0 +1,193 @@
+use strip_shared::compiler::compile;
+use strip_shared::compositor::*;
+use strip_shared::input::Input;
+use strip_shared::led::*;
+use strip_shared::parser::parse;
+use strip_shared::transition::*;
+
+// Fills the layer with one color, counting its frames in the red channel of LED 0.
+const FILL: &str = "
+  lb t0 0
+  addi t0 t0 1
+  sb t0 0
+  li t1 0x1000
+  ecall t2 CHANNEL(zero)
+loop:
+  sb t0 0(t1)
+  sb t3 1(t1)
+  sb t4 2(t1)
+  addi t1 t1 3
+  addi t2 t2 -1
+  bnez t2 loop
+";
+
+#[test]
+fn test_blend() {
+  let bottom = (200, 100, 0);
+  let top = (100, 100, 255);
+  assert_eq!(Blend::Normal.apply(bottom, top, 255), top);
+  assert_eq!(Blend::Normal.apply(bottom, top, 0), bottom);
+  assert_eq!(Blend::Normal.apply(bottom, top, 128), (150, 100, 128));
+  assert_eq!(Blend::Add.apply(bottom, top, 255), (255, 200, 255));
+  assert_eq!(Blend::Multiply.apply(bottom, top, 255), (78, 39, 0));
+  assert_eq!(Blend::Max.apply(bottom, top, 255), (200, 100, 255));
+  // Masks only darken, by the brightest channel of the mask pixel.
+  assert_eq!(Blend::AlphaMask.apply(bottom, (0, 0, 255), 255), bottom);
+  assert_eq!(Blend::AlphaMask.apply(bottom, (64, 0, 0), 255), (50, 25, 0));
+  assert_eq!(Blend::AlphaMask.apply(bottom, (0, 0, 0), 128), (100, 50, 0));
+
+  assert_eq!(Blend::parse("mask"), Some(Blend::AlphaMask));
+  assert_eq!(Blend::parse("screen"), None);
+}
+
+#[test]
+fn test_compose() {
+  let mut compositor = Compositor::new();
+  compositor.push(layer(8, "li t3 0\nli t4 0\n"));
+  let mut overlay = layer(8, "li t3 80\nli t4 0\n");
+  overlay.set_blend(Blend::Add);
+  overlay.set_range(2, 3);
+  compositor.push(overlay);
+
+  let mut strip = strip(6);
+  assert!(compositor.tick());
+  assert!(compositor.spin(&mut Idle));
+  compositor.compose(&mut strip);
+  assert_eq!(
+    strip.presented_pixels().collect::<Vec<_>>(),
+    vec![
+      (1, 0, 0),
+      (1, 0, 0),
+      (2, 80, 0),
+      (2, 80, 0),
+      (2, 80, 0),
+      (1, 0, 0),
+    ]
+  );
+
+  // Layers configure at runtime, changes show with the next compose.
+  let overlay = compositor.layer_mut(1).unwrap();
+  overlay.set_opacity(0);
+  compositor.compose(&mut strip);
+  assert_eq!(strip.pixel(2), (1, 0, 0));
+  compositor.layer_mut(1).unwrap().set_range(4, 8);
+  compositor.layer_mut(1).unwrap().set_opacity(255);
+  compositor.compose(&mut strip);
+  assert_eq!(strip.pixel(3), (1, 0, 0));
+  assert_eq!(strip.pixel(5), (2, 80, 0));
+
+  compositor.set_layer(0, None);
+  compositor.compose(&mut strip);
+  assert_eq!(strip.pixel(0), (0, 0, 0));
+}
+
+#[test]
+fn test_prescalers() {
+  let mut compositor = Compositor::new();
+  compositor.push(layer(8, ""));
+  compositor.push(layer(8, ""));
+  compositor.layer_mut(1).unwrap().set_prescaler(2);
+  for _ in 0..6 {
+    assert!(compositor.tick());
+    assert!(compositor.spin(&mut Idle));
+  }
+  let mut frames = vec![];
+  for idx in 0..2 {
+    frames.push(compositor.layer_mut(idx).unwrap().env().pixel(0).0);
+  }
+  assert_eq!(frames, vec![6, 2]);
+
+  // Nothing to spin on ticks no layer is due.
+  let mut compositor = Compositor::new();
+  compositor.push(layer(8, ""));
+  compositor.layer_mut(0).unwrap().set_prescaler(1);
+  assert!(!compositor.tick());
+  assert!(!compositor.spin(&mut Idle));
+  assert!(compositor.tick());
+  assert!(compositor.spin(&mut Idle));
+}
+
+#[test]
+fn test_push() {
+  let mut compositor = Compositor::new();
+  for idx in 0..MAX_LAYERS {
+    assert_eq!(compositor.push(layer(2, "")), Some(idx));
+  }
+  assert_eq!(compositor.push(layer(2, "")), None);
+  compositor.set_layer(MAX_LAYERS - 1, None);
+  assert_eq!(compositor.push(layer(2, "")), Some(MAX_LAYERS - 1));
+  assert!(compositor.layer(MAX_LAYERS).is_none());
+}
+
+#[test]
+fn test_transition() {
+  let mut compositor = Compositor::new();
+  compositor.push(layer(4, "li t3 0\nli t4 0\n"));
+  let mut strip = strip(4);
+  let transition = Transition {
+    effect: Effect::Crossfade,
+    frames: 2,
+  };
+  let incoming = layer(4, "li t3 200\nli t4 0\n");
+  assert!(compositor
+    .start_transition(0, incoming, transition)
+    .is_none());
+  assert_eq!(compositor.transition(), Some((0, 2)));
+  assert!(compositor.take_outgoing().is_none());
+
+  // Both programs run during the transition.
+  let mut greens = vec![];
+  for _ in 0..3 {
+    assert!(compositor.tick());
+    compositor.spin(&mut Idle);
+    compositor.compose(&mut strip);
+    greens.push(strip.pixel(0));
+  }
+  assert_eq!(greens, vec![(1, 100, 0), (2, 200, 0), (3, 200, 0)]);
+  assert_eq!(compositor.transition(), None);
+
+  // The outgoing layer stopped at its second frame and is free for the next program.
+  let mut outgoing = compositor.take_outgoing().unwrap();
+  assert_eq!(outgoing.env().pixel(0), (2, 0, 0));
+  outgoing
+    .load(Box::leak(
+      compile(&parse(FILL).unwrap()).unwrap().into_boxed_slice(),
+    ))
+    .unwrap();
+  assert!(compositor
+    .start_transition(0, outgoing, Transition::CUT)
+    .is_none());
+  assert_eq!(compositor.transition(), None);
+  compositor.tick();
+  compositor.spin(&mut Idle);
+  compositor.compose(&mut strip);
+  assert_eq!(strip.pixel(0), (1, 0, 0));
+  assert!(compositor.take_outgoing().is_some());
+}
+
+struct Idle;
+
+impl Input for Idle {
+  fn buttons(&mut self) -> u16 {
+    0
+  }
+
+  fn analog(&mut self, _: usize) -> u8 {
+    0
+  }
+
+  fn encoder(&mut self, _: usize) -> i32 {
+    0
+  }
+}
+
+fn layer(leds: usize, colors: &str) -> Layer<'static, Vec<u8>> {
+  let code = format!("{}{}", colors, FILL);
+  let bytecode = compile(&parse(&code).unwrap()).unwrap();
+  let env = LedEnv::new(vec![0; 8], vec![0; leds * 3], ColorOrder::RGB);
+  Layer::new(env, Box::leak(bytecode.into_boxed_slice())).unwrap()
+}
+
+fn strip(leds: usize) -> LedEnv<Vec<u8>> {
+  LedEnv::new(vec![0; 8], vec![0; leds * 3], ColorOrder::RGB)
+}
@@ -0,0 +1,144 @@
+use strip_shared::driver::*;
+use strip_shared::pixel::{Channels, ColorOrder};
+
+fn encode<D: Encoder>(driver: &D, pixels: &[(u8, u8, u8)]) -> Vec<u8> {
+  let mut out = vec![];
+  driver
+    .encode(pixels.iter().copied(), |byte| {
+      out.push(byte);
+      Ok::<(), ()>(())
+    })
+    .unwrap();
+  out
+}
+
+fn encode_levels<D: Encoder>(driver: &D, channels: Channels, pixels: &[[u16; 4]]) -> Vec<u8> {
+  let mut out = vec![];
+  driver
+    .encode_levels(channels, pixels.iter().copied(), |byte| {
+      out.push(byte);
+      Ok::<(), ()>(())
+    })
+    .unwrap();
+  out
+}
+
+#[test]
+fn test_ws2812() {
+  let out = encode(&Ws2812::default(), &[(0xff, 0x00, 0x81)]);
+  let mut expected = vec![
+    0x88, 0x88, 0x88, 0x88, // G 0x00
+    0xee, 0xee, 0xee, 0xee, // R 0xff
+    0xe8, 0x88, 0x88, 0x8e, // B 0x81
+  ];
+  expected.extend_from_slice(&[0; WS2812_RESET_LEN]);
+  assert_eq!(out, expected);
+
+  let out = encode(&Ws2812::new(ColorOrder::RGB), &[(0x40, 0, 0)]);
+  assert_eq!(out[..4], [0x8e, 0x88, 0x88, 0x88]);
+  assert_eq!(encode(&Ws2812::default(), &[]), vec![0; WS2812_RESET_LEN]);
+}
+
+#[test]
+fn test_ws2812_levels() {
+  let pixel = [0xffff, 0x0000, 0x8181, 0x4040];
+  let out = encode_levels(&Ws2812::default(), Channels::Rgbw, &[pixel]);
+  assert_eq!(
+    out[..16],
+    [
+      0x88, 0x88, 0x88, 0x88, // G 0x00
+      0xee, 0xee, 0xee, 0xee, // R 0xff
+      0xe8, 0x88, 0x88, 0x8e, // B 0x81
+      0x8e, 0x88, 0x88, 0x88, // W 0x40
+    ]
+  );
+  assert_eq!(out.len(), 16 + WS2812_RESET_LEN);
+
+  // 16-bit levels go out big endian, both bytes of every channel.
+  let driver = Ws2812::new(ColorOrder::RGB);
+  let out = encode_levels(&driver, Channels::Rgb16, &[[0x40ff, 0, 0, 0]]);
+  assert_eq!(out[..8], [0x8e, 0x88, 0x88, 0x88, 0xee, 0xee, 0xee, 0xee]);
+  assert_eq!(out.len(), 6 * 4 + WS2812_RESET_LEN);
+}
+
+#[test]
+fn test_apa102() {
+  let out = encode(&Apa102::new(), &[(0x10, 0x20, 0x30), (0xff, 0, 0)]);
+  assert_eq!(
+    out,
+    vec![
+      0x00, 0x00, 0x00, 0x00, // start frame
+      0xff, 0x30, 0x20, 0x10, // LED 0
+      0xff, 0x00, 0x00, 0xff, // LED 1
+      0xff, 0xff, 0xff, 0xff, // end frame
+    ]
+  );
+
+  let dimmed = Apa102 {
+    brightness: 10,
+    ..Apa102::new()
+  };
+  assert_eq!(encode(&dimmed, &[(1, 2, 3)])[4..8], [0xea, 3, 2, 1]);
+  let clamped = Apa102 {
+    brightness: 40,
+    order: ColorOrder::RGB,
+    ..Apa102::new()
+  };
+  assert_eq!(encode(&clamped, &[(1, 2, 3)])[4..8], [0xff, 1, 2, 3]);
+}
+
+#[test]
+fn test_apa102_levels() {
+  // No white LED, white adds to the colors.
+  let pixels = [[0x10ff, 0x2000, 0xf000, 0x2000]];
+  let out = encode_levels(&Apa102::new(), Channels::Rgbw, &pixels);
+  assert_eq!(out[4..8], [0xff, 0xff, 0x40, 0x30]);
+}
+
+#[test]
+fn test_end_frame_length() {
+  let pixels = vec![(0, 0, 0); 100];
+  let out = encode(&Apa102::new(), &pixels);
+  assert_eq!(out.len(), 4 + 400 + 7);
+  assert!(out[404..].iter().all(|byte| *byte == 0xff));
+
+  let out = encode(&Apa102::sk9822(), &pixels);
+  assert_eq!(out.len(), 4 + 400 + 4 + 7);
+  assert!(out[404..].iter().all(|byte| *byte == 0));
+
+  let out = encode(&Apa102::sk9822(), &[(0x10, 0x20, 0x30)]);
+  assert_eq!(out, vec![0, 0, 0, 0, 0xff, 0x30, 0x20, 0x10, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_driver() {
+  assert_eq!(
+    Driver::parse("ws2812"),
+    Some(Driver::Ws2812(Ws2812::default()))
+  );
+  assert_eq!(
+    Driver::parse("SK9822"),
+    Some(Driver::Apa102(Apa102::sk9822()))
+  );
+  assert_eq!(Driver::parse("lpd8806"), None);
+  let driver = Driver::parse("apa102").unwrap();
+  assert_eq!(
+    encode(&driver, &[(1, 2, 3)]),
+    encode(&Apa102::new(), &[(1, 2, 3)])
+  );
+}
+
+#[test]
+fn test_write_error() {
+  let mut written = 0;
+  let res = Apa102::new().encode([(1, 2, 3)].iter().copied(), |_| {
+    written += 1;
+    if written == 6 {
+      Err("bus fault")
+    } else {
+      Ok(())
+    }
+  });
+  assert_eq!(res, Err("bus fault"));
+  assert_eq!(written, 6);
+}
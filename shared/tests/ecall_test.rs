@@ -0,0 +1,137 @@
+use strip_shared::compiler::*;
+use strip_shared::ecall::*;
+use strip_shared::led::*;
+use strip_shared::parser::parse;
+
+#[test]
+fn test_registry() {
+  for (idx, def) in ECALLS.iter().enumerate() {
+    assert!((def.number as usize) < MAX_ECALLS);
+    assert!(ECALLS[..idx].iter().all(|other| other.number != def.number));
+    assert!(ECALLS[..idx].iter().all(|other| other.name != def.name));
+  }
+  assert_eq!(find(ECALLS, "HSV2RGB").map(|def| def.number), Some(HSV2RGB));
+  assert_eq!(lookup(ECALLS, SET_PSC).map(|def| def.name), Some("SET_PSC"));
+  assert_eq!(find(ECALLS, "NOPE"), None);
+  assert_eq!(
+    find(ECALLS, "HSV2RGB").unwrap().to_string(),
+    "HSV2RGB(addr)"
+  );
+  assert_eq!(find(ECALLS, "FRAME").unwrap().to_string(), "FRAME");
+  assert_eq!(find(ECALLS, "FILL").unwrap().to_string(), "FILL(a0..a2)");
+}
+
+#[test]
+fn test_dispatcher() {
+  const REGISTRY: &[EcallDef] = &[
+    EcallDef {
+      name: "ADD",
+      number: 3,
+      param: Param::Value,
+      returns: true,
+      results: 0,
+      help: "",
+    },
+    EcallDef {
+      name: "CLEAR",
+      number: 9,
+      param: Param::None,
+      returns: false,
+      results: 0,
+      help: "",
+    },
+  ];
+  let dispatcher: Dispatcher<i32, ()> = Dispatcher::new(
+    REGISTRY,
+    &[
+      ("ADD", |acc, param, _| {
+        *acc += param;
+        Ok(*acc)
+      }),
+      ("CLEAR", |acc, _, _| {
+        *acc = 0;
+        Ok(0)
+      }),
+    ],
+  );
+  let mut acc = 0;
+  let add = dispatcher.handler(3).unwrap();
+  assert_eq!(add(&mut acc, 5, &mut [0; 8]), Ok(5));
+  assert_eq!(add(&mut acc, 2, &mut [0; 8]), Ok(7));
+  assert!(dispatcher.handler(4).is_none());
+  assert!(dispatcher.handler(200).is_none());
+  dispatcher.handler(9).unwrap()(&mut acc, 0, &mut [0; 8]).unwrap();
+  assert_eq!(acc, 0);
+}
+
+#[test]
+#[should_panic]
+fn test_dispatcher_unknown_name() {
+  let _: Dispatcher<i32, ()> = Dispatcher::new(ECALLS, &[("NOPE", |_, _, _| Ok(0))]);
+}
+
+#[test]
+fn test_predefined_names() {
+  let predefined = compile_src(
+    "
+    li s0 2
+    ecall zero SET_PSC(s0)
+    ecall s1 FRAME
+  ",
+  );
+  let defined = compile_src(
+    "
+    .equ SET_PSC 0x0
+    .equ FRAME 0x2
+    li s0 2
+    ecall zero SET_PSC(s0)
+    ecall s1 FRAME
+  ",
+  );
+  assert_eq!(predefined, (defined.0.clone(), vec![]));
+  assert_eq!(defined.1, vec![]);
+
+  // Program constants shadow ecall names.
+  let (shadowed, warnings) = compile_src(
+    "
+    .equ FRAME 0x3
+    ecall s1 FRAME
+  ",
+  );
+  let (elapsed, _) = compile_src("ecall s1 ELAPSED_MS");
+  assert_eq!(shadowed, elapsed);
+  assert_eq!(warnings, vec![]);
+}
+
+#[test]
+fn test_unknown_ecall() {
+  let (_, warnings) = compile_src(
+    "
+    ecall zero SET_PSC
+    ecall zero 0x3f
+    li s0 1
+    ecall s0 0x3e(s0)
+  ",
+  );
+  assert_eq!(
+    warnings,
+    vec![
+      Warning::UnknownEcall {
+        pc: 1,
+        number: 0x3f
+      },
+      Warning::UnknownEcall {
+        pc: 3,
+        number: 0x3e
+      },
+    ]
+  );
+  assert_eq!(
+    warnings[0].to_string(),
+    "warning: unknown ecall 0x3f at pc 1"
+  );
+}
+
+fn compile_src(code: &str) -> (Vec<u8>, Vec<Warning>) {
+  compile_with(&parse(code).unwrap(), ECALLS).unwrap()
+}
@@ -0,0 +1,783 @@
+use strip_shared::audio::Sound;
+use strip_shared::color::*;
+use strip_shared::compiler::compile;
+use strip_shared::input::Input;
+use strip_shared::led::*;
+use strip_shared::parser::parse;
+use strip_shared::vm::*;
+use strip_shared::Reg;
+
+#[test]
+fn test_memory_map() {
+  let env = spin_env(
+    4,
+    ColorOrder::RGB,
+    "
+    li s0 0x42
+    sb s0 1
+    sb s0 0x1000
+    li s1 0x0a0b
+    sh s1 0x100a
+  ",
+  );
+  assert_eq!(env.ram(), &[0, 0x42, 0, 0, 0, 0, 0, 0]);
+  assert_eq!(env.led_ram()[..3], [0x42, 0, 0]);
+  assert_eq!(env.led_ram()[10..], [0x0a, 0x0b]);
+}
+
+#[test]
+fn test_memory_overread() {
+  let mut vm = load_vm(4, ColorOrder::RGB, "sb zero 0x100c");
+  assert!(vm.respin().is_err());
+  let mut vm = load_vm(4, ColorOrder::RGB, "lb s0 8");
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_set_psc() {
+  let mut vm = load_vm(
+    4,
+    ColorOrder::RGB,
+    "
+    .equ SET_PSC 0x0
+
+    li s0 2
+    ecall zero SET_PSC(s0)
+  ",
+  );
+  assert!(vm.get_env().tick());
+  vm.respin().unwrap();
+  assert_eq!(vm.get_env().prescaler(), 2);
+  let ticks: Vec<bool> = (0..6).map(|_| vm.get_env().tick()).collect();
+  assert_eq!(ticks, [false, false, true, false, false, true]);
+}
+
+#[test]
+fn test_frame_timing() {
+  let mut vm = load_vm(
+    1,
+    ColorOrder::RGB,
+    "
+    .equ SET_PSC 0x0
+    .equ FRAME 0x2
+    .equ ELAPSED_MS 0x3
+    .equ DELTA_MS 0x4
+
+    li s0 127
+    ecall zero SET_PSC(s0)
+    ecall s1 FRAME
+    ecall s2 ELAPSED_MS
+    ecall s3 DELTA_MS
+    sw s1 0
+  ",
+  );
+  let mut frames = vec![];
+  for _ in 0..3 {
+    while !vm.get_env().tick() {}
+    vm.respin().unwrap();
+    let reg = vm.get_reg();
+    frames.push((reg[4], reg[5], reg[6]));
+  }
+  assert_eq!(frames, [(0, 0, 0), (1, 125, 125), (2, 250, 125)]);
+  assert_eq!(vm.get_env().ram()[..4], [0, 0, 0, 2]);
+  assert_eq!(vm.get_env().frame(), 2);
+
+  // Program load restarts the clock.
+  vm.reset();
+  assert_eq!(vm.get_env().frame(), 0);
+  assert_eq!(vm.get_env().elapsed_ms(), 0);
+}
+
+#[test]
+fn test_rand() {
+  let code = "
+    .equ RAND 0x5
+    .equ SEED 0x6
+
+    ecall s0 RAND
+    li s1 300
+    ecall s1 RAND(s1)
+    li s2 42
+    ecall zero SEED(s2)
+    ecall s2 RAND
+  ";
+  let bytecode = compile(&parse(code).unwrap()).unwrap();
+  let run = |seed: Option<u32>| {
+    let mut env = LedEnv::new(vec![0; 8], vec![0; 3], ColorOrder::RGB);
+    if let Some(seed) = seed {
+      env.set_seed(seed);
+    }
+    let mut vm = VM::new(env);
+    vm.load(&bytecode).unwrap();
+    vm.respin().unwrap();
+    let reg = vm.get_reg();
+    (reg[3], reg[4], reg[5])
+  };
+
+  let mut rng = XorShift::new(DEFAULT_SEED);
+  let (first, second) = (rng.next_u32(), rng.next_u32());
+  let mut reseeded = XorShift::new(42);
+  let expected = (
+    first as i32,
+    (second % 300) as i32,
+    reseeded.next_u32() as i32,
+  );
+  assert_eq!(run(None), expected);
+  assert_eq!(run(None), expected);
+  assert_ne!(run(Some(7)).0, expected.0);
+  assert_eq!(run(Some(7)).2, expected.2);
+
+  // Zero seed would lock xorshift at zero.
+  assert_eq!(XorShift::new(0), XorShift::new(DEFAULT_SEED));
+}
+
+#[test]
+fn test_multi_arg_ecalls() {
+  let mut vm = load_vm(
+    4,
+    ColorOrder::GRB,
+    "
+    li a0 1
+    li a1 2
+    li a2 0x3040
+    lui a2 0x20
+    ecall zero FILL
+
+    li a0 0
+    li a1 2
+    li a2 255
+    ecall zero BLEND
+
+    li a0 3
+    li a1 0
+    li a2 128
+    ecall zero BLEND
+
+    li a0 3
+    ecall s0 PIXEL
+    mv s1 a0
+    mv s2 a1
+    mv s3 a2
+
+    li a0 200
+    li a1 128
+    ecall s4 SCALE8
+  ",
+  );
+  vm.respin().unwrap();
+  let env = vm.get_env();
+  assert_eq!(env.pixel(0), (0x20, 0x30, 0x40));
+  assert_eq!(env.pixel(1), (0x20, 0x30, 0x40));
+  assert_eq!(env.pixel(3), (0x10, 0x18, 0x20));
+  assert_eq!(env.led_ram()[9..], [0x18, 0x10, 0x20]);
+  let reg = vm.get_reg();
+  assert_eq!(reg[3..8], [0x10_1820, 0x10, 0x18, 0x20, 100]);
+  assert_eq!(reg[Reg::a0 as usize], 100);
+
+  let mut vm = load_vm(2, ColorOrder::RGB, "li a0 1\nli a1 2\necall zero FILL");
+  assert!(vm.respin().is_err());
+  let mut vm = load_vm(2, ColorOrder::RGB, "li a0 -1\necall zero PIXEL");
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_range_color_ecalls() {
+  let env = spin_env(
+    4,
+    ColorOrder::GRB,
+    "
+    li a0 0
+    li a1 4
+    li a2 6500
+    ecall zero KELVIN
+
+    li a0 2
+    li a1 2
+    li a2 127
+    ecall zero DIM
+
+    li a0 0
+    li a1 3
+    li a2 0
+    li a3 0xff
+    ecall zero GRADIENT
+
+    li a0 0
+    li a1 1
+    li a2 0x10
+    ecall zero ADD
+
+    li a0 1
+    li a1 1
+    li a2 0x1000
+    li a3 255
+    ecall zero MIX
+  ",
+  );
+  assert_eq!(env.pixel(0), (0, 0, 16));
+  assert_eq!(env.pixel(1), (0, 16, 0));
+  assert_eq!(env.pixel(2), (0, 0, 255));
+  assert_eq!(env.pixel(3), (127, 124, 126));
+
+  let env = spin_env(
+    4,
+    ColorOrder::GRB,
+    "
+    li s0 0x1000
+    li s1 0xff
+    li s2 0xa0
+    sb zero (s0)
+    sb s1 1(s0)
+    sb s1 2(s0)
+    sb s2 3(s0)
+    sb s1 4(s0)
+    sb s1 5(s0)
+    li s2 77
+    sb s2 8(s0)
+    li a0 0
+    li a1 1
+    ecall zero SPECTRUM
+    li a0 1
+    li a1 1
+    ecall zero RAINBOW
+    li a0 2
+    li a1 1
+    ecall zero HSL2RGB
+
+    li a0 0
+    li a1 1
+    ecall zero RGB2HSV
+    li a0 2
+    li a1 1
+    ecall zero RGB2HSL
+  ",
+  );
+  assert_eq!(env.led_ram()[..3], [0, 255, 255]);
+  assert_eq!(env.pixel(1), (0, 0, 255));
+  assert_eq!(env.led_ram()[6..9], [0, 0, 77]);
+
+  let mut vm = load_vm(2, ColorOrder::RGB, "li a0 1\nli a1 2\necall zero DIM");
+  assert!(vm.respin().is_err());
+  let mut vm = load_vm(2, ColorOrder::RGB, "lui a1 0x8000\necall zero GRADIENT");
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_output_ecalls() {
+  let mut vm = load_vm(
+    2,
+    ColorOrder::GRB,
+    "
+    li a0 22
+    li a1 10
+    li a2 28
+    ecall zero GAMMA
+    li s0 127
+    ecall zero BRIGHTNESS(s0)
+    li s0 1
+    ecall zero DITHER(s0)
+
+    li a0 0
+    li a1 2
+    li a2 0x7f7f
+    lui a2 0x7f
+    ecall zero FILL
+    ecall s1 POWER
+  ",
+  );
+  vm.respin().unwrap();
+  let ma = vm.get_reg()[Reg::s1 as usize];
+  let env = vm.get_env();
+  assert_eq!(env.output().gamma(), [22, 10, 28]);
+  assert_eq!(env.output().brightness(), 127);
+  assert!(env.output().dither());
+  assert_eq!(env.pixel(0), (127, 127, 127));
+  let frame: Vec<_> = env.output_pixels().collect();
+  assert_eq!(frame[0], env.output().apply((127, 127, 127)));
+  assert_ne!(frame[1], (127, 127, 127));
+  assert_eq!(ma, env.power().ma as i32);
+  assert!(ma > 2 && ma < 2 + 6 * 20 / 2);
+
+  vm.reset();
+  assert_eq!(vm.get_env().output().brightness(), 255);
+  assert!(!vm.get_env().output().dither());
+}
+
+#[test]
+fn test_pixel_formats() {
+  let code = "
+    .format grbw
+    li a0 1
+    li a1 1
+    li a2 0x40ff
+    lui a2 0x20
+    ecall zero FILL
+    li s0 STRIP_BASE
+    li s1 0x55
+    sb s1 PIXEL_SIZE(s0)
+  ";
+  let bytecode = compile(&parse(code).unwrap()).unwrap();
+  assert_eq!(bytecode[..3], [0xaf, 0xb0, 0x12]);
+  let mut env = LedEnv::new(vec![0; 8], vec![0; 16], ColorOrder::RGB);
+  env.set_leds(4);
+  let mut vm = VM::new(env);
+  vm.load(&bytecode).unwrap();
+  vm.respin().unwrap();
+  let env = vm.get_env();
+  assert_eq!(env.format(), PixelFormat::parse("grbw").unwrap());
+  assert_eq!(env.leds(), 4);
+  assert_eq!(
+    env.led_ram(),
+    &[0, 0, 0, 0, 0x55, 0, 0xdf, 0x20, 0, 0, 0, 0, 0, 0, 0, 0]
+  );
+  assert_eq!(env.pixel(1), (0x20, 0x75, 0xff));
+
+  // Next program runs in the default format again.
+  vm.load(include_bytes!("../../docs/blinky.bin")).unwrap();
+  assert_eq!(vm.get_env().format(), ColorOrder::RGB.into());
+}
+
+#[test]
+fn test_pixel_format_fit() {
+  let mut env = LedEnv::new(vec![0; 8], vec![0; 4 * MAX_PIXEL_SIZE], ColorOrder::GRB);
+  env.set_leds(4);
+  let mut vm = VM::new(env);
+  let prog =
+    compile(&parse(".format rgb16\nli a0 3\nli a1 1\nli a2 0xff\necall zero FILL").unwrap())
+      .unwrap();
+  vm.load(&prog).unwrap();
+  vm.respin().unwrap();
+  let env = vm.get_env();
+  assert_eq!(env.led_ram().len(), 24);
+  assert_eq!(env.led_ram()[18..], [0, 0, 0, 0, 0xff, 0xff]);
+  assert_eq!(env.pixel(3), (0, 0, 0xff));
+
+  // LED RAM of three RGB LEDs fits only two RGBW ones.
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; 9], ColorOrder::RGB));
+  let prog = compile(&parse(".format rgbw\nnop").unwrap()).unwrap();
+  assert!(vm.load(&prog).is_err());
+  assert!(parse(".format xyz\nnop")
+    .map(|exprs| compile(&exprs).is_err())
+    .unwrap());
+}
+
+#[test]
+fn test_channels() {
+  let channels = [
+    Channel {
+      leds: 3,
+      format: ColorOrder::RGB.into(),
+    },
+    Channel {
+      leds: 2,
+      format: ColorOrder::GRB.into(),
+    },
+  ];
+  let load = |code: &str| {
+    let mut env = LedEnv::new(vec![0; 8], vec![0; 5 * MAX_PIXEL_SIZE], ColorOrder::RGB);
+    env.set_channels(&channels).unwrap();
+    let mut vm = VM::new(env);
+    let prog = compile(&parse(code).unwrap()).unwrap();
+    vm.load(Box::leak(prog.into_boxed_slice())).unwrap();
+    vm
+  };
+
+  // Index ecalls draw on the selected channel, windows map channels at 0x1000 steps.
+  let mut vm = load(
+    "
+    li a0 1
+    ecall s0 CHANNEL(a0)
+    li a0 0
+    li a1 2
+    li a2 0xff
+    ecall zero FILL
+    li t0 0x10
+    li t1 0x1000
+    sb t0 2(t1)
+    li t1 0x2000
+    sb t0 4(t1)
+  ",
+  );
+  vm.respin().unwrap();
+  assert_eq!(vm.get_reg()[Reg::s0 as usize], 2);
+  let env = vm.get_env();
+  assert_eq!(env.leds(), 5);
+  assert_eq!(env.channels(), 2);
+  assert_eq!(env.channel_ram(0), &[0, 0, 0x10, 0, 0, 0, 0, 0, 0]);
+  assert_eq!(env.channel_ram(1), &[0, 0, 0xff, 0, 0x10, 0xff]);
+  assert_eq!(env.led_ram().len(), 15);
+  assert_eq!(env.pixel(3), (0, 0, 0xff));
+  assert_eq!(env.pixel(4), (0x10, 0, 0xff));
+  let pixels: Vec<Rgb> = env.channel_output_pixels(1).collect();
+  assert_eq!(pixels, [(0, 0, 0xff), (0x10, 0, 0xff)]);
+
+  // Windows end with their channel, the last one extends to the end of the address space.
+  for addr in ["0x1009", "0x2006", "0x3000"].iter() {
+    let mut vm = load(&format!("li t1 {}\nsb zero (t1)", addr));
+    assert!(vm.respin().is_err());
+  }
+  let mut vm = load("li a0 2\necall zero CHANNEL(a0)");
+  assert!(vm.respin().is_err());
+
+  // Reloading selects the first channel again.
+  let mut vm = load("li a0 1\necall zero CHANNEL(a0)");
+  vm.respin().unwrap();
+  let prog = compile(&parse("li a0 2\nli a1 1\nli a2 0xff\necall zero FILL").unwrap()).unwrap();
+  vm.load(&prog).unwrap();
+  vm.respin().unwrap();
+  assert_eq!(vm.get_env().pixel(2), (0, 0, 0xff));
+}
+
+#[test]
+fn test_pixel_out_of_range() {
+  let mut env = LedEnv::new(vec![0; 8], vec![0; 5 * MAX_PIXEL_SIZE], ColorOrder::RGB);
+  let channel = Channel {
+    leds: 2,
+    format: ColorOrder::RGB.into(),
+  };
+  env.set_channels(&[channel, channel]).unwrap();
+  env.set_pixel(3, (1, 2, 3));
+  // Pixels past the last LED read black and writes to them are dropped.
+  for idx in [4, 5, 100].iter() {
+    env.set_pixel(*idx, (0xff, 0xff, 0xff));
+    assert_eq!(env.pixel(*idx), (0, 0, 0));
+  }
+  assert_eq!(env.led_ram(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+}
+
+#[test]
+fn test_xy_ecalls() {
+  let load = |code: &str| {
+    let mut vm = VM::new(LedEnv::new(vec![0; 16], vec![0; 18], ColorOrder::RGB));
+    let prog = compile(&parse(code).unwrap()).unwrap();
+    vm.load(Box::leak(prog.into_boxed_slice())).unwrap();
+    vm.respin().unwrap();
+    vm
+  };
+
+  // Strips map as a single row until the program declares a panel.
+  let mut vm = load(
+    "
+    ecall s0 MATRIX_SIZE
+    li a0 3
+    li a1 2
+    li a2 1
+    ecall zero MATRIX
+    ecall s1 MATRIX_SIZE
+    li a0 0
+    li a1 1
+    li a2 0xff
+    ecall zero SET_XY
+    li a0 2
+    li a1 1
+    ecall s2 XY
+    li a0 0
+    li a1 1
+    ecall zero GET_XY
+  ",
+  );
+  let reg = vm.get_reg();
+  assert_eq!(reg[Reg::s0 as usize], 6);
+  assert_eq!(reg[Reg::s1 as usize], 6);
+  assert_eq!(reg[Reg::s2 as usize], 3);
+  assert_eq!(reg[Reg::a0 as usize..(Reg::a2 as usize + 1)], [0, 0, 0xff]);
+  assert_eq!(vm.get_env().pixel(5), (0, 0, 0xff));
+  assert_eq!(vm.get_env().matrix().size(), (3, 2));
+
+  // Program LUT, positions without LED are skipped.
+  let mut vm = load(
+    "
+    .half 5 4 3 0xffff 1 0
+    li a0 3
+    li a1 2
+    li a2 4
+    ecall zero MATRIX
+    ecall zero XY_LUT(zero)
+    li a0 0
+    li a1 0
+    li a2 0xff
+    ecall zero SET_XY
+    li a1 1
+    ecall s0 XY
+    li a0 3
+    ecall s1 XY
+  ",
+  );
+  assert_eq!(vm.get_reg()[Reg::s0 as usize], -1);
+  assert_eq!(vm.get_reg()[Reg::s1 as usize], -1);
+  assert_eq!(vm.get_env().pixel(5), (0, 0, 0xff));
+
+  // Host matrix and LUT come back after every load.
+  let mut env = LedEnv::new(vec![0; 8], vec![0; 18], ColorOrder::RGB);
+  env.set_matrix(Matrix::parse("2x2,lut"));
+  env.set_lut(vec![0, 3, 0, 2, 0xff, 0xff, 0, 9]);
+  assert_eq!(env.xy(0, 0), Some(3));
+  assert_eq!(env.xy(1, 1), None);
+  let mut vm = VM::new(env);
+  let prog = compile(&parse("li a0 4\nli a1 1\nli a2 0\necall zero MATRIX").unwrap()).unwrap();
+  vm.load(&prog).unwrap();
+  vm.respin().unwrap();
+  assert_eq!(vm.get_env().xy(3, 0), Some(3));
+  vm.load(&prog).unwrap();
+  assert_eq!(vm.get_env().xy(1, 0), Some(2));
+
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; 18], ColorOrder::RGB));
+  let prog = compile(&parse("li a0 -1\nli a1 1\nli a2 0\necall zero MATRIX").unwrap()).unwrap();
+  vm.load(&prog).unwrap();
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_buffers() {
+  let load = |code: &str, front: bool| {
+    let mut env = LedEnv::new(vec![0; 8], vec![0; 6], ColorOrder::RGB);
+    if front {
+      env.set_front(vec![0; 6]).unwrap();
+    }
+    let mut vm = VM::new(env);
+    let prog = compile(&parse(code).unwrap()).unwrap();
+    vm.load(Box::leak(prog.into_boxed_slice())).unwrap();
+    vm
+  };
+  let frame = |vm: &mut VM<LedEnv<Vec<u8>>>| {
+    assert!(vm.get_env().tick());
+    vm.respin().unwrap();
+    vm.get_env().finish_frame();
+  };
+  let shown = |vm: &mut VM<LedEnv<Vec<u8>>>| vm.get_env().output_pixels().next().unwrap();
+  let add = "li a0 0\nli a1 1\nli a2 1\necall zero ADD\n";
+
+  // Outputs only see presented frames, LED RAM persists by default.
+  let mut vm = load(add, true);
+  for _ in 0..3 {
+    frame(&mut vm);
+  }
+  assert_eq!(shown(&mut vm), (0, 0, 3));
+  vm.respin().unwrap();
+  assert_eq!(vm.get_env().pixel(0), (0, 0, 4));
+  assert_eq!(shown(&mut vm), (0, 0, 3));
+  // The last presented frame stays on until the next program presents.
+  // The last frame stays on presented until the next program presents.
+  let prog = compile(&parse("nop").unwrap()).unwrap();
+  vm.load(&prog).unwrap();
+  assert_eq!(vm.get_env().pixel(0), (0, 0, 0));
+  assert_eq!(shown(&mut vm), (0, 0, 3));
+
+  let mut vm = load(&format!("li t0 1\necall zero BUFFER(t0)\n{}", add), true);
+  for _ in 0..3 {
+    frame(&mut vm);
+  }
+  assert_eq!(shown(&mut vm), (0, 0, 1));
+  assert_eq!(vm.get_env().pixel(0), (0, 0, 0));
+  assert_eq!(vm.get_env().buffer_mode(), BufferMode::Clear);
+
+  // Programs presenting themselves are not presented at the end of a frame.
+  let mut vm = load(&format!("{}ecall zero PRESENT\n{}", add, add), true);
+  frame(&mut vm);
+  frame(&mut vm);
+  assert_eq!(shown(&mut vm), (0, 0, 3));
+  assert_eq!(vm.get_env().pixel(0), (0, 0, 4));
+
+  // Single buffer clears when the next frame starts.
+  let mut vm = load(&format!("li t0 1\necall zero BUFFER(t0)\n{}", add), false);
+  frame(&mut vm);
+  frame(&mut vm);
+  assert_eq!(shown(&mut vm), (0, 0, 1));
+  vm.get_env().tick();
+  assert_eq!(shown(&mut vm), (0, 0, 0));
+
+  let mut vm = load("li t0 2\necall zero BUFFER(t0)", true);
+  assert!(vm.respin().is_err());
+}
+
+struct Board {
+  buttons: u16,
+  steps: i32,
+}
+
+impl Input for Board {
+  fn buttons(&mut self) -> u16 {
+    self.buttons
+  }
+
+  fn analog(&mut self, idx: usize) -> u8 {
+    idx as u8 * 10
+  }
+
+  fn encoder(&mut self, _: usize) -> i32 {
+    std::mem::take(&mut self.steps)
+  }
+}
+
+#[test]
+fn test_input_ecalls() {
+  let code = "
+    li t0 1
+    ecall s0 BUTTON(t0)
+    ecall s1 PRESSED(t0)
+    ecall s2 RELEASED(t0)
+    li t0 3
+    ecall s3 ANALOG(t0)
+    li t0 0
+    ecall s4 ENCODER(t0)
+  ";
+  let mut vm = load_vm(1, ColorOrder::RGB, code);
+  let mut board = Board {
+    buttons: 0b10,
+    steps: -2,
+  };
+  let frame = |vm: &mut VM<LedEnv<Vec<u8>>>, board: &mut Board| {
+    vm.get_env().sample_input(board);
+    vm.respin().unwrap();
+    let reg = vm.get_reg();
+    [Reg::s0, Reg::s1, Reg::s2, Reg::s3, Reg::s4].map(|r| reg[r as usize])
+  };
+  assert_eq!(frame(&mut vm, &mut board), [1, 1, 0, 30, -2]);
+  assert_eq!(frame(&mut vm, &mut board), [1, 0, 0, 30, 0]);
+  board.buttons = 0b01;
+  assert_eq!(frame(&mut vm, &mut board), [0, 0, 1, 30, 0]);
+
+  let mut vm = load_vm(1, ColorOrder::RGB, "li t0 4\necall zero ANALOG(t0)");
+  assert!(vm.respin().is_err());
+  let mut vm = load_vm(1, ColorOrder::RGB, "li t0 -1\necall zero BUTTON(t0)");
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_sound_ecalls() {
+  let code = "
+    ecall s0 LEVEL
+    ecall s1 BEAT
+    li t0 15
+    ecall s2 BAND(t0)
+    li t0 4
+    ecall zero SET_BANDS(t0)
+    li t0 1
+    ecall s3 BAND(t0)
+  ";
+  let mut vm = load_vm(1, ColorOrder::RGB, code);
+  let mut sound = Sound {
+    level: 90,
+    beat: true,
+    ..Sound::default()
+  };
+  sound.bands[4..8].copy_from_slice(&[10, 70, 30, 0]);
+  sound.bands[15] = 200;
+  vm.get_env().set_sound(sound);
+  vm.respin().unwrap();
+  let reg = vm.get_reg();
+  let regs = [Reg::s0, Reg::s1, Reg::s2, Reg::s3].map(|r| reg[r as usize]);
+  assert_eq!(regs, [90, 1, 200, 70]);
+
+  // Band 15 is out of the 4 bands from the previous frame, a reload splits into 16 again.
+  assert!(vm.respin().is_err());
+  vm.get_env().reset();
+  vm.respin().unwrap();
+  let mut vm = load_vm(1, ColorOrder::RGB, "li t0 17\necall zero SET_BANDS(t0)");
+  assert!(vm.respin().is_err());
+}
+
+#[test]
+fn test_single_param_binaries() {
+  // Prebuilt binaries predate argument registers and still run the same.
+  let bytecode = std::fs::read("../docs/rainbow.bin").unwrap();
+  let code = std::fs::read_to_string("../docs/rainbow.s").unwrap();
+  assert_eq!(bytecode, compile(&parse(&code).unwrap()).unwrap());
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; 900], ColorOrder::RGB));
+  vm.load(&bytecode).unwrap();
+  vm.respin().ok();
+  assert_eq!(vm.get_env().prescaler(), 24);
+  assert_eq!(vm.get_env().pixel(44), hsv2rgb(0, 0xff, 0x20));
+}
+
+#[test]
+fn test_color_helpers() {
+  assert_eq!(scale8(255, 255), 255);
+  assert_eq!(scale8(255, 0), 0);
+  assert_eq!(scale8(200, 127), 100);
+  assert_eq!(blend((0, 100, 255), (255, 0, 255), 0), (0, 100, 255));
+  assert_eq!(blend((0, 100, 255), (255, 0, 255), 255), (255, 0, 255));
+  assert_eq!(blend((0, 100, 255), (255, 0, 255), 128), (128, 50, 255));
+  assert_eq!(pack_rgb((0x12, 0x34, 0x56)), 0x12_3456);
+  assert_eq!(unpack_rgb(0x12_3456), (0x12, 0x34, 0x56));
+}
+
+#[test]
+fn test_hsv2rgb() {
+  assert_eq!(hsv2rgb(0, 255, 255), (255, 0, 0));
+  assert_eq!(hsv2rgb(85, 255, 255), (0, 255, 0));
+  assert_eq!(hsv2rgb(42, 0, 128), (128, 128, 128));
+  assert_eq!(hsv2rgb(100, 255, 0), (0, 0, 0));
+}
+
+#[test]
+fn test_hsv2rgb_ecall() {
+  let code = "
+    .equ HSV2RGB 0x1
+
+    li s0 0x1003
+    li s1 0xff
+    li s2 85
+    sb s2 (s0)
+    sb s1 1(s0)
+    sb s1 2(s0)
+    ecall zero HSV2RGB(s0)
+  ";
+  let env = spin_env(2, ColorOrder::RGB, code);
+  assert_eq!(env.led_ram(), &[0, 0, 0, 0, 255, 0]);
+  assert_eq!(env.pixel(1), (0, 255, 0));
+
+  let env = spin_env(2, ColorOrder::GRB, code);
+  assert_eq!(env.led_ram(), &[0, 0, 0, 255, 0, 0]);
+  assert_eq!(env.pixel(1), (0, 255, 0));
+}
+
+#[test]
+fn test_color_order() {
+  let orders = [
+    (ColorOrder::RGB, [1, 2, 3]),
+    (ColorOrder::RBG, [1, 3, 2]),
+    (ColorOrder::GRB, [2, 1, 3]),
+    (ColorOrder::GBR, [2, 3, 1]),
+    (ColorOrder::BRG, [3, 1, 2]),
+    (ColorOrder::BGR, [3, 2, 1]),
+  ];
+  for (order, bytes) in orders.iter() {
+    let mut buf = [0; 3];
+    order.encode((1, 2, 3), &mut buf);
+    assert_eq!(&buf, bytes);
+    assert_eq!(order.decode(&buf), (1, 2, 3));
+  }
+}
+
+#[test]
+fn test_rainbow() {
+  let code = std::fs::read_to_string("../docs/rainbow.s").unwrap();
+  let exprs = parse(&code).unwrap();
+  let bytecode = compile(&exprs).unwrap();
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; 900], ColorOrder::RGB));
+  vm.load(&bytecode).unwrap();
+  // The loop stops at STRIP_BASE, a fault every frame would make the supervisor fall back.
+  vm.respin().unwrap();
+  let env = vm.get_env();
+  assert_eq!(env.prescaler(), 24);
+  assert_eq!(env.leds(), 300);
+  assert_eq!(env.pixels().count(), 300);
+  assert_eq!(env.pixel(0), hsv2rgb(212, 0xff, 0x20));
+  assert_eq!(env.pixel(44), hsv2rgb(0, 0xff, 0x20));
+  assert_eq!(env.pixel(299), hsv2rgb(0xff, 0xff, 0x20));
+}
+
+fn load_vm(leds: usize, order: ColorOrder, code: &str) -> VM<'static, LedEnv<Vec<u8>>> {
+  let exprs = parse(code).unwrap();
+  let bytecode = compile(&exprs).unwrap();
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; leds * 3], order));
+  vm.load(Box::leak(bytecode.into_boxed_slice())).unwrap();
+  vm
+}
+
+fn spin_env(leds: usize, order: ColorOrder, code: &str) -> LedEnv<Vec<u8>> {
+  let mut vm = load_vm(leds, order, code);
+  vm.respin().unwrap();
+  std::mem::replace(vm.get_env(), LedEnv::new(vec![], vec![], ColorOrder::RGB))
+}
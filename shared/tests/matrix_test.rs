@@ -0,0 +1,68 @@
+use strip_shared::matrix::*;
+
+#[test]
+fn test_wiring() {
+  let wirings = [
+    (Wiring::Rows, [[0, 1, 2], [3, 4, 5]]),
+    (Wiring::Serpentine, [[0, 1, 2], [5, 4, 3]]),
+    (Wiring::Columns, [[0, 2, 4], [1, 3, 5]]),
+    (Wiring::ColumnSerpentine, [[0, 3, 4], [1, 2, 5]]),
+  ];
+  for (wiring, grid) in wirings.iter() {
+    let matrix = Matrix::new(3, 2, *wiring);
+    assert_eq!(matrix.size(), (3, 2));
+    for (y, row) in grid.iter().enumerate() {
+      for (x, idx) in row.iter().enumerate() {
+        assert_eq!(matrix.xy(x as i32, y as i32, &[]), Some(*idx));
+      }
+    }
+    assert_eq!(matrix.xy(-1, 0, &[]), None);
+    assert_eq!(matrix.xy(3, 0, &[]), None);
+    assert_eq!(matrix.xy(0, 2, &[]), None);
+  }
+}
+
+#[test]
+fn test_transforms() {
+  let transforms = [
+    ("3x2,rot90", vec![vec![3, 0], vec![4, 1], vec![5, 2]]),
+    ("3x2,rot180", vec![vec![5, 4, 3], vec![2, 1, 0]]),
+    ("3x2,rot270", vec![vec![2, 5], vec![1, 4], vec![0, 3]]),
+    ("3x2,flipx", vec![vec![2, 1, 0], vec![5, 4, 3]]),
+    ("3x2,flipy", vec![vec![3, 4, 5], vec![0, 1, 2]]),
+    ("3x2,serpentine,rot90", vec![vec![5, 0], vec![4, 1], vec![3, 2]]),
+  ];
+  for (spec, grid) in transforms.iter() {
+    let matrix = Matrix::parse(spec).unwrap();
+    assert_eq!(matrix.size(), (grid[0].len() as u16, grid.len() as u16));
+    for (y, row) in grid.iter().enumerate() {
+      for (x, idx) in row.iter().enumerate() {
+        assert_eq!(matrix.xy(x as i32, y as i32, &[]), Some(*idx), "{}", spec);
+      }
+    }
+  }
+}
+
+#[test]
+fn test_lut() {
+  let matrix = Matrix::new(2, 2, Wiring::Lut);
+  let lut = [0, 3, 0xff, 0xff, 0, 0, 0, 1];
+  assert_eq!(matrix.xy(0, 0, &lut), Some(3));
+  assert_eq!(matrix.xy(1, 0, &lut), None);
+  assert_eq!(matrix.xy(0, 1, &lut), Some(0));
+  assert_eq!(matrix.xy(1, 1, &lut), Some(1));
+  // Positions past the end of the table have no LED.
+  assert_eq!(matrix.xy(1, 1, &lut[..6]), None);
+}
+
+#[test]
+fn test_flags() {
+  let matrix = Matrix::parse("16x8,column-serpentine,rot270,flipy").unwrap();
+  assert_eq!(matrix.wiring, Wiring::ColumnSerpentine);
+  assert_eq!(matrix.flags(), 0x5b);
+  assert_eq!(Matrix::from_flags(16, 8, 0x5b), Some(matrix));
+  assert_eq!(Matrix::from_flags(16, 8, 0x5), None);
+  assert_eq!(Matrix::from_flags(16, 8, 0x80), None);
+  assert_eq!(Matrix::parse("16"), None);
+  assert_eq!(Matrix::parse("16x8,zigzag"), None);
+}
@@ -0,0 +1,130 @@
+use strip_shared::output::*;
+
+#[test]
+fn test_default_is_identity() {
+  let mut pipeline = Pipeline::new();
+  for val in 0..=255 {
+    assert_eq!(pipeline.apply((val, val, val)), (val, val, val));
+  }
+  pipeline.set_dither(true);
+  for _ in 0..8 {
+    pipeline.advance();
+    assert_eq!(pipeline.apply((0, 1, 255)), (0, 1, 255));
+  }
+}
+
+#[test]
+fn test_gamma() {
+  let mut pipeline = Pipeline::new();
+  pipeline.set_gamma([22, 10, 28]);
+  assert_eq!(pipeline.gamma(), [22, 10, 28]);
+  assert_eq!(pipeline.apply((0, 0, 0)), (0, 0, 0));
+  assert_eq!(pipeline.apply((255, 255, 255)), (255, 255, 255));
+  assert_eq!(pipeline.apply((128, 128, 128)), (56, 128, 37));
+
+  pipeline.set_gamma([0, 0, 0]);
+  assert_eq!(pipeline.gamma(), [1, 1, 1]);
+}
+
+#[test]
+fn test_brightness() {
+  let mut pipeline = Pipeline::new();
+  pipeline.set_brightness(127);
+  assert_eq!(pipeline.apply((255, 100, 0)), (127, 50, 0));
+  pipeline.set_brightness(0);
+  assert_eq!(pipeline.apply((255, 255, 255)), (0, 0, 0));
+}
+
+#[test]
+fn test_dither() {
+  let mut pipeline = Pipeline::new();
+  pipeline.set_brightness(127);
+  assert_eq!(pipeline.apply((1, 3, 0)), (0, 1, 0));
+
+  pipeline.set_dither(true);
+  let mut sums = (0, 0);
+  for _ in 0..8 {
+    let (r, g, _) = pipeline.apply((1, 3, 0));
+    sums = (sums.0 + r as u32, sums.1 + g as u32);
+    pipeline.advance();
+  }
+  // Half steps average out over a dither cycle.
+  assert_eq!(sums, (4, 12));
+}
+
+#[test]
+fn test_reset() {
+  let mut pipeline = Pipeline::new();
+  pipeline.set_gamma([25, 25, 25]);
+  pipeline.set_brightness(10);
+  pipeline.set_dither(true);
+  pipeline.reset();
+  assert_eq!(pipeline.gamma(), [LINEAR_GAMMA; 3]);
+  assert_eq!(pipeline.brightness(), 255);
+  assert!(!pipeline.dither());
+  assert_eq!(pipeline.apply((77, 78, 79)), (77, 78, 79));
+}
+
+#[test]
+fn test_power_estimate() {
+  let budget = PowerBudget::default();
+  // 300 LEDs at full white.
+  let power = budget.estimate(300, 300 * 3 * 255);
+  assert_eq!(power.ma, 18_300);
+  assert_eq!(power.limited_ma, 18_300);
+  assert_eq!(power.scale, 255);
+  assert_eq!(budget.estimate(300, 0).ma, 300);
+  assert_eq!(budget.estimate(0, 0).ma, 0);
+}
+
+#[test]
+fn test_power_limit() {
+  let budget = PowerBudget {
+    limit_ma: 5000,
+    ..PowerBudget::default()
+  };
+  let power = budget.estimate(300, 300 * 3 * 255);
+  assert_eq!(power.ma, 18_300);
+  assert_eq!(power.scale, 66);
+  assert!(power.limited_ma <= 5000);
+  assert_eq!(power.limited_ma, 4958);
+
+  // Within budget frames are left alone.
+  assert_eq!(budget.estimate(300, 10_000).scale, 255);
+
+  // Idle draw alone exceeds the supply.
+  let power = budget.estimate(6000, 6000 * 3);
+  assert_eq!((power.scale, power.limited_ma), (0, 6000));
+}
+
+#[test]
+fn test_power_limit_below_idle() {
+  let budget = PowerBudget {
+    limit_ma: 100,
+    ..PowerBudget::default()
+  };
+  let power = budget.estimate(300, 0);
+  assert_eq!((power.ma, power.limited_ma, power.scale), (300, 300, 0));
+  let power = budget.estimate(300, 300 * 3 * 255);
+  assert_eq!((power.limited_ma, power.scale), (300, 0));
+}
+
+#[test]
+fn test_power_pipeline() {
+  let mut pipeline = Pipeline::new();
+  pipeline.set_power_budget(PowerBudget {
+    channel_ma: 60,
+    idle_ma: 0,
+    limit_ma: 30,
+  });
+  let frame = [(255, 0, 0), (0, 0, 255)];
+  let power = pipeline.power(frame.iter().copied());
+  assert_eq!((power.ma, power.scale), (120, 63));
+  assert_eq!(pipeline.apply_scaled((255, 0, 0), power.scale), (63, 0, 0));
+
+  pipeline.set_brightness(127);
+  assert_eq!(pipeline.power(frame.iter().copied()).ma, 59);
+
+  pipeline.reset();
+  assert_eq!(pipeline.power_budget().limit_ma, 30);
+}
@@ -0,0 +1,63 @@
+use strip_shared::pixel::*;
+
+#[test]
+fn test_parse() {
+  assert_eq!(
+    PixelFormat::parse("grb"),
+    Some(PixelFormat::new(ColorOrder::GRB, Channels::Rgb))
+  );
+  assert_eq!(
+    PixelFormat::parse("GRBW"),
+    Some(PixelFormat::new(ColorOrder::GRB, Channels::Rgbw))
+  );
+  assert_eq!(
+    PixelFormat::parse("bgr16"),
+    Some(PixelFormat::new(ColorOrder::BGR, Channels::Rgb16))
+  );
+  assert_eq!(PixelFormat::parse("rgbx"), None);
+  assert_eq!(PixelFormat::parse("w"), None);
+}
+
+#[test]
+fn test_format_byte() {
+  for name in &["rgb", "bgr", "grbw", "gbrw", "rgb16", "brg16"] {
+    let format = PixelFormat::parse(name).unwrap();
+    assert_eq!(PixelFormat::from_byte(format.to_byte()), Some(format));
+  }
+  assert_eq!(PixelFormat::parse("rgb").unwrap().to_byte(), 0);
+  assert_eq!(PixelFormat::parse("grbw").unwrap().to_byte(), 0x12);
+  assert_eq!(PixelFormat::from_byte(0x06), None);
+  assert_eq!(PixelFormat::from_byte(0x30), None);
+}
+
+#[test]
+fn test_encode() {
+  let cases: &[(&str, &[u8])] = &[
+    ("rgb", &[0x10, 0x20, 0x30]),
+    ("grb", &[0x20, 0x10, 0x30]),
+    ("bgr", &[0x30, 0x20, 0x10]),
+    ("rgbw", &[0, 0x10, 0x20, 0x10]),
+    ("grbw", &[0x10, 0, 0x20, 0x10]),
+    ("rgb16", &[0x10, 0x10, 0x20, 0x20, 0x30, 0x30]),
+    ("gbr16", &[0x20, 0x20, 0x30, 0x30, 0x10, 0x10]),
+  ];
+  for (name, bytes) in cases {
+    let format = PixelFormat::parse(name).unwrap();
+    assert_eq!(format.size(), bytes.len());
+    let mut buf = [0; MAX_PIXEL_SIZE];
+    format.encode((0x10, 0x20, 0x30), &mut buf);
+    assert_eq!(&buf[..format.size()], *bytes, "{}", name);
+    assert_eq!(format.decode(bytes), (0x10, 0x20, 0x30), "{}", name);
+  }
+}
+
+#[test]
+fn test_decode_wide() {
+  let rgbw = PixelFormat::parse("rgbw").unwrap();
+  assert_eq!(rgbw.decode(&[200, 0, 0, 100]), (255, 100, 100));
+  let rgb16 = PixelFormat::parse("rgb16").unwrap();
+  assert_eq!(
+    rgb16.decode(&[0x12, 0xff, 0, 0x80, 0xff, 0]),
+    (0x12, 0, 0xff)
+  );
+}
@@ -0,0 +1,149 @@
+use strip_shared::compiler::compile;
+use strip_shared::driver::*;
+use strip_shared::input::NoInput;
+use strip_shared::led::*;
+use strip_shared::link::Packet;
+use strip_shared::parser::parse;
+use strip_shared::runtime::*;
+use strip_shared::telemetry::Program;
+use strip_shared::vm::Env;
+
+/// Records the bytes sent to each channel.
+#[derive(Default)]
+struct MockSpi {
+  channels: Vec<Vec<u8>>,
+}
+
+impl MockSpi {
+  fn take(&mut self, ch: usize) -> Vec<u8> {
+    self
+      .channels
+      .get_mut(ch)
+      .map(std::mem::take)
+      .unwrap_or_default()
+  }
+}
+
+impl Output for MockSpi {
+  type Error = ();
+
+  fn write(&mut self, ch: usize, byte: u8) -> Result<(), ()> {
+    if self.channels.len() <= ch {
+      self.channels.resize(ch + 1, vec![]);
+    }
+    self.channels[ch].push(byte);
+    Ok(())
+  }
+}
+
+type TestRuntime = Runtime<'static, Vec<u8>, MockSpi, NoInput>;
+
+const PIXEL: &str = "
+  li t0 0xff
+  sb t0 0x1000
+  li t0 0x81
+  sb t0 0x1002
+";
+
+#[test]
+fn test_ws2812_stream() {
+  let mut runtime = runtime(2, Driver::Ws2812(Ws2812::default()), PIXEL);
+  assert!(runtime.refresh());
+  let mut expected = vec![
+    0x88, 0x88, 0x88, 0x88, // G 0x00
+    0xee, 0xee, 0xee, 0xee, // R 0xff
+    0xe8, 0x88, 0x88, 0x8e, // B 0x81
+    0x88, 0x88, 0x88, 0x88, // LED 1 off
+    0x88, 0x88, 0x88, 0x88, //
+    0x88, 0x88, 0x88, 0x88, //
+  ];
+  expected.extend_from_slice(&[0; WS2812_RESET_LEN]);
+  assert_eq!(runtime.output_mut().take(0), expected);
+}
+
+#[test]
+fn test_apa102_stream() {
+  let mut runtime = runtime(2, Driver::Apa102(Apa102::new()), PIXEL);
+  assert!(runtime.refresh());
+  assert_eq!(
+    runtime.output_mut().take(0),
+    vec![
+      0x00, 0x00, 0x00, 0x00, // start frame
+      0xff, 0x81, 0x00, 0xff, // LED 0
+      0xff, 0x00, 0x00, 0x00, // LED 1
+      0xff, 0xff, 0xff, 0xff, // end frame
+    ]
+  );
+}
+
+#[test]
+fn test_prescaler() {
+  let code = "
+    li t0 2
+    ecall zero SET_PSC(t0)
+    lb t0 0
+    addi t0 t0 1
+    sb t0 0
+  ";
+  let mut runtime = runtime(1, Driver::Ws2812(Ws2812::default()), code);
+  let due: Vec<bool> = (0..7).map(|_| runtime.refresh()).collect();
+  assert_eq!(due, [true, false, false, true, false, false, true]);
+  let mut frames = [0];
+  runtime.env().mem_fetch(0, &mut frames).unwrap();
+  assert_eq!(frames, [3]);
+
+  // Every frame goes out whole, ticks in between send nothing.
+  let frame = 4 * 3 + WS2812_RESET_LEN;
+  assert_eq!(runtime.output_mut().take(0).len(), 3 * frame);
+}
+
+#[test]
+fn test_pause() {
+  let mut runtime = runtime(1, Driver::Ws2812(Ws2812::default()), PIXEL);
+  let program = || Program {
+    slot: None,
+    name: &[],
+  };
+  assert_eq!(runtime.handle(Packet::Pause, program()), Some(Packet::Ack));
+  assert!(!runtime.refresh());
+  assert!(runtime.output_mut().take(0).is_empty());
+
+  assert_eq!(runtime.handle(Packet::Step, program()), Some(Packet::Ack));
+  assert!(runtime.refresh());
+  assert!(!runtime.refresh());
+  assert_eq!(runtime.output_mut().take(0).len(), 4 * 3 + WS2812_RESET_LEN);
+
+  runtime.handle(Packet::Resume, program());
+  assert!(runtime.refresh());
+  // Packets of other protocols are left to the device.
+  assert_eq!(runtime.handle(Packet::Next, program()), None);
+}
+
+#[test]
+fn test_channels() {
+  let code = "
+    li t0 0xff
+    sb t0 0x1000
+  ";
+  let mut runtime = runtime(4, Driver::Ws2812(Ws2812::default()), code);
+  let format = PixelFormat::new(ColorOrder::RGB, Channels::Rgb);
+  let channels = [Channel { leds: 1, format }, Channel { leds: 3, format }];
+  runtime.env().set_channels(&channels).unwrap();
+  assert!(runtime.refresh());
+  let first = runtime.output_mut().take(0);
+  let second = runtime.output_mut().take(1);
+  assert_eq!(first.len(), 4 * 3 + WS2812_RESET_LEN);
+  assert_eq!(first[4..8], [0xee; 4]);
+  assert_eq!(second.len(), 4 * 9 + WS2812_RESET_LEN);
+  assert!(second[..36].iter().all(|&b| b == 0x88));
+}
+
+fn runtime(leds: usize, driver: Driver, code: &str) -> TestRuntime {
+  let bytecode = compile(&parse(code).unwrap()).unwrap();
+  let env = LedEnv::new(vec![0; 8], vec![0; leds * 3], ColorOrder::RGB);
+  let mut runtime = Runtime::new(env, driver, MockSpi::default(), NoInput);
+  runtime
+    .load(Box::leak(bytecode.into_boxed_slice()))
+    .unwrap();
+  runtime
+}
@@ -0,0 +1,279 @@
+use strip_shared::storage::*;
+
+const PAGE_SIZE: usize = 256;
+const WRITE_SIZE: usize = 8;
+
+/// RAM backed NOR flash, writes can only clear bits of erased memory.
+struct RamFlash {
+  mem: Vec<u8>,
+  erases: Vec<u32>,
+  worn: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum FlashError {
+  Alignment,
+  NotErased,
+  Worn,
+}
+
+impl RamFlash {
+  fn new(pages: usize) -> Self {
+    RamFlash {
+      mem: vec![0xff; pages * PAGE_SIZE],
+      erases: vec![0; pages],
+      worn: false,
+    }
+  }
+}
+
+impl FlashStorage for RamFlash {
+  type Error = FlashError;
+
+  fn page_size(&self) -> usize {
+    PAGE_SIZE
+  }
+
+  fn write_size(&self) -> usize {
+    WRITE_SIZE
+  }
+
+  fn capacity(&self) -> usize {
+    self.mem.len()
+  }
+
+  fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+    buf.copy_from_slice(&self.mem[offset..(offset + buf.len())]);
+    Ok(())
+  }
+
+  fn erase(&mut self, offset: usize) -> Result<(), Self::Error> {
+    if !offset.is_multiple_of(PAGE_SIZE) {
+      return Err(FlashError::Alignment);
+    }
+    self.erases[offset / PAGE_SIZE] += 1;
+    self.mem[offset..(offset + PAGE_SIZE)]
+      .iter_mut()
+      .for_each(|b| *b = 0xff);
+    Ok(())
+  }
+
+  fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+    if !offset.is_multiple_of(WRITE_SIZE) || !data.len().is_multiple_of(WRITE_SIZE) {
+      return Err(FlashError::Alignment);
+    }
+    let mem = &mut self.mem[offset..(offset + data.len())];
+    if mem.iter().any(|b| *b != 0xff) {
+      return Err(FlashError::NotErased);
+    }
+    if self.worn {
+      return Err(FlashError::Worn);
+    }
+    mem.copy_from_slice(data);
+    Ok(())
+  }
+}
+
+fn program(len: usize, seed: u8) -> Vec<u8> {
+  let mut prog = vec![0xaf, 0xaf, 0, 0];
+  prog.extend((4..len).map(|idx| (idx as u8).wrapping_mul(seed)));
+  prog
+}
+
+#[test]
+fn test_empty_storage() {
+  let storage = Storage::mount(RamFlash::new(10), 300).unwrap();
+  assert_eq!(storage.slot_size(), 512);
+  assert_eq!(storage.slots(), 4);
+  assert_eq!(storage.default_slot(), None);
+  assert_eq!(storage.next(None), None);
+  assert_eq!(storage.prev(None), None);
+  assert!(storage.info(0).is_none());
+  let mut buf = [0; 512];
+  assert_eq!(storage.load(0, &mut buf), Err(StorageError::Empty));
+  assert_eq!(storage.load(4, &mut buf), Err(StorageError::InvalidSlot));
+  assert_eq!(
+    Storage::mount(RamFlash::new(10), 0).err(),
+    Some(StorageError::InvalidSlot)
+  );
+}
+
+#[test]
+fn test_store_load() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  let fire = program(301, 3);
+  let rainbow = program(64, 7);
+  storage.store(0, b"fire", &fire).unwrap();
+  storage.store(2, b"rainbow", &rainbow).unwrap();
+
+  let storage = Storage::mount(storage.release(), 512).unwrap();
+  let mut buf = [0; 512];
+  assert_eq!(storage.load(0, &mut buf), Ok(fire.len()));
+  assert_eq!(&buf[..fire.len()], &fire[..]);
+  assert_eq!(storage.load(2, &mut buf), Ok(rainbow.len()));
+  assert_eq!(&buf[..rainbow.len()], &rainbow[..]);
+
+  let info = storage.info(2).unwrap();
+  assert_eq!(info.name(), b"rainbow");
+  assert_eq!(info.len(), 64);
+  assert!(storage.info(1).is_none());
+
+  let mut small = [0; 100];
+  assert_eq!(storage.load(0, &mut small), Err(StorageError::TooLarge));
+}
+
+#[test]
+fn test_locate() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  let prog = program(100, 3);
+  storage.store(1, b"fire", &prog).unwrap();
+  let range = storage.locate(1).unwrap();
+  assert_eq!(&storage.flash().mem[range], &prog[..]);
+  assert_eq!(storage.locate(0), Err(StorageError::Empty));
+  assert_eq!(storage.locate(4), Err(StorageError::InvalidSlot));
+
+  let mut flash = storage.release();
+  flash.mem[PAGE_SIZE * 2 + 512 + 10] ^= 0x01;
+  let storage = Storage::mount(flash, 512).unwrap();
+  assert_eq!(storage.locate(1), Err(StorageError::Corrupt));
+}
+
+#[test]
+fn test_store_errors() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  assert_eq!(
+    storage.store(4, b"", &program(8, 1)),
+    Err(StorageError::InvalidSlot)
+  );
+  assert_eq!(
+    storage.store(0, b"", &program(513, 1)),
+    Err(StorageError::TooLarge)
+  );
+  assert_eq!(
+    storage.store(0, b"name longer than sixteen", &program(8, 1)),
+    Err(StorageError::TooLarge)
+  );
+  assert_eq!(storage.store(0, b"", &[]), Err(StorageError::Empty));
+  assert!(storage.info(0).is_none());
+}
+
+#[test]
+fn test_default_slot() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  assert_eq!(storage.set_default(1), Err(StorageError::Empty));
+  storage.store(1, b"blinky", &program(64, 1)).unwrap();
+  storage.set_default(1).unwrap();
+
+  let mut storage = Storage::mount(storage.release(), 512).unwrap();
+  assert_eq!(storage.default_slot(), Some(1));
+  storage.remove(1).unwrap();
+  assert_eq!(storage.default_slot(), None);
+}
+
+#[test]
+fn test_next_prev() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  for slot in [0, 2, 3].iter() {
+    storage.store(*slot, b"", &program(16, *slot)).unwrap();
+  }
+  assert_eq!(storage.next(None), Some(0));
+  assert_eq!(storage.next(Some(0)), Some(2));
+  assert_eq!(storage.next(Some(2)), Some(3));
+  assert_eq!(storage.next(Some(3)), Some(0));
+  assert_eq!(storage.prev(None), Some(3));
+  assert_eq!(storage.prev(Some(0)), Some(3));
+  assert_eq!(storage.prev(Some(2)), Some(0));
+  assert_eq!(storage.next(Some(1)), Some(2));
+
+  storage.remove(2).unwrap();
+  assert_eq!(storage.next(Some(0)), Some(3));
+}
+
+#[test]
+fn test_wear() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  let prog = program(300, 5);
+  storage.store(0, b"fire", &prog).unwrap();
+  storage.store(0, b"fire", &prog).unwrap();
+  storage.set_default(0).unwrap();
+  storage.set_default(0).unwrap();
+  assert_eq!(storage.info(0).unwrap().erases(), 1);
+  // Slot pages erased once, table pages take turns.
+  assert_eq!(&storage.flash().erases[..4], &[1, 1, 1, 1]);
+
+  // Renaming touches the table only.
+  storage.store(0, b"embers", &prog).unwrap();
+  assert_eq!(&storage.flash().erases[..4], &[2, 1, 1, 1]);
+
+  storage.store(0, b"embers", &program(300, 6)).unwrap();
+  assert_eq!(&storage.flash().erases[..4], &[2, 2, 2, 2]);
+  assert_eq!(storage.info(0).unwrap().erases(), 2);
+
+  // Removed slot keeps its wear counter.
+  storage.remove(0).unwrap();
+  storage.store(0, b"", &prog).unwrap();
+  assert_eq!(storage.info(0).unwrap().erases(), 3);
+}
+
+#[test]
+fn test_integrity() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  let prog = program(100, 9);
+  storage.store(0, b"one", &prog).unwrap();
+  storage.store(1, b"two", &prog).unwrap();
+
+  let mut flash = storage.release();
+  flash.mem[PAGE_SIZE * 2 + 50] ^= 0x01;
+  let mut storage = Storage::mount(flash, 512).unwrap();
+  let mut buf = [0; 512];
+  assert_eq!(storage.load(0, &mut buf), Err(StorageError::Corrupt));
+
+  // Storing the same program repairs the damaged slot.
+  storage.store(0, b"one", &prog).unwrap();
+  assert_eq!(storage.load(0, &mut buf), Ok(prog.len()));
+
+  // Torn table write falls back to the previous table,
+  // fourth commit went into the second table page.
+  storage.store(2, b"three", &prog).unwrap();
+  let mut flash = storage.release();
+  flash.mem[PAGE_SIZE + 20] ^= 0xff;
+  let storage = Storage::mount(flash, 512).unwrap();
+  assert!(storage.info(2).is_none());
+  assert_eq!(storage.info(1).unwrap().name(), b"two");
+
+  // Garbage in both tables mounts as empty storage.
+  let mut flash = storage.release();
+  flash.mem[..(PAGE_SIZE * 2)].iter_mut().for_each(|b| *b = 0);
+  let storage = Storage::mount(flash, 512).unwrap();
+  assert_eq!(storage.next(None), None);
+}
+
+#[test]
+fn test_failed_commit() {
+  let mut storage = Storage::mount(RamFlash::new(10), 512).unwrap();
+  let prog = program(100, 3);
+  storage.store(0, b"one", &prog).unwrap();
+
+  // A table that did not make it to flash is not taken over.
+  let mut flash = storage.release();
+  flash.worn = true;
+  let mut storage = Storage::mount(flash, 512).unwrap();
+  let res = storage.store(0, b"renamed", &prog);
+  assert_eq!(res, Err(StorageError::Flash(FlashError::Worn)));
+  assert_eq!(storage.info(0).unwrap().name(), b"one");
+  assert_eq!(
+    storage.set_default(0),
+    Err(StorageError::Flash(FlashError::Worn))
+  );
+  assert_eq!(storage.default_slot(), None);
+
+  // Once flash takes writes again the changes go through.
+  let mut flash = storage.release();
+  flash.worn = false;
+  let mut storage = Storage::mount(flash, 512).unwrap();
+  storage.store(0, b"renamed", &prog).unwrap();
+  storage.set_default(0).unwrap();
+  let storage = Storage::mount(storage.release(), 512).unwrap();
+  assert_eq!(storage.info(0).unwrap().name(), b"renamed");
+  assert_eq!(storage.default_slot(), Some(0));
+}
@@ -0,0 +1,121 @@
+use strip_shared::compiler::compile;
+use strip_shared::led::*;
+use strip_shared::parser::parse;
+use strip_shared::supervisor::*;
+use strip_shared::vm::*;
+
+const RUNAWAY: &str = "
+  li t0 0x20
+  sb t0 0x1000
+loop:
+  j loop
+";
+
+// Faults every other frame, counting frames in RAM.
+const FLAKY: &str = "
+  lb t0 0
+  addi t0 t0 1
+  sb t0 0
+  andi t0 t0 1
+  beqz t0 done
+  sb zero 0x101e
+done:
+";
+
+#[test]
+fn test_budget() {
+  let mut vm = load_vm(6, "li t0 3\nli t1 4\nadd t0 t0 t1");
+  assert_eq!(vm.respin_budget(4), Ok(4));
+  assert_eq!(vm.respin_budget(3), Err(VMError::Timeout));
+  let mut vm = load_vm(6, RUNAWAY);
+  assert_eq!(vm.respin_budget(1000), Err(VMError::Timeout));
+}
+
+#[test]
+fn test_tick_budget() {
+  assert_eq!(tick_budget(48_000_000), 732);
+  let mut supervisor = Supervisor::new(1000, 3);
+  supervisor.tick_budget = Some(100);
+  // The frame period grows with the prescaler, so does the budget up to `budget`.
+  let mut vm = load_vm(6, &format!("{}li t0 1\n", "nop\n".repeat(150)));
+  assert_eq!(supervisor.spin(&mut vm), Err(VMError::Timeout));
+  vm.get_env().set_prescaler(2);
+  assert_eq!(supervisor.spin(&mut vm), Ok(152));
+  vm.get_env().set_prescaler(20);
+  supervisor.budget = 120;
+  assert_eq!(supervisor.spin(&mut vm), Err(VMError::Timeout));
+}
+
+#[test]
+fn test_fallback() {
+  let mut vm = load_vm(6, RUNAWAY);
+  let mut supervisor = Supervisor::new(1000, 3);
+  for faults in 1..=2 {
+    assert_eq!(supervisor.spin(&mut vm), Err(VMError::Timeout));
+    assert_eq!(supervisor.faults(), faults);
+    assert_eq!(supervisor.fallback(), None);
+    // Faulting frames keep what was drawn before the fault.
+    assert_eq!(vm.get_env().pixel(0), (0x20, 0, 0));
+  }
+  assert_eq!(supervisor.spin(&mut vm), Err(VMError::Timeout));
+  assert_eq!(supervisor.fallback(), Some(VMError::Timeout));
+  assert_eq!(supervisor.last_error(), Some(VMError::Timeout));
+
+  let pattern = fault_pattern(VMError::Timeout, 0);
+  assert_eq!(pattern[0], (64, 0, 0));
+  let shown: Vec<_> = vm.get_env().pixels().collect();
+  assert_eq!(shown[..FAULT_LEDS], pattern);
+  assert_eq!(shown[FAULT_LEDS..], [(0, 0, 0); 2]);
+
+  // The safe program halts in time and the pattern stays until another program loads.
+  assert_eq!(supervisor.spin(&mut vm), Ok(5));
+  assert_eq!(vm.get_env().prescaler(), 31);
+  assert_eq!(
+    vm.get_env().pixels().take(FAULT_LEDS).collect::<Vec<_>>(),
+    pattern
+  );
+  supervisor.reset();
+  assert_eq!(supervisor.fallback(), None);
+  assert_eq!(supervisor.faults(), 3);
+}
+
+#[test]
+fn test_fault_streak() {
+  let mut vm = load_vm(10, FLAKY);
+  let mut supervisor = Supervisor::new(1000, 2);
+  let res: Vec<bool> = (0..6).map(|_| supervisor.spin(&mut vm).is_ok()).collect();
+  assert_eq!(res, [false, true, false, true, false, true]);
+  assert_eq!(supervisor.faults(), 3);
+  assert_eq!(supervisor.last_error(), Some(VMError::EnvFault));
+  assert_eq!(supervisor.fallback(), None);
+}
+
+#[test]
+fn test_fault_pattern() {
+  let kinds = [
+    VMError::Timeout,
+    VMError::EnvFault,
+    VMError::InvalidProg,
+    VMError::EmptyProg,
+  ];
+  for (idx, err) in kinds.iter().enumerate() {
+    let pattern = fault_pattern(*err, 1200);
+    for other in kinds[(idx + 1)..].iter() {
+      assert_ne!(pattern, fault_pattern(*other, 1200));
+    }
+    assert_eq!(fault_pattern(*err, 1700), [(0, 0, 0); FAULT_LEDS]);
+  }
+}
+
+#[test]
+fn test_safe_program() {
+  let code = std::fs::read_to_string("../docs/safe.s").unwrap();
+  assert_eq!(SAFE_PROGRAM, &compile(&parse(&code).unwrap()).unwrap()[..]);
+}
+
+fn load_vm(leds: usize, code: &str) -> VM<'static, LedEnv<Vec<u8>>> {
+  let bytecode = compile(&parse(code).unwrap()).unwrap();
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; leds * 3], ColorOrder::RGB));
+  vm.load(Box::leak(bytecode.into_boxed_slice())).unwrap();
+  vm
+}
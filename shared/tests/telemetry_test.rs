@@ -0,0 +1,223 @@
+use strip_shared::compiler::compile;
+use strip_shared::led::*;
+use strip_shared::link::*;
+use strip_shared::parser::parse;
+use strip_shared::storage::NAME_LEN;
+use strip_shared::supervisor::Supervisor;
+use strip_shared::telemetry::*;
+use strip_shared::vm::*;
+
+const PROGRAM: Program = Program {
+  slot: Some(2),
+  name: b"rainbow",
+};
+
+fn status() -> Status {
+  let mut name = [0; NAME_LEN];
+  name[..4].copy_from_slice(b"fire");
+  Status {
+    slot: Some(5),
+    name,
+    paused: true,
+    fallback: false,
+    frames: 123_456,
+    avg_ops: 2100,
+    max_ops: 4096,
+    spin_us: 850,
+    faults: 3,
+    last_error: Some(VMError::Timeout),
+    brightness: 200,
+    power_ma: 1440,
+  }
+}
+
+#[test]
+fn test_packet_roundtrip() {
+  let packets = [
+    Packet::Status,
+    Packet::Pause,
+    Packet::Resume,
+    Packet::Step,
+    Packet::Read {
+      addr: 0x1000,
+      len: 64,
+    },
+    Packet::Write {
+      addr: 4,
+      data: &[1, 2, 3],
+    },
+    Packet::Memory {
+      addr: 0x1003,
+      data: &[0xff; 64],
+    },
+    Packet::Report(status()),
+    Packet::Report(Status {
+      slot: None,
+      last_error: None,
+      ..status()
+    }),
+    Packet::Nack(Nack::BadAddress),
+  ];
+  for packet in packets.iter() {
+    let mut buf = [0; MAX_FRAME];
+    let len = packet.encode(&mut buf).unwrap();
+    let mut decoder = Decoder::new();
+    for byte in buf[..(len - 1)].iter() {
+      assert!(decoder.feed(*byte).is_none());
+    }
+    assert_eq!(decoder.feed(buf[len - 1]).unwrap(), Ok(*packet));
+  }
+  assert_eq!(status().name(), b"fire");
+}
+
+#[test]
+fn test_status_errors() {
+  let mut payload = [0; STATUS_LEN];
+  status().encode(&mut payload);
+  assert_eq!(Status::parse(&payload[1..]), Err(LinkError::BadLength));
+  payload[NAME_LEN + 22] = 9;
+  assert_eq!(Status::parse(&payload), Err(LinkError::UnknownCommand));
+}
+
+#[test]
+fn test_pause_and_step() {
+  let mut vm = load_vm("li t0 1");
+  let supervisor = Supervisor::default();
+  let mut monitor = Monitor::new();
+  let mut handle = |monitor: &mut Monitor, packet| {
+    let reply = monitor.handle(packet, vm.get_env(), &supervisor, PROGRAM);
+    reply.map(|reply| format!("{:?}", reply))
+  };
+  assert!(monitor.is_running());
+  assert_eq!(handle(&mut monitor, Packet::Pause), Some("Ack".into()));
+  assert!(!monitor.is_running());
+
+  // Every step lets one more frame through.
+  handle(&mut monitor, Packet::Step);
+  handle(&mut monitor, Packet::Step);
+  monitor.record(Some(10));
+  assert!(monitor.is_running());
+  monitor.record(Some(10));
+  assert!(!monitor.is_running());
+
+  handle(&mut monitor, Packet::Resume);
+  assert!(monitor.is_running());
+  monitor.record(Some(10));
+  assert!(monitor.is_running());
+
+  // Stepping a running device pauses it after the next frame.
+  handle(&mut monitor, Packet::Step);
+  assert!(monitor.is_paused() && monitor.is_running());
+  monitor.record(Some(10));
+  assert!(!monitor.is_running());
+
+  // Packets of other protocols are left alone.
+  assert_eq!(handle(&mut monitor, Packet::Commit), None);
+}
+
+#[test]
+fn test_status() {
+  let mut vm = load_vm(
+    "
+    li t0 100
+    ecall zero BRIGHTNESS(t0)
+    li t0 0x40
+    sb t0 0x1000
+    lb t0 0x400
+  ",
+  );
+  let mut supervisor = Supervisor::new(1000, 3);
+  let mut monitor = Monitor::new();
+  for _ in 0..2 {
+    let ops = supervisor.spin(&mut vm).ok();
+    monitor.record(ops);
+    vm.get_env().finish_frame();
+  }
+  monitor.set_spin_us(420);
+  let status = monitor.status(vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!(status.slot, Some(2));
+  assert_eq!(status.name(), b"rainbow");
+  assert_eq!(
+    (
+      status.frames,
+      status.avg_ops,
+      status.max_ops,
+      status.spin_us
+    ),
+    (2, 0, 0, 420)
+  );
+  assert_eq!(
+    (status.faults, status.last_error),
+    (2, Some(VMError::EnvFault))
+  );
+  assert_eq!(status.brightness, 100);
+  assert!(status.power_ma > 0);
+  assert!(!status.paused && !status.fallback);
+
+  let mut vm = load_vm("li t0 1\nli t1 2");
+  let mut monitor = Monitor::new();
+  for _ in 0..3 {
+    let ops = supervisor.spin(&mut vm).ok();
+    monitor.record(ops);
+  }
+  monitor.record(None);
+  let status = monitor.status(vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!((status.frames, status.avg_ops, status.max_ops), (4, 3, 3));
+  monitor.reset();
+  let status = monitor.status(vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!((status.frames, status.avg_ops), (0, 0));
+}
+
+#[test]
+fn test_memory() {
+  let mut vm = load_vm("halt");
+  let supervisor = Supervisor::default();
+  let mut monitor = Monitor::new();
+  let write = Packet::Write {
+    addr: 0x1001,
+    data: &[7, 8],
+  };
+  let reply = monitor.handle(write, vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!(reply, Some(Packet::Ack));
+  assert_eq!(vm.get_env().pixel(0), (0, 7, 8));
+
+  let reply = monitor.handle(
+    Packet::Write {
+      addr: 6,
+      data: &[1, 2, 3],
+    },
+    vm.get_env(),
+    &supervisor,
+    PROGRAM,
+  );
+  assert_eq!(reply, Some(Packet::Nack(Nack::BadAddress)));
+
+  let read = Packet::Read {
+    addr: 0x1000,
+    len: 4,
+  };
+  let reply = monitor.handle(read, vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!(
+    reply,
+    Some(Packet::Memory {
+      addr: 0x1000,
+      data: &[0, 7, 8, 0]
+    })
+  );
+  let read = Packet::Read {
+    addr: 0x1000,
+    len: MAX_READ as u8 + 1,
+  };
+  let reply = monitor.handle(read, vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!(reply, Some(Packet::Nack(Nack::TooLarge)));
+  let read = Packet::Read { addr: 4, len: 8 };
+  let reply = monitor.handle(read, vm.get_env(), &supervisor, PROGRAM);
+  assert_eq!(reply, Some(Packet::Nack(Nack::BadAddress)));
+}
+
+fn load_vm(code: &str) -> VM<'static, LedEnv<Vec<u8>>> {
+  let bytecode = compile(&parse(code).unwrap()).unwrap();
+  let mut vm = VM::new(LedEnv::new(vec![0; 8], vec![0; 30], ColorOrder::RGB));
+  vm.load(Box::leak(bytecode.into_boxed_slice())).unwrap();
+  vm
+}
@@ -0,0 +1,47 @@
+use strip_shared::transition::*;
+
+#[test]
+fn test_parse() {
+  assert_eq!(
+    Transition::parse("wipe:30"),
+    Some(Transition {
+      effect: Effect::Wipe,
+      frames: 30,
+    })
+  );
+  assert_eq!(Transition::parse("cut"), Some(Transition::CUT));
+  assert_eq!(Transition::parse("dissolve"), None);
+  assert_eq!(Transition::parse("fade:10"), None);
+}
+
+#[test]
+fn test_amount() {
+  let amounts = |effect, step| {
+    let transition = Transition { effect, frames: 4 };
+    (0..8)
+      .map(|idx| transition.amount(step, idx, 8))
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(amounts(Effect::Crossfade, 0), [0; 8]);
+  assert_eq!(amounts(Effect::Crossfade, 1), [63; 8]);
+  assert_eq!(amounts(Effect::Crossfade, 4), [255; 8]);
+  assert_eq!(amounts(Effect::Wipe, 1), [255, 255, 0, 0, 0, 0, 0, 0]);
+  assert_eq!(
+    amounts(Effect::Wipe, 3),
+    [255, 255, 255, 255, 255, 255, 0, 0]
+  );
+
+  // Dissolve switches LEDs for good, in the same order every time.
+  let mut shown = 0;
+  for step in 0..=4 {
+    let step_amounts = amounts(Effect::Dissolve, step);
+    assert!(step_amounts
+      .iter()
+      .all(|&amount| amount == 0 || amount == 255));
+    let now = step_amounts.iter().filter(|&&amount| amount == 255).count();
+    assert!(now >= shown);
+    shown = now;
+  }
+  assert_eq!(shown, 8);
+  assert_eq!(amounts(Effect::Dissolve, 2), amounts(Effect::Dissolve, 2));
+}
@@ -0,0 +1,272 @@
+use strip_shared::compiler::compile;
+use strip_shared::led::*;
+use strip_shared::link::*;
+use strip_shared::parser::parse;
+use strip_shared::upload::*;
+use strip_shared::vm::*;
+
+#[test]
+fn test_crc() {
+  assert_eq!(crc16(b"123456789"), 0x29b1);
+  assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+}
+
+#[test]
+fn test_packet_roundtrip() {
+  let packets = [
+    Packet::Start {
+      size: 1234,
+      crc: 0xdead_beef,
+    },
+    Packet::Data {
+      offset: 64,
+      data: &[1, 2, 3],
+    },
+    Packet::Commit,
+    Packet::Select(3),
+    Packet::Next,
+    Packet::Prev,
+    Packet::SetDefault(1),
+    Packet::Ack,
+    Packet::Nack(Nack::OutOfOrder),
+    Packet::Nack(Nack::StorageFailed),
+    Packet::Nack(Nack::Busy),
+  ];
+  for packet in packets.iter() {
+    let mut buf = [0; MAX_FRAME];
+    let len = packet.encode(&mut buf).unwrap();
+    let mut decoder = Decoder::new();
+    // Line noise before the frame is skipped until sync byte.
+    assert!(decoder.feed(0x00).is_none());
+    assert!(decoder.feed(0x42).is_none());
+    let decoded: Vec<Packet> = buf[..len]
+      .iter()
+      .filter_map(|byte| decoder.feed(*byte).map(|res| res.map(to_static)))
+      .map(|res| res.unwrap())
+      .collect();
+    assert_eq!(decoded.len(), 1);
+    match (packet, decoded[0]) {
+      (Packet::Data { offset, data }, Packet::Data { offset: o, .. }) => {
+        assert_eq!(*offset, o);
+        assert_eq!(*data, &[1, 2, 3]);
+      }
+      (expected, actual) => assert_eq!(*expected, actual),
+    }
+  }
+}
+
+#[test]
+fn test_packet_errors() {
+  let mut buf = [0; MAX_FRAME];
+  let len = Packet::Commit.encode(&mut buf).unwrap();
+  buf[len - 1] ^= 0xff;
+  let mut decoder = Decoder::new();
+  let res: Vec<_> = buf[..len]
+    .iter()
+    .filter_map(|byte| decoder.feed(*byte).map(|res| res.map(to_static)))
+    .collect();
+  assert_eq!(res, vec![Err(LinkError::BadChecksum)]);
+
+  let mut decoder = Decoder::new();
+  let res: Vec<_> = [SYNC, 0x02, 0xff, 0xff]
+    .iter()
+    .filter_map(|byte| decoder.feed(*byte).map(|res| res.map(to_static)))
+    .collect();
+  assert_eq!(res, vec![Err(LinkError::BadLength)]);
+
+  let data = [0; CHUNK_SIZE + 1];
+  let packet = Packet::Data {
+    offset: 0,
+    data: &data,
+  };
+  assert_eq!(packet.encode(&mut buf), Err(LinkError::BadLength));
+
+  let mut decoder = Decoder::new();
+  let len = Packet::Save {
+    slot: 2,
+    name: b"rainbow",
+  }
+  .encode(&mut buf)
+  .unwrap();
+  let res: Vec<_> = buf[..len]
+    .iter()
+    .filter_map(|byte| match decoder.feed(*byte) {
+      Some(Ok(Packet::Save { slot, name })) => Some((slot, name.to_vec())),
+      _ => None,
+    })
+    .collect();
+  assert_eq!(res, vec![(2, b"rainbow".to_vec())]);
+}
+
+#[test]
+fn test_upload() {
+  // Initialised RAM stretches the program over several chunks.
+  let code = std::fs::read_to_string("../docs/rainbow.s").unwrap();
+  let prog = compile_src(&format!(".zero 200\n{}", code));
+  assert!(prog.len() > CHUNK_SIZE * 3);
+  let mut receiver = Receiver::new(vec![0; 4096]);
+  loopback(&prog, &mut receiver, |_| false, |_| false).unwrap();
+  assert_eq!(receiver.take(), Some(prog.len()));
+  assert_eq!(receiver.take(), None);
+  assert_eq!(&receiver.buffer()[..prog.len()], &prog[..]);
+
+  let mut vm = VM::new(LedEnv::new(vec![0; 256], vec![0; 900], ColorOrder::RGB));
+  vm.load(&receiver.buffer()[..prog.len()]).unwrap();
+  vm.respin().ok();
+  assert_eq!(vm.get_env().prescaler(), 24);
+}
+
+#[test]
+fn test_upload_lossy_link() {
+  let prog = compile_file("../docs/blinky.s");
+  let mut receiver = Receiver::new(vec![0; 4096]);
+  loopback(
+    &prog,
+    &mut receiver,
+    |frame| frame % 3 == 1,
+    |reply| reply % 4 == 2,
+  )
+  .unwrap();
+  assert_eq!(receiver.take(), Some(prog.len()));
+  assert_eq!(&receiver.buffer()[..prog.len()], &prog[..]);
+}
+
+#[test]
+fn test_upload_dead_link() {
+  let prog = compile_file("../docs/blinky.s");
+  let mut receiver = Receiver::new(vec![0; 4096]);
+  let res = loopback(&prog, &mut receiver, |_| false, |_| true);
+  assert_eq!(res, Err(UploadError::RetriesExceeded));
+  assert_eq!(receiver.take(), None);
+}
+
+#[test]
+fn test_upload_rejected() {
+  let prog = compile_file("../docs/rainbow.s");
+  let mut receiver = Receiver::new(vec![0; 16]);
+  let res = loopback(&prog, &mut receiver, |_| false, |_| false);
+  assert_eq!(res, Err(UploadError::Rejected(Nack::TooLarge)));
+
+  let mut receiver = Receiver::new(vec![0; 16]);
+  let res = loopback(&[1, 2, 3, 4], &mut receiver, |_| false, |_| false);
+  assert_eq!(res, Err(UploadError::Rejected(Nack::BadProgram)));
+  assert_eq!(receiver.take(), None);
+}
+
+#[test]
+fn test_receiver_state() {
+  let mut receiver = Receiver::new(vec![0; 16]);
+  assert_eq!(
+    receiver.handle(Ok(Packet::Commit)),
+    Packet::Nack(Nack::BadState)
+  );
+  let data = Packet::Data {
+    offset: 0,
+    data: &[0xaf, 0xaf],
+  };
+  assert_eq!(receiver.handle(Ok(data)), Packet::Nack(Nack::BadState));
+
+  let prog = [0xaf, 0xaf, 0, 0];
+  let start = Packet::Start {
+    size: 4,
+    crc: crc32(&prog),
+  };
+  assert_eq!(receiver.handle(Ok(start)), Packet::Ack);
+  let gap = Packet::Data {
+    offset: 2,
+    data: &prog[2..],
+  };
+  assert_eq!(receiver.handle(Ok(gap)), Packet::Nack(Nack::OutOfOrder));
+  assert_eq!(receiver.handle(Ok(data)), Packet::Ack);
+  assert_eq!(
+    receiver.handle(Ok(Packet::Commit)),
+    Packet::Nack(Nack::BadChecksum)
+  );
+  assert_eq!(receiver.handle(Ok(data)), Packet::Ack);
+  assert_eq!(receiver.handle(Ok(gap)), Packet::Ack);
+  assert_eq!(
+    receiver.handle(Err(LinkError::BadChecksum)),
+    Packet::Nack(Nack::BadFrame)
+  );
+  assert_eq!(receiver.handle(Ok(Packet::Commit)), Packet::Ack);
+  assert_eq!(receiver.handle(Ok(Packet::Commit)), Packet::Ack);
+  assert_eq!(receiver.handle(Ok(start)), Packet::Nack(Nack::Busy));
+  assert_eq!(receiver.program(), Some(&prog[..]));
+  assert_eq!(receiver.take(), Some(4));
+  assert_eq!(receiver.program(), Some(&prog[..]));
+  assert_eq!(receiver.handle(Ok(start)), Packet::Ack);
+  assert_eq!(receiver.program(), None);
+}
+
+fn to_static(packet: Packet) -> Packet<'static> {
+  match packet {
+    Packet::Data { offset, .. } => Packet::Data { offset, data: &[] },
+    Packet::Start { size, crc } => Packet::Start { size, crc },
+    Packet::Commit => Packet::Commit,
+    Packet::Save { slot, .. } => Packet::Save { slot, name: &[] },
+    Packet::Select(slot) => Packet::Select(slot),
+    Packet::Next => Packet::Next,
+    Packet::Prev => Packet::Prev,
+    Packet::SetDefault(slot) => Packet::SetDefault(slot),
+    Packet::Status => Packet::Status,
+    Packet::Pause => Packet::Pause,
+    Packet::Resume => Packet::Resume,
+    Packet::Step => Packet::Step,
+    Packet::Read { addr, len } => Packet::Read { addr, len },
+    Packet::Write { addr, .. } => Packet::Write { addr, data: &[] },
+    Packet::Ack => Packet::Ack,
+    Packet::Nack(reason) => Packet::Nack(reason),
+    Packet::Report(status) => Packet::Report(status),
+    Packet::Memory { addr, .. } => Packet::Memory { addr, data: &[] },
+  }
+}
+
+fn compile_file(path: &str) -> Vec<u8> {
+  compile_src(&std::fs::read_to_string(path).unwrap())
+}
+
+fn compile_src(code: &str) -> Vec<u8> {
+  compile(&parse(code).unwrap()).unwrap()
+}
+
+/// Wires host and device state machines through encoded frames,
+/// `corrupt` and `drop` select host frames to damage and device replies to lose.
+fn loopback(
+  prog: &[u8],
+  receiver: &mut Receiver<Vec<u8>>,
+  corrupt: impl Fn(usize) -> bool,
+  drop: impl Fn(usize) -> bool,
+) -> Result<(), UploadError> {
+  let mut sender = Sender::new(prog)?;
+  let mut device = Decoder::new();
+  let mut host = Decoder::new();
+  let mut buf = [0; MAX_FRAME];
+  let mut frames = 0;
+  while let Some(packet) = sender.packet() {
+    let len = packet.encode(&mut buf).unwrap();
+    if corrupt(frames) {
+      buf[len / 2] ^= 0x5a;
+    }
+    let mut replies = vec![];
+    for byte in buf[..len].iter() {
+      if let Some(packet) = device.feed(*byte) {
+        replies.push(receiver.handle(packet));
+      }
+    }
+
+    let mut reply = None;
+    if !drop(frames) {
+      for packet in replies {
+        let len = packet.encode(&mut buf).unwrap();
+        for byte in buf[..len].iter() {
+          if let Some(packet) = host.feed(*byte) {
+            reply = packet.ok().map(to_static);
+          }
+        }
+      }
+    }
+    sender.reply(reply)?;
+    frames += 1;
+  }
+  Ok(())
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrow)]
+
 use strip_shared::compiler::compile;
 use strip_shared::parser::parse;
 use strip_shared::vm::*;
@@ -601,3 +603,21 @@ impl Env for TestEnv {
     Ok(0)
   }
 }
+
+#[test]
+fn test_header() {
+  assert_eq!(
+    Header::parse(&[0xaf, 0xaf, 0, 2, 7, 7, 0]),
+    Some(Header {
+      format: None,
+      ram_len: 2,
+      len: 4
+    })
+  );
+  let header = Header::parse(&[0xaf, 0xb0, 0x12, 0, 1, 7]).unwrap();
+  assert_eq!(header.format, Some(0x12));
+  assert_eq!(header.code_offset(), 6);
+  assert_eq!(Header::parse(&[0xaf, 0xaf, 0, 2, 7]), None);
+  assert_eq!(Header::parse(&[0xaf, 0xb0, 0x12, 0]), None);
+  assert_eq!(Header::parse(&[0xaf, 0xb1, 0, 0, 0]), None);
+}